@@ -0,0 +1,104 @@
+//! Line based differences between two texts
+use std::ops::Range;
+
+use imara_diff::{intern::InternedInput, sources::lines_with_terminator, Algorithm};
+use ropey::Rope;
+
+use crate::transaction::Transaction;
+
+/// Block of lines of the old text replaced with lines of the new one, ranges are line indices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub before: Range<usize>,
+    pub after: Range<usize>,
+}
+
+/// Changed blocks of lines in order they appear in text, unchanged lines are left out
+pub fn hunks(before: &Rope, after: &Rope) -> Vec<Hunk> {
+    let before = before.to_string();
+    let after = after.to_string();
+    let input = InternedInput::new(
+        lines_with_terminator(&before),
+        lines_with_terminator(&after),
+    );
+
+    let mut hunks = vec![];
+    imara_diff::diff(
+        Algorithm::Histogram,
+        &input,
+        |before: Range<u32>, after: Range<u32>| {
+            hunks.push(Hunk {
+                before: before.start as usize..before.end as usize,
+                after: after.start as usize..after.end as usize,
+            });
+        },
+    );
+
+    hunks
+}
+
+/// Transaction turning `before` into `after` by replacing only the changed lines, positions
+/// in unchanged lines are kept in place when mapped through it
+pub fn transaction(before: &Rope, after: &Rope) -> Transaction {
+    let mut tx = Transaction::new(before, 0);
+    let mut pos = 0;
+
+    for Hunk {
+        before: old,
+        after: new,
+    } in hunks(before, after)
+    {
+        let start = before.line_to_char(old.start);
+        let end = before.line_to_char(old.end);
+        let inserted = after.slice(after.line_to_char(new.start)..after.line_to_char(new.end));
+
+        tx.move_forward_by(start - pos);
+        tx.delete(end - start);
+        tx.insert(String::from(inserted));
+        pos = end;
+    }
+
+    tx
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transaction::Assoc;
+
+    #[test]
+    fn changed_lines() {
+        let before = Rope::from("a\nb\nc\nd");
+        let after = Rope::from("a\nc\nx\nd");
+
+        assert_eq!(
+            hunks(&before, &after),
+            [
+                Hunk {
+                    before: 1..2,
+                    after: 1..1
+                },
+                Hunk {
+                    before: 3..3,
+                    after: 2..3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn transaction_keeps_unchanged_positions() {
+        let before = Rope::from("one\ntwo\nthree\nfour\n");
+        let after = Rope::from("zero\none\nthree\nfour!\n");
+
+        let tx = transaction(&before, &after);
+        let mut text = before.clone();
+        tx.apply(&mut text);
+        assert_eq!(text, after);
+
+        // `h` of "three" follows its line
+        assert_eq!(tx.map_pos(9, Assoc::Before), 10);
+        // position in replaced line moves to its start
+        assert_eq!(tx.map_pos(16, Assoc::Before), 15);
+    }
+}
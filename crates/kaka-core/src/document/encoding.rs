@@ -0,0 +1,287 @@
+use std::{
+    borrow::Cow,
+    io::{self, ErrorKind, Write},
+};
+
+use chardetng::EncodingDetector;
+use encoding_rs::{EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use ropey::Rope;
+use thiserror::Error;
+
+use super::LineEnding;
+
+/// Encoding of file with its byte order mark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{ch:?} can't be encoded as {encoding}")]
+struct Unencodable {
+    ch: char,
+    encoding: &'static str,
+}
+
+impl FileEncoding {
+    /// Detects encoding of `bytes` unless one is `forced`, returns it with decoded text and
+    /// whether the text is lossy
+    ///
+    /// Byte order mark wins over guessing, valid UTF-8 is taken as it is and anything else
+    /// is up to detector. Malformed sequences are decoded as replacement characters, so the
+    /// text can't be encoded back to the same bytes.
+    pub fn decode<'a>(
+        bytes: &'a [u8],
+        forced: Option<&'static Encoding>,
+    ) -> (Self, Cow<'a, str>, bool) {
+        let bom = Encoding::for_bom(bytes).filter(|(bom, _)| forced.is_none_or(|f| f == *bom));
+        let bom_len = bom.map_or(0, |(_, len)| len);
+
+        let encoding = match (forced, bom) {
+            (Some(encoding), _) | (None, Some((encoding, _))) => encoding,
+            (None, None) if std::str::from_utf8(bytes).is_ok() => UTF_8,
+            (None, None) => {
+                let mut detector = EncodingDetector::new();
+                detector.feed(bytes, true);
+                detector.guess(None, true)
+            }
+        };
+
+        let (text, malformed) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        if malformed {
+            log::warn!("Malformed {} sequences were replaced", encoding.name());
+        }
+
+        let file_encoding = Self {
+            encoding,
+            bom: bom.is_some(),
+        };
+
+        (file_encoding, text, malformed)
+    }
+
+    /// Writes `text` encoded, with `\n` replaced by `line_ending`
+    pub fn encode(
+        &self,
+        text: &Rope,
+        line_ending: LineEnding,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        if self.bom {
+            writer.write_all(self.bom_bytes())?;
+        }
+
+        let mut encoder = TextEncoder::new(self.encoding);
+
+        for chunk in text.chunks() {
+            for (idx, part) in chunk.split('\n').enumerate() {
+                if idx > 0 {
+                    encoder.write(line_ending.as_str(), writer)?;
+                }
+                encoder.write(part, writer)?;
+            }
+        }
+
+        encoder.finish(writer)
+    }
+
+    /// Same byte order mark setting with other encoding, mark is kept only for Unicode ones
+    #[must_use]
+    pub fn with_encoding(self, encoding: &'static Encoding) -> Self {
+        let unicode = [UTF_8, UTF_16LE, UTF_16BE].contains(&encoding);
+
+        Self {
+            encoding,
+            bom: self.bom && unicode,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    fn bom_bytes(&self) -> &'static [u8] {
+        if self.encoding == UTF_16LE {
+            b"\xFF\xFE"
+        } else if self.encoding == UTF_16BE {
+            b"\xFE\xFF"
+        } else {
+            b"\xEF\xBB\xBF"
+        }
+    }
+}
+
+/// `encoding_rs` encodes UTF-16 as UTF-8, so it's handled separately
+enum TextEncoder {
+    Utf8,
+    Utf16 { big_endian: bool },
+    Other(encoding_rs::Encoder),
+}
+
+impl TextEncoder {
+    fn new(encoding: &'static Encoding) -> Self {
+        if encoding == UTF_8 {
+            Self::Utf8
+        } else if encoding == UTF_16LE || encoding == UTF_16BE {
+            Self::Utf16 {
+                big_endian: encoding == UTF_16BE,
+            }
+        } else {
+            Self::Other(encoding.new_encoder())
+        }
+    }
+
+    fn write(&mut self, s: &str, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Self::Utf8 => writer.write_all(s.as_bytes()),
+            Self::Utf16 { big_endian } => {
+                for unit in s.encode_utf16() {
+                    let bytes = if *big_endian {
+                        unit.to_be_bytes()
+                    } else {
+                        unit.to_le_bytes()
+                    };
+                    writer.write_all(&bytes)?;
+                }
+                Ok(())
+            }
+            Self::Other(encoder) => encode_with(encoder, s, false, writer),
+        }
+    }
+
+    fn finish(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Self::Other(encoder) => encode_with(encoder, "", true, writer),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn encode_with(
+    encoder: &mut encoding_rs::Encoder,
+    mut s: &str,
+    last: bool,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let mut buf = [0u8; 1024];
+
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(s, &mut buf, last);
+        writer.write_all(&buf[..written])?;
+        s = &s[read..];
+
+        match result {
+            EncoderResult::InputEmpty => return Ok(()),
+            EncoderResult::OutputFull => (),
+            EncoderResult::Unmappable(ch) => {
+                let err = Unencodable {
+                    ch,
+                    encoding: encoder.encoding().name(),
+                };
+                return Err(io::Error::new(ErrorKind::InvalidData, err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use encoding_rs::{ISO_8859_2, WINDOWS_1252};
+
+    use super::*;
+
+    fn encode(encoding: FileEncoding, text: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        encoding.encode(&Rope::from(text), LineEnding::Lf, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    #[test]
+    fn detect() {
+        let (encoding, text, _) = FileEncoding::decode("zażółć".as_bytes(), None);
+        assert_eq!(
+            (encoding, text.as_ref()),
+            (FileEncoding::default(), "zażółć")
+        );
+
+        let (encoding, text, _) = FileEncoding::decode(b"\xEF\xBB\xBFa", None);
+        assert_eq!(encoding.encoding, UTF_8);
+        assert!(encoding.bom);
+        assert_eq!(text, "a");
+
+        let (encoding, text, _) = FileEncoding::decode(b"\xFF\xFEa\0", None);
+        assert_eq!(encoding.encoding, UTF_16LE);
+        assert_eq!(text, "a");
+
+        let (encoding, text, _) = FileEncoding::decode(b"caf\xE9 cr\xE8me br\xFBl\xE9e", None);
+        assert_eq!(encoding.encoding, WINDOWS_1252);
+        assert_eq!(text, "café crème brûlée");
+    }
+
+    #[test]
+    fn forced() {
+        let (encoding, text, _) = FileEncoding::decode(b"\xB1", Some(ISO_8859_2));
+        assert_eq!(encoding.encoding, ISO_8859_2);
+        assert_eq!(text, "ą");
+    }
+
+    #[test]
+    fn malformed() {
+        let (encoding, text, lossy) = FileEncoding::decode(b"a\xFF", Some(UTF_8));
+        assert_eq!(encoding.encoding, UTF_8);
+        assert_eq!(text, "a\u{FFFD}");
+        assert!(lossy);
+
+        let (_, _, lossy) = FileEncoding::decode(b"a\xFF", Some(WINDOWS_1252));
+        assert!(!lossy);
+    }
+
+    #[test]
+    fn round_trip() {
+        for bytes in [
+            &b"\xEF\xBB\xBFa\nb"[..],
+            b"\xFE\xFF\0a\0\n",
+            b"\xFF\xFEa\0\n\0",
+            b"caf\xE9\n",
+        ] {
+            let (encoding, text, _) = FileEncoding::decode(bytes, None);
+            assert_eq!(encode(encoding, &text).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn line_endings() {
+        let mut bytes = vec![];
+        let encoding = FileEncoding {
+            encoding: UTF_16LE,
+            bom: false,
+        };
+
+        encoding
+            .encode(&Rope::from("a\n"), LineEnding::Crlf, &mut bytes)
+            .unwrap();
+        assert_eq!(bytes, b"a\0\r\0\n\0");
+    }
+
+    #[test]
+    fn unencodable() {
+        let encoding = FileEncoding {
+            encoding: WINDOWS_1252,
+            bom: false,
+        };
+
+        let err = encode(encoding, "zażółć").unwrap_err();
+        assert_eq!(err.to_string(), "'ż' can't be encoded as windows-1252");
+    }
+}
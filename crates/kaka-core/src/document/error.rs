@@ -12,4 +12,31 @@ pub enum Error {
 
     #[error("{} is not a file", .0.display())]
     NotAFile(PathBuf),
+
+    #[error("{} is read only", .0.display())]
+    ReadOnly(PathBuf),
+
+    #[error("Malformed {0} sequences were replaced when reading, use :w! to write them anyway or :reopen with other encoding")]
+    LossyText(&'static str),
+
+    #[error("No file name")]
+    NoPath,
+
+    #[error("Undo file is malformed: {0}")]
+    UndoFile(#[from] serde_yaml::Error),
+
+    #[error("Undo file version {0} is not supported")]
+    UndoFileVersion(u32),
+
+    #[error("{} doesn't match file contents", .0.display())]
+    StaleUndoFile(PathBuf),
+
+    #[error("Journal is malformed: {0}")]
+    Journal(serde_yaml::Error),
+
+    #[error("Journal version {0} is not supported")]
+    JournalVersion(u32),
+
+    #[error("Journal doesn't match file contents")]
+    StaleJournal,
 }
@@ -0,0 +1,148 @@
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+/// Line break written to file, text of `Document` uses `\n` in its place. Other breaks found
+/// in file are kept as they are, so `\r` of `\r\n` in unix file stays in the text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Unknown file format {0:?}, expected unix, dos or mac")]
+pub struct UnknownLineEnding(String);
+
+impl LineEnding {
+    /// The most common line break in `text`, `Lf` when there are none
+    pub fn detect(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let (mut lf, mut crlf, mut cr) = (0usize, 0usize, 0usize);
+
+        for (idx, byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n' if idx > 0 && bytes[idx - 1] == b'\r' => crlf += 1,
+                b'\n' => lf += 1,
+                b'\r' if bytes.get(idx + 1) != Some(&b'\n') => cr += 1,
+                _ => (),
+            }
+        }
+
+        if crlf > lf && crlf >= cr {
+            Self::Crlf
+        } else if cr > lf && cr > crlf {
+            Self::Cr
+        } else {
+            Self::Lf
+        }
+    }
+
+    /// Replaces this line break with `\n`
+    pub fn normalize(self, text: String) -> String {
+        match self {
+            Self::Lf => text,
+            Self::Crlf => text.replace("\r\n", "\n"),
+            Self::Cr => {
+                let mut chars = text.chars().peekable();
+                let mut normalized = String::with_capacity(text.len());
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\r' if chars.peek() != Some(&'\n') => normalized.push('\n'),
+                        c => normalized.push(c),
+                    }
+                }
+
+                normalized
+            }
+        }
+    }
+
+    /// Whether `text` has `\n` which isn't part of this line break, it would be written
+    /// back as this one
+    pub fn loses_breaks(self, text: &str) -> bool {
+        match self {
+            Self::Lf => false,
+            Self::Crlf => text
+                .match_indices('\n')
+                .any(|(idx, _)| idx == 0 || text.as_bytes()[idx - 1] != b'\r'),
+            Self::Cr => text.contains('\n'),
+        }
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+
+    /// Name used by `:set fileformat`
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Lf => "unix",
+            Self::Crlf => "dos",
+            Self::Cr => "mac",
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = UnknownLineEnding;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unix" => Ok(Self::Lf),
+            "dos" => Ok(Self::Crlf),
+            "mac" => Ok(Self::Cr),
+            _ => Err(UnknownLineEnding(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_dominant() {
+        assert_eq!(LineEnding::detect("a"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\rb\r"), LineEnding::Cr);
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\r\nb\nc\n"), LineEnding::Lf);
+    }
+
+    #[test]
+    fn normalize_breaks() {
+        let text = "a\r\nb\rc\n";
+        assert_eq!(LineEnding::Lf.normalize(text.into()), text);
+        assert_eq!(LineEnding::Crlf.normalize(text.into()), "a\nb\rc\n");
+        assert_eq!(LineEnding::Cr.normalize("a\rb\r".into()), "a\nb\n");
+        assert_eq!(LineEnding::Cr.normalize(text.into()), "a\r\nb\nc\n");
+
+        assert!(!LineEnding::Lf.loses_breaks(text));
+        assert!(LineEnding::Crlf.loses_breaks(text));
+        assert!(!LineEnding::Crlf.loses_breaks("a\r\nb\rc\r\n"));
+        assert!(LineEnding::Cr.loses_breaks("a\rb\rc\n"));
+    }
+
+    #[test]
+    fn names() {
+        for ending in [LineEnding::Lf, LineEnding::Crlf, LineEnding::Cr] {
+            assert_eq!(ending.name().parse(), Ok(ending));
+        }
+        assert!("windows".parse::<LineEnding>().is_err());
+    }
+}
@@ -1,19 +1,39 @@
+mod encoding;
 mod error;
+mod line_ending;
+mod recovery;
+mod save;
+mod undo_file;
 
+pub use encoding::FileEncoding;
 pub use error::Error;
-use unicode_width::UnicodeWidthChar;
+pub use line_ending::{LineEnding, UnknownLineEnding};
+pub use recovery::{Journal, JournalState};
+pub use save::create_private_dir;
 
 use std::{
-    fs::File,
-    io::BufReader,
+    borrow::Cow,
+    fs,
+    io::{self, ErrorKind},
     num::NonZeroUsize,
     path::{Path, PathBuf},
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
 };
 
+use encoding_rs::Encoding;
 use ropey::Rope;
 
-use crate::{history::History, transaction::Transaction};
+use crate::{
+    diff, graphemes,
+    history::{History, TimeTravel},
+    indent::Indent,
+    selection::{Selection, SelectionSet},
+    transaction::{Assoc, Transaction},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DocumentId(NonZeroUsize);
@@ -36,6 +56,17 @@ pub struct Document {
     tx_context: Option<TransactionContext>,
     fs_metadata: Option<FilesystemMetadata>,
     history: History,
+    /// History revision of text written to file
+    saved_revision: usize,
+    line_ending: LineEnding,
+    /// `None` when file mixes line breaks which can't be written back as they were
+    saved_line_ending: Option<LineEnding>,
+    encoding: FileEncoding,
+    saved_encoding: FileEncoding,
+    /// Malformed bytes of file were replaced when decoding, saving would lose them
+    lossy: bool,
+    /// Indentation rules of the document's language, shared with commands using them
+    indent: Arc<Indent>,
 }
 
 impl Document {
@@ -47,6 +78,13 @@ impl Document {
             tx_context: None,
             fs_metadata: None,
             history: History::default(),
+            saved_revision: 0,
+            line_ending: LineEnding::default(),
+            saved_line_ending: Some(LineEnding::default()),
+            encoding: FileEncoding::default(),
+            saved_encoding: FileEncoding::default(),
+            lossy: false,
+            indent: Arc::default(),
         }
     }
 
@@ -60,34 +98,128 @@ impl Document {
     ///
     /// `io::Error` - file not found | lack of permissions
     pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_path_with_encoding(path, None)
+    }
+
+    /// Like [`Self::from_path`], `encoding` is used instead of detected one
+    pub fn from_path_with_encoding(
+        path: impl AsRef<Path>,
+        encoding: Option<&'static Encoding>,
+    ) -> Result<Self, Error> {
         let path = path.as_ref();
 
         let mut doc = Self::new_scratch();
 
-        let mut doc_metadata = FilesystemMetadata {
-            path: path.to_owned(),
-            writable: true, // TODO check parent metadata?
-        };
+        doc.fs_metadata = Some(FilesystemMetadata::new(path)?);
 
         if !path.exists() {
-            doc.fs_metadata = Some(doc_metadata);
+            if let Some(encoding) = encoding {
+                doc.encoding.encoding = encoding;
+                doc.saved_encoding = doc.encoding;
+            }
+
             return Ok(doc);
         }
 
-        let metadata = path.metadata()?;
-        if !metadata.is_file() {
-            return Err(Error::NotAFile(path.into()));
+        doc.load(path, encoding)?;
+
+        Ok(doc)
+    }
+
+    fn load(&mut self, path: &Path, encoding: Option<&'static Encoding>) -> Result<(), Error> {
+        let bytes = fs::read(path)?;
+        let disk = DiskState::new(&fs::metadata(path)?, &bytes);
+        let (encoding, text, lossy) = FileEncoding::decode(&bytes, encoding);
+
+        if let Some(metadata) = self.fs_metadata.as_mut() {
+            metadata.disk = Some(disk);
         }
 
-        doc_metadata.writable = !metadata.permissions().readonly();
+        self.encoding = encoding;
+        self.saved_encoding = encoding;
+        self.lossy = lossy;
+        self.line_ending = LineEnding::detect(&text);
+        self.saved_line_ending =
+            (!self.line_ending.loses_breaks(&text)).then_some(self.line_ending);
+        self.text = Rope::from(self.line_ending.normalize(text.into_owned()));
+
+        Ok(())
+    }
 
-        let file = File::open(path)?;
-        let text = Rope::from_reader(BufReader::new(file))?;
+    /// Reads file again decoding it as `encoding`, history is dropped since it doesn't apply
+    /// to the new text
+    pub fn reload_with_encoding(&mut self, encoding: &'static Encoding) -> Result<(), Error> {
+        let path = self.path().ok_or(Error::NoPath)?.to_owned();
 
-        doc.text = text;
-        doc.fs_metadata = Some(doc_metadata);
+        self.load(&path, Some(encoding))?;
+        self.tx_context = None;
+        self.history = History::default();
+        self.saved_revision = self.history.revision();
 
-        Ok(doc)
+        Ok(())
+    }
+
+    /// Reads file changed on disk again, the difference is applied as a single commit, so it
+    /// can be undone and selections can follow it through the returned transaction
+    pub fn reload(&mut self, selections: &SelectionSet) -> Result<Transaction, Error> {
+        assert!(self.tx_context.is_none());
+
+        let path = self.path().ok_or(Error::NoPath)?.to_owned();
+        let old_text = self.text.clone();
+
+        self.load(&path, Some(self.saved_encoding.encoding))?;
+
+        let tx = diff::transaction(&old_text, &self.text);
+        if tx.changes_text() {
+            let mut after = selections.clone();
+            after.map(&tx, Assoc::Before);
+
+            self.history
+                .create_commit(&old_text, tx.clone(), selections.clone(), after);
+        }
+
+        self.saved_revision = self.history.revision();
+
+        Ok(tx)
+    }
+
+    /// File was changed by someone else since it was loaded or saved, deleted file doesn't
+    /// count as the text can be saved again
+    pub fn changed_on_disk(&mut self) -> Result<bool, Error> {
+        let Some(metadata) = self.fs_metadata.as_mut() else {
+            return Ok(false);
+        };
+
+        let fs_metadata = match fs::metadata(&metadata.path) {
+            Ok(fs_metadata) => fs_metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+
+        if metadata
+            .disk
+            .is_some_and(|disk| disk.same_metadata(&fs_metadata))
+        {
+            return Ok(false);
+        }
+
+        let current = DiskState::new(&fs_metadata, &fs::read(&metadata.path)?);
+        if metadata.disk.is_some_and(|disk| disk.hash == current.hash) {
+            // touched or rewritten with the same content
+            metadata.disk = Some(current);
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Takes file as it is now for the loaded one, so its changes are not reported until it
+    /// changes again
+    pub fn ignore_disk_changes(&mut self) -> Result<(), Error> {
+        let metadata = self.fs_metadata.as_mut().ok_or(Error::NoPath)?;
+        metadata.disk = DiskState::read(&metadata.path)?;
+
+        Ok(())
     }
 
     pub const fn is_scratch(&self) -> bool {
@@ -98,7 +230,7 @@ impl Document {
         &self.text
     }
 
-    pub fn text_mut(&mut self) -> &mut Rope {
+    pub const fn text_mut(&mut self) -> &mut Rope {
         &mut self.text
     }
 
@@ -106,23 +238,218 @@ impl Document {
         self.fs_metadata.as_ref().map(|m| m.path.as_ref())
     }
 
+    /// Whether document is bound to the file at `path`, however the path is spelled
+    pub fn has_path(&self, path: &Path) -> bool {
+        let Some(own) = self.path() else {
+            return false;
+        };
+
+        match (canonical_path(own), canonical_path(path)) {
+            (Ok(own), Ok(path)) => own == path,
+            _ => own == path,
+        }
+    }
+
+    /// Path as typed when opening, `[scratch]` for documents without one
+    pub fn display_name(&self) -> Cow<'_, str> {
+        self.path()
+            .map_or(Cow::Borrowed("[scratch]"), Path::to_string_lossy)
+    }
+
     pub const fn id(&self) -> DocumentId {
         self.id
     }
 
+    /// Display column of `char_idx` char of the line, see [`graphemes::column`]
     pub fn column(&self, line_idx: usize, char_idx: usize) -> usize {
-        let line = self.text.line(line_idx);
+        graphemes::column(self.text.line(line_idx), char_idx)
+    }
 
-        (0..char_idx)
-            .map(|i| line.char(i).width().unwrap_or(1))
-            .sum()
+    /// Text differs from what was loaded or saved last time
+    pub fn is_modified(&self) -> bool {
+        let uncommitted = self
+            .tx_context
+            .as_ref()
+            .is_some_and(|ctx| ctx.transaction.changes_text());
+
+        uncommitted
+            || self.history.revision() != self.saved_revision
+            || Some(self.line_ending) != self.saved_line_ending
+            || self.encoding != self.saved_encoding
     }
 
-    pub fn save(&self) -> Result<(), std::io::Error> {
-        if let Some(metadata) = self.fs_metadata.as_ref() {
-            if metadata.writable {
-                self.text.write_to(File::create(&metadata.path)?)?;
-            }
+    pub const fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Changes line break written on save, text itself keeps using `\n`
+    pub const fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    pub const fn encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+
+    /// Changes encoding used on save
+    pub const fn set_encoding(&mut self, encoding: FileEncoding) {
+        self.encoding = encoding;
+    }
+
+    /// Indentation rules, cheap to hold while the document is edited
+    pub fn indent(&self) -> Arc<Indent> {
+        Arc::clone(&self.indent)
+    }
+
+    pub fn set_indent(&mut self, indent: Indent) {
+        self.indent = Arc::new(indent);
+    }
+
+    /// Writes text to the bound file, refused when text read from it is lossy
+    pub fn save(&mut self) -> Result<(), Error> {
+        if self.lossy {
+            return Err(Error::LossyText(self.saved_encoding.encoding.name()));
+        }
+
+        self.force_save()
+    }
+
+    /// Like [`Self::save`], lossy text replaces the original file
+    pub fn force_save(&mut self) -> Result<(), Error> {
+        let metadata = self.fs_metadata.as_ref().ok_or(Error::NoPath)?;
+
+        if !metadata.writable {
+            return Err(Error::ReadOnly(metadata.path.clone()));
+        }
+
+        self.write_to(&metadata.path)?;
+        self.saved_revision = self.history.revision();
+        self.ignore_disk_changes()?;
+        self.saved_line_ending = Some(self.line_ending);
+        self.saved_encoding = self.encoding;
+        self.lossy = false;
+
+        Ok(())
+    }
+
+    /// Writes text to `path` without changing the file document is bound to
+    ///
+    /// Text is encoded with document's encoding and line endings, existing file is replaced
+    /// atomically through temporary file renamed over it
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+
+        if path.is_dir() {
+            return Err(Error::NotAFile(path.into()));
+        }
+
+        save::write_atomic(path, |writer| {
+            self.encoding.encode(&self.text, self.line_ending, writer)
+        })?;
+
+        Ok(())
+    }
+
+    /// Binds document to `path` and saves it there, subsequent saves go to the new file
+    pub fn save_as(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let metadata = FilesystemMetadata::new(path.as_ref())?;
+
+        if !metadata.writable {
+            return Err(Error::ReadOnly(metadata.path));
+        }
+
+        self.write_to(&metadata.path)?;
+        self.fs_metadata = Some(metadata);
+        self.saved_revision = self.history.revision();
+        self.ignore_disk_changes()?;
+        self.saved_line_ending = Some(self.line_ending);
+        self.saved_encoding = self.encoding;
+        self.lossy = false;
+
+        Ok(())
+    }
+
+    /// Writes history to undo file inside `dir`, it is tied to the current text
+    pub fn write_undo_file(&self, dir: &Path) -> Result<(), Error> {
+        let path = self.path().ok_or(Error::NoPath)?;
+        let undo_path = undo_file::path_in(dir, path)?;
+
+        undo_file::write(&self.history, &self.text, &undo_path)
+    }
+
+    /// Restores history from undo file inside `dir`, as long as text is the same as when the
+    /// file was written
+    pub fn read_undo_file(&mut self, dir: &Path) -> Result<(), Error> {
+        let path = self.path().ok_or(Error::NoPath)?;
+        let undo_path = undo_file::path_in(dir, path)?;
+
+        self.history = undo_file::read(&self.text, &undo_path)?;
+        self.saved_revision = self.history.revision();
+
+        Ok(())
+    }
+
+    /// Changes not written to file, empty journal when there are none
+    pub fn journal(&self) -> Journal {
+        let head = self.history.revision();
+
+        // uncommitted changes are made on top of the head
+        let head_text = self
+            .tx_context
+            .as_ref()
+            .map_or(&self.text, |ctx| &ctx.saved_text);
+
+        let mut saved_text = head_text.clone();
+        for tx in self.history.transactions_between(head, self.saved_revision) {
+            tx.apply(&mut saved_text);
+        }
+
+        let transactions = self
+            .history
+            .transactions_between(self.saved_revision, head)
+            .into_iter()
+            .chain(self.uncommitted())
+            .cloned()
+            .collect();
+
+        Journal::new(&saved_text, transactions)
+    }
+
+    /// State of unsaved changes, cheaper to compare than the whole journal
+    pub fn journal_state(&self) -> JournalState {
+        JournalState {
+            revision: self.history.revision(),
+            saved_revision: self.saved_revision,
+            uncommitted: self.uncommitted().cloned(),
+        }
+    }
+
+    /// Whether journal written in `state` is still up to date
+    pub fn is_journaled(&self, state: &JournalState) -> bool {
+        state.revision == self.history.revision()
+            && state.saved_revision == self.saved_revision
+            && state.uncommitted.as_ref() == self.uncommitted()
+    }
+
+    fn uncommitted(&self) -> Option<&Transaction> {
+        self.tx_context
+            .as_ref()
+            .map(|ctx| &ctx.transaction)
+            .filter(|tx| tx.changes_text())
+    }
+
+    /// Replays `journal` on text loaded from file, each of its transactions is a separate
+    /// commit
+    pub fn recover(&mut self, journal: &Journal) -> Result<(), Error> {
+        assert!(self.tx_context.is_none());
+
+        for tx in journal.transactions(&self.text)? {
+            let saved_text = self.text.clone();
+            let pos = tx.apply(&mut self.text);
+
+            let cursor = SelectionSet::single(Selection::at_pos(pos));
+            self.history
+                .create_commit(&saved_text, tx.clone(), cursor.clone(), cursor);
         }
 
         Ok(())
@@ -132,7 +459,8 @@ impl Document {
         self.tx_context.is_some()
     }
 
-    pub fn open_transaction(&mut self, pos: usize) {
+    /// Opens transaction unless one is already open, `selections` are restored by undo
+    pub fn open_transaction(&mut self, pos: usize, selections: &SelectionSet) {
         if let Some(ctx) = self.tx_context.as_mut() {
             ctx.transaction.move_to(pos);
         } else {
@@ -140,11 +468,12 @@ impl Document {
             self.tx_context = Some(TransactionContext {
                 transaction: Transaction::new(&saved_text, pos),
                 saved_text,
+                selections: selections.clone(),
             });
         }
     }
 
-    pub fn with_new_transaction<F>(&mut self, pos: usize, callback: F)
+    pub fn with_new_transaction<F>(&mut self, pos: usize, selections: &SelectionSet, callback: F)
     where
         F: FnMut(&mut Self, &mut Transaction) -> TransactionLeave,
     {
@@ -154,6 +483,7 @@ impl Document {
         self.tx_context = Some(TransactionContext {
             transaction: Transaction::new(&saved_text, pos),
             saved_text,
+            selections: selections.clone(),
         });
 
         self.with_transaction(callback);
@@ -172,16 +502,19 @@ impl Document {
         let TransactionContext {
             mut transaction,
             saved_text,
+            selections,
         } = tx_context;
 
         match callback(self, &mut transaction) {
-            TransactionLeave::Commit => {
-                self.history.create_commit(&saved_text, transaction);
+            TransactionLeave::Commit(after) => {
+                self.history
+                    .create_commit(&saved_text, transaction, selections, after);
             }
             TransactionLeave::Keep => {
                 self.tx_context = Some(TransactionContext {
                     transaction,
                     saved_text,
+                    selections,
                 });
             }
             TransactionLeave::Rollback => {
@@ -190,12 +523,40 @@ impl Document {
         }
     }
 
-    pub fn undo(&mut self) -> Option<usize> {
-        self.history.undo().map(|tx| tx.apply(&mut self.text))
+    /// Reverts the last commit, returns selections from before it
+    pub fn undo(&mut self) -> Option<SelectionSet> {
+        let (tx, selections) = self.history.undo()?;
+        tx.apply(&mut self.text);
+
+        Some(selections.clone())
+    }
+
+    /// Reapplies undone commit, returns selections from after it
+    pub fn redo(&mut self) -> Option<SelectionSet> {
+        let (tx, selections) = self.history.redo()?;
+        tx.apply(&mut self.text);
+
+        Some(selections.clone())
+    }
+
+    /// Goes back in history across undo branches, returns selections of reached revision
+    pub fn earlier(&mut self, by: TimeTravel) -> Option<SelectionSet> {
+        let (txs, selections) = self.history.earlier(by);
+        for tx in txs {
+            tx.apply(&mut self.text);
+        }
+
+        selections.cloned()
     }
 
-    pub fn redo(&mut self) -> Option<usize> {
-        self.history.redo().map(|tx| tx.apply(&mut self.text))
+    /// Goes forward in history across undo branches, returns selections of reached revision
+    pub fn later(&mut self, by: TimeTravel) -> Option<SelectionSet> {
+        let (txs, selections) = self.history.later(by);
+        for tx in txs {
+            tx.apply(&mut self.text);
+        }
+
+        selections.cloned()
     }
 }
 
@@ -203,16 +564,103 @@ impl Document {
 pub struct FilesystemMetadata {
     path: PathBuf,
     writable: bool,
+    /// File as it was loaded or saved, `None` until it exists
+    disk: Option<DiskState>,
+}
+
+impl FilesystemMetadata {
+    fn new(path: &Path) -> Result<Self, Error> {
+        let mut metadata = Self {
+            path: path.to_owned(),
+            writable: true, // TODO check parent metadata?
+            disk: None,
+        };
+
+        if path.exists() {
+            let fs_metadata = path.metadata()?;
+            if !fs_metadata.is_file() {
+                return Err(Error::NotAFile(path.into()));
+            }
+
+            metadata.writable = !fs_metadata.permissions().readonly();
+        }
+
+        Ok(metadata)
+    }
+}
+
+/// Tells apart file changed by someone else from the one editor knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiskState {
+    modified: Option<SystemTime>,
+    /// Changes when file is replaced, as editors saving through rename do
+    inode: u64,
+    len: u64,
+    hash: u64,
+}
+
+impl DiskState {
+    fn new(metadata: &fs::Metadata, bytes: &[u8]) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            inode: inode(metadata),
+            len: metadata.len(),
+            hash: undo_file::hash(bytes.iter().copied()),
+        }
+    }
+
+    /// State of file at `path`, `None` when it doesn't exist
+    fn read(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(Self::new(&fs::metadata(path)?, &bytes))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// File looks untouched, content has to be compared only when this fails
+    fn same_metadata(&self, metadata: &fs::Metadata) -> bool {
+        self.modified == metadata.modified().ok()
+            && self.inode == inode(metadata)
+            && self.len == metadata.len()
+    }
+}
+
+/// Absolute path without symlinks, file which doesn't exist yet needs an existing directory
+fn canonical_path(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(absolute) => Ok(absolute),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let name = path.file_name().ok_or(err)?;
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+
+            Ok(fs::canonicalize(dir)?.join(name))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+const fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
 }
 
 /// Descibes what to do with transaction on scope exit
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TransactionLeave {
     /// Keep current transaction
     Keep,
 
-    /// Commit changes
-    Commit,
+    /// Commit changes, with selections left by them
+    Commit(SelectionSet),
 
     /// Rollback changes
     Rollback,
@@ -222,6 +670,8 @@ pub enum TransactionLeave {
 struct TransactionContext {
     transaction: Transaction,
     saved_text: Rope,
+    /// Selections from before the transaction
+    selections: SelectionSet,
 }
 
 pub trait AsRope {
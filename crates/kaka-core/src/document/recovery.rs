@@ -0,0 +1,214 @@
+//! Journal of changes not written to file yet, so they survive a crash of the editor
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufReader, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use super::{save, undo_file, Error};
+use crate::{
+    diff::{self, Hunk},
+    transaction::Transaction,
+};
+
+/// Bumped whenever serialized `Journal` changes in incompatible way
+const VERSION: u32 = 1;
+
+/// Unchanged lines between changed blocks which are still shown in one hunk
+const JOINED_GAP: usize = 3;
+
+/// Transactions replayed on text of the file to get the text of unsaved document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    version: u32,
+    /// Process writing the journal, a living one still has the file open
+    pid: u32,
+    text_hash: u64,
+    transactions: Vec<Transaction>,
+}
+
+/// Unsaved changes of document journal was made from, the journal stays the same as long as
+/// they do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalState {
+    pub(super) revision: usize,
+    pub(super) saved_revision: usize,
+    pub(super) uncommitted: Option<Transaction>,
+}
+
+impl Journal {
+    pub(super) fn new(text: &Rope, transactions: Vec<Transaction>) -> Self {
+        Self {
+            version: VERSION,
+            pid: std::process::id(),
+            text_hash: undo_file::text_hash(text),
+            transactions,
+        }
+    }
+
+    /// Journal of document saved at `path` inside `dir`, named like its undo file
+    pub fn path_in(dir: &Path, path: &Path) -> io::Result<PathBuf> {
+        undo_file::path_in(dir, path)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let journal: Self =
+            serde_yaml::from_reader(BufReader::new(file)).map_err(Error::Journal)?;
+
+        if journal.version != VERSION {
+            return Err(Error::JournalVersion(journal.version));
+        }
+
+        Ok(journal)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let serialized = serde_yaml::to_string(self).map_err(Error::Journal)?;
+
+        save::write_private(path, |writer| writer.write_all(serialized.as_bytes()))?;
+
+        Ok(())
+    }
+
+    pub const fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// There is nothing to recover
+    pub const fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub(super) fn transactions(&self, text: &Rope) -> Result<&[Transaction], Error> {
+        if self.text_hash != undo_file::text_hash(text) {
+            return Err(Error::StaleJournal);
+        }
+
+        Ok(&self.transactions)
+    }
+
+    /// Text of unsaved document, `text` has to be the one journal was written for
+    pub fn replay(&self, text: &Rope) -> Result<Rope, Error> {
+        let mut replayed = text.clone();
+
+        for tx in self.transactions(text)? {
+            tx.apply(&mut replayed);
+        }
+
+        Ok(replayed)
+    }
+
+    /// Lines changed by replaying journal on `text`, in unified diff format
+    pub fn diff(&self, text: &Rope) -> Result<String, Error> {
+        let replayed = self.replay(text)?;
+
+        Ok(line_diff(text, &replayed))
+    }
+}
+
+/// Changed lines in unified diff format, blocks separated by a few unchanged lines are
+/// joined into one hunk, other context lines are left out. Empty range of hunk header
+/// starts at the line preceding it.
+fn line_diff(old: &Rope, new: &Rope) -> String {
+    let mut diff = String::new();
+    let mut blocks = diff::hunks(old, new).into_iter().peekable();
+
+    while let Some(first) = blocks.next() {
+        let mut hunk = vec![first];
+        while let Some(next) = blocks.next_if(|next| {
+            let last = hunk.last().expect("Hunk has a block");
+            next.before.start - last.before.end <= JOINED_GAP
+        }) {
+            hunk.push(next);
+        }
+
+        let first = &hunk[0];
+        let last = hunk.last().expect("Hunk has a block");
+        let before = first.before.start..last.before.end;
+        let after = first.after.start..last.after.end;
+
+        let header_start = |range: &Range<usize>| range.start + usize::from(!range.is_empty());
+        let _ = writeln!(
+            diff,
+            "@@ -{},{} +{},{} @@",
+            header_start(&before),
+            before.len(),
+            header_start(&after),
+            after.len()
+        );
+
+        let mut unchanged = before.start;
+        for Hunk { before, after } in hunk {
+            let context = (unchanged..before.start).map(|idx| (' ', old.line(idx)));
+            let removed = before.clone().map(|idx| ('-', old.line(idx)));
+            let added = after.map(|idx| ('+', new.line(idx)));
+
+            for (mark, line) in context.chain(removed).chain(added) {
+                let _ = write!(diff, "{mark}{line}");
+                if line.chars().last() != Some('\n') {
+                    diff.push('\n');
+                }
+            }
+
+            unchanged = before.end;
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn journal(text: &Rope, edits: &[(usize, &str)]) -> Journal {
+        let transactions = edits
+            .iter()
+            .map(|&(pos, s)| {
+                let mut tx = Transaction::new(text, pos);
+                tx.insert(s);
+                tx
+            })
+            .collect();
+
+        Journal::new(text, transactions)
+    }
+
+    #[test]
+    fn replay() {
+        let text = Rope::from("a\nb\n");
+        let journal = journal(&text, &[(2, "x\n")]);
+
+        assert_eq!(journal.replay(&text).unwrap(), "a\nx\nb\n");
+        assert!(matches!(
+            journal.replay(&Rope::from("changed\n")),
+            Err(Error::StaleJournal)
+        ));
+    }
+
+    #[test]
+    fn diff() {
+        let text = Rope::from("a\nb\nc\nd\n");
+        let journal = journal(&text, &[(4, "x\n")]);
+
+        assert_eq!(journal.diff(&text).unwrap(), "@@ -2,0 +3,1 @@\n+x\n");
+
+        let old = Rope::from("a\nb\nc\nd");
+        let new = Rope::from("a\nc\nx\nd");
+        assert_eq!(line_diff(&old, &new), "@@ -2,2 +2,2 @@\n-b\n c\n+x\n");
+
+        let old = Rope::from("a\nb\nc\nd\ne\nf\n");
+        let new = Rope::from("b\nc\nd\ne\nf\nx\n");
+        assert_eq!(
+            line_diff(&old, &new),
+            "@@ -1,1 +0,0 @@\n-a\n@@ -6,0 +6,1 @@\n+x\n",
+            "distant changes are separate hunks"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Writing text to file so that a failure in the middle never leaves it truncated
+use std::{
+    fs::{self, File, Metadata, OpenOptions},
+    io::{self, BufWriter, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// Writes `content` to temporary file next to `path`, syncs it and renames it over `path`
+///
+/// Symlinks are followed, so the link itself stays in place. Files with other hard links,
+/// or which ownership can't be kept, are overwritten in place instead, renaming would detach
+/// them from the new content.
+pub fn write_atomic<F>(path: &Path, content: F) -> io::Result<()>
+where
+    F: Fn(&mut BufWriter<File>) -> io::Result<()>,
+{
+    replace(path, content, false)
+}
+
+/// Like [`write_atomic`], but file is readable only by its owner, it holds history of edited
+/// files and should not be more open than they are
+pub fn write_private<F>(path: &Path, content: F) -> io::Result<()>
+where
+    F: Fn(&mut BufWriter<File>) -> io::Result<()>,
+{
+    replace(path, content, true)
+}
+
+/// Creates `dir` with missing parents, accessible only by its owner
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    builder.create(dir)
+}
+
+fn replace<F>(path: &Path, content: F, private: bool) -> io::Result<()>
+where
+    F: Fn(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let path = resolve_symlinks(path)?;
+
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => Some(metadata),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+
+    if metadata.as_ref().is_some_and(has_hard_links) {
+        return write_in_place(&path, content, private);
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let (tmp_path, tmp) = create_temp(dir, &path, private)?;
+
+    // private file gets its own permissions, even when the old one had different ones
+    let replaced = metadata.as_ref().filter(|_| !private);
+
+    let prepared = write_and_sync(tmp, &content).and_then(|()| match replaced {
+        Some(metadata) => {
+            fs::set_permissions(&tmp_path, metadata.permissions())?;
+            Ok(keep_owner(&tmp_path, metadata))
+        }
+        None => Ok(true),
+    });
+
+    let renamed = prepared.and_then(|owned| {
+        if owned {
+            fs::rename(&tmp_path, &path)?;
+        }
+        Ok(owned)
+    });
+
+    match renamed {
+        Ok(true) => sync_dir(dir),
+        Ok(false) => {
+            let _ = fs::remove_file(&tmp_path);
+            write_in_place(&path, content, private)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(err)
+        }
+    }
+}
+
+/// Truncates and writes file, used when it can't be replaced
+fn write_in_place<F>(path: &Path, content: F, private: bool) -> io::Result<()>
+where
+    F: Fn(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    if private {
+        owner_only(&mut options);
+    }
+
+    let file = options.open(path)?;
+
+    write_and_sync(file, &content)
+}
+
+fn write_and_sync<F>(file: File, content: F) -> io::Result<()>
+where
+    F: Fn(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let mut writer = BufWriter::new(file);
+    content(&mut writer)?;
+
+    let file = writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()
+}
+
+fn create_temp(dir: &Path, path: &Path, private: bool) -> io::Result<(PathBuf, File)> {
+    let name = path
+        .file_name()
+        .map_or_else(Default::default, |name| name.to_string_lossy());
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    if private {
+        owner_only(&mut options);
+    }
+
+    for attempt in 0.. {
+        let tmp_path = dir.join(format!(".{name}.{}.{attempt}.kaka", std::process::id()));
+
+        match options.open(&tmp_path) {
+            Ok(file) => return Ok((tmp_path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && attempt < 16 => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    unreachable!()
+}
+
+/// Follows symlinks of existing files, dangling link is replaced like a regular file
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(path),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(path.to_owned()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(unix)]
+fn has_hard_links(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn has_hard_links(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// File is created readable and writable only by its owner
+#[cfg(unix)]
+fn owner_only(options: &mut OpenOptions) {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    options.mode(0o600);
+}
+
+#[cfg(not(unix))]
+fn owner_only(_options: &mut OpenOptions) {}
+
+/// Gives file the owner of `metadata`, returns `false` when that is not possible
+#[cfg(unix)]
+fn keep_owner(path: &Path, metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid())).is_ok()
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_path: &Path, _metadata: &Metadata) -> bool {
+    true
+}
+
+/// Makes rename durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    fn write(path: &Path, text: &str) -> io::Result<()> {
+        write_atomic(path, |writer| writer.write_all(text.as_bytes()))
+    }
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("kaka-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir(&path).unwrap();
+            Self(path)
+        }
+
+        fn entries(&self) -> usize {
+            fs::read_dir(&self.0).unwrap().count()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn replaces_file_without_leftovers() {
+        let dir = TempDir::new("replace");
+        let path = dir.0.join("file");
+
+        write(&path, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+
+        fs::write(&path, "old old old\n").unwrap();
+        write(&path, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+
+        assert_eq!(dir.entries(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("mode");
+        let path = dir.0.join("script");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        write(&path, "exit\n").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let dir = TempDir::new("private");
+        let private_dir = dir.0.join("undo").join("dir");
+        create_private_dir(&private_dir).unwrap();
+        assert_eq!(mode(&private_dir), 0o700);
+
+        let path = private_dir.join("history");
+        write_private(&path, |writer| writer.write_all(b"new\n")).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, |writer| writer.write_all(b"new\n")).unwrap();
+        assert_eq!(mode(&path), 0o600, "old permissions are not kept");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_through_links() {
+        let dir = TempDir::new("links");
+        let target = dir.0.join("target");
+        let symlink = dir.0.join("symlink");
+        let hardlink = dir.0.join("hardlink");
+
+        fs::write(&target, "old\n").unwrap();
+        std::os::unix::fs::symlink(&target, &symlink).unwrap();
+        fs::hard_link(&target, &hardlink).unwrap();
+
+        write(&symlink, "symlink\n").unwrap();
+        assert!(fs::symlink_metadata(&symlink).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "symlink\n");
+
+        write(&hardlink, "hardlink\n").unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "hardlink\n");
+
+        assert_eq!(dir.entries(), 3);
+    }
+}
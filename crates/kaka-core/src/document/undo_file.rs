@@ -0,0 +1,107 @@
+//! Undo history kept on disk, so it survives restarting the editor
+use std::{
+    fs::File,
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use super::{save, Error};
+use crate::history::History;
+
+/// Bumped whenever serialized `History` changes in incompatible way
+const VERSION: u32 = 3;
+
+#[derive(Serialize)]
+struct UndoFileRef<'a> {
+    version: u32,
+    text_hash: u64,
+    history: &'a History,
+}
+
+#[derive(Deserialize)]
+struct UndoFile {
+    version: u32,
+    text_hash: u64,
+    history: History,
+}
+
+/// Undo file of document saved at `path` inside `dir`, absolute path is flattened into
+/// file name with `%` in place of separators
+pub fn path_in(dir: &Path, path: &Path) -> io::Result<PathBuf> {
+    let absolute = super::canonical_path(path)?;
+
+    let name = absolute
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "%");
+
+    Ok(dir.join(name))
+}
+
+pub fn write(history: &History, text: &Rope, path: &Path) -> Result<(), Error> {
+    let undo_file = UndoFileRef {
+        version: VERSION,
+        text_hash: text_hash(text),
+        history,
+    };
+
+    let serialized = serde_yaml::to_string(&undo_file)?;
+
+    save::write_private(path, |writer| writer.write_all(serialized.as_bytes()))?;
+
+    Ok(())
+}
+
+/// Reads history saved with `text`, fails when file was changed outside of the editor
+pub fn read(text: &Rope, path: &Path) -> Result<History, Error> {
+    let file = File::open(path)?;
+    let undo_file: UndoFile = serde_yaml::from_reader(BufReader::new(file))?;
+
+    if undo_file.version != VERSION {
+        return Err(Error::UndoFileVersion(undo_file.version));
+    }
+
+    if undo_file.text_hash != text_hash(text) {
+        return Err(Error::StaleUndoFile(path.into()));
+    }
+
+    Ok(undo_file.history)
+}
+
+pub(super) fn text_hash(text: &Rope) -> u64 {
+    hash(text.bytes())
+}
+
+/// FNV-1a, unlike `DefaultHasher` it is guaranteed to stay the same between releases
+pub(super) fn hash(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    bytes.into_iter().fold(OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stable_hash() {
+        assert_eq!(text_hash(&Rope::new()), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(text_hash(&Rope::from("a")), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flattened_path() {
+        let dir = Path::new("undo");
+        let path = path_in(dir, Path::new("Cargo.toml")).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy();
+
+        assert!(name.starts_with('%'));
+        assert!(name.ends_with("%Cargo.toml"));
+    }
+}
@@ -3,7 +3,7 @@
 //! Based on <https://github.com/cessen/led/blob/c4fa72405f510b7fd16052f90a598c429b3104a6/src/graphemes.rs>
 use ropey::{iter::Chunks, str_utils::byte_to_char_idx, RopeSlice};
 use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use std::fmt;
 
@@ -31,6 +31,42 @@ pub fn grapheme_width(g: &str) -> usize {
     }
 }
 
+/// Columns between tab stops
+pub const TAB_WIDTH: usize = 8;
+
+/// Width of `c` drawn at display `column`, tab reaches the next tab stop
+#[must_use]
+pub fn char_width(c: char, column: usize) -> usize {
+    match c {
+        '\t' => TAB_WIDTH - column % TAB_WIDTH,
+        c => c.width().unwrap_or(1),
+    }
+}
+
+/// Display column of char at `char_idx` in `line`
+#[must_use]
+pub fn column(line: RopeSlice, char_idx: usize) -> usize {
+    line.chars()
+        .take(char_idx)
+        .fold(0, |column, c| column + char_width(c, column))
+}
+
+/// Index of char drawn over display `column` of `line`, length of the line when it's shorter
+#[must_use]
+pub fn char_at_column(line: RopeSlice, column: usize) -> usize {
+    let mut end = 0;
+
+    for (idx, c) in line.chars().enumerate() {
+        end += char_width(c, end);
+
+        if end > column {
+            return idx;
+        }
+    }
+
+    line.len_chars()
+}
+
 #[must_use]
 pub fn nth_prev_grapheme_boundary(slice: RopeSlice, char_idx: usize, n: usize) -> usize {
     if n == 0 {
@@ -348,3 +384,25 @@ impl<'a> Iterator for RopeGraphemes<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let text = Rope::from("a\tb\u{4e16}c\n");
+        let line = text.slice(..);
+
+        let columns = (0..=5).map(|idx| column(line, idx)).collect::<Vec<_>>();
+        assert_eq!(columns, [0, 1, 8, 9, 11, 12]);
+
+        let chars = (0..=12)
+            .map(|column| char_at_column(line, column))
+            .collect::<Vec<_>>();
+        assert_eq!(chars, [0, 1, 1, 1, 1, 1, 1, 1, 2, 3, 3, 4, 5]);
+        assert_eq!(char_at_column(line, 13), 6, "past the line");
+    }
+}
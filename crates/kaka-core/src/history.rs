@@ -1,75 +1,285 @@
-use std::time::{Duration, SystemTime};
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
 
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::transaction::Transaction;
+use crate::{selection::SelectionSet, transaction::Transaction};
 
-#[derive(Debug, Default)]
+/// Undo tree, change made after undo starts a new branch and keeps the undone one
+///
+/// Nodes are numbered in order of creation, `0` is the text before any commit and commit `n`
+/// is kept at `commits[n - 1]`. Commits are never removed, so numbers double as revisions.
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct History {
     commits: Vec<Commit>,
     head: usize,
+    /// Child of the initial text redo goes to
+    root_redo: Option<usize>,
+}
+
+/// How far to move in history with `:earlier` and `:later`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeTravel {
+    /// Number of commits in order of creation
+    Steps(usize),
+    Duration(Duration),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Invalid time {0:?}, expected count optionally followed by s, m, h or d")]
+pub struct InvalidTimeTravel(String);
+
+impl FromStr for TimeTravel {
+    type Err = InvalidTimeTravel;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidTimeTravel(s.to_string());
+
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let count = s[..digits].parse::<u64>().map_err(|_| invalid())?;
+
+        let unit = match &s[digits..] {
+            "" => {
+                return usize::try_from(count)
+                    .map(Self::Steps)
+                    .map_err(|_| invalid())
+            }
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+
+        count
+            .checked_mul(unit)
+            .map(|secs| Self::Duration(Duration::from_secs(secs)))
+            .ok_or_else(invalid)
+    }
 }
 
 impl History {
-    pub fn create_commit(&mut self, text: &Rope, tx: Transaction) {
+    /// Commits `tx` made on `text`, `before` and `after` are selections restored by undo
+    /// and redo
+    pub fn create_commit(
+        &mut self,
+        text: &Rope,
+        tx: Transaction,
+        before: SelectionSet,
+        after: SelectionSet,
+    ) {
         if !tx.changes_text() {
             return;
         }
 
-        let commit = Commit::new(text, tx);
+        self.push(Commit::new(text, tx, before, after));
+    }
+
+    fn push(&mut self, mut commit: Commit) {
+        commit.parent = self.head;
+        self.commits.push(commit);
+
+        let node = self.commits.len();
+        *self.redo_mut(self.head) = Some(node);
+        self.head = node;
+    }
+
+    /// Identifies text at the head, the same revision always means the same text
+    pub const fn revision(&self) -> usize {
+        self.head
+    }
+
+    /// Moves head to the parent, returns inversion with selections from before the commit
+    pub fn undo(&mut self) -> Option<(&Transaction, &SelectionSet)> {
+        let node = self.head;
+        let parent = self.commit(node)?.parent;
+
+        *self.redo_mut(parent) = Some(node);
+        self.head = parent;
+
+        let commit = &self.commits[node - 1];
+        Some((&commit.inversion, &commit.selections_before))
+    }
+
+    /// Moves head to the child it was undone from, or the newest one when there are many,
+    /// returns transaction with selections from after the commit
+    pub fn redo(&mut self) -> Option<(&Transaction, &SelectionSet)> {
+        let node = match self.head {
+            0 => self.root_redo,
+            head => self.commits[head - 1].redo,
+        }?;
+
+        self.head = node;
+
+        let commit = &self.commits[node - 1];
+        Some((&commit.transaction, &commit.selections_after))
+    }
+
+    /// Moves head back in time, across branches
+    pub fn earlier(&mut self, by: TimeTravel) -> (Vec<&Transaction>, Option<&SelectionSet>) {
+        let target = match by {
+            TimeTravel::Steps(steps) => self.head.saturating_sub(steps),
+            TimeTravel::Duration(duration) => {
+                let time = self.head_time().saturating_sub(duration);
+                self.created_until(time).min(self.head)
+            }
+        };
+
+        self.jump_to(target)
+    }
+
+    /// Moves head forward in time, across branches
+    pub fn later(&mut self, by: TimeTravel) -> (Vec<&Transaction>, Option<&SelectionSet>) {
+        let target = match by {
+            TimeTravel::Steps(steps) => self.head.saturating_add(steps),
+            TimeTravel::Duration(duration) => {
+                let time = self.head_time().saturating_add(duration);
+                self.created_until(time).max(self.head)
+            }
+        };
+
+        self.jump_to(target)
+    }
+
+    /// Moves head to `target` through their common ancestor, returns transactions to apply
+    /// in order and selections of `target`, `None` when head doesn't move
+    pub fn jump_to(&mut self, target: usize) -> (Vec<&Transaction>, Option<&SelectionSet>) {
+        let target = target.min(self.commits.len());
+        let (undo, redo) = self.route(self.head, target);
 
-        while self.head < self.commits.len() {
-            self.commits.pop();
+        // redo follows the branch jumped into
+        for &node in &redo {
+            let parent = self.commits[node - 1].parent;
+            *self.redo_mut(parent) = Some(node);
         }
 
-        self.commits.push(commit);
-        self.head += 1;
+        self.head = target;
+
+        let selections = match (redo.first(), undo.last()) {
+            (Some(&node), _) => Some(&self.commits[node - 1].selections_after),
+            (None, Some(&node)) => Some(&self.commits[node - 1].selections_before),
+            (None, None) => None,
+        };
+
+        (self.transactions(&undo, &redo), selections)
     }
 
-    /// move history by one
-    pub fn undo(&mut self) -> Option<&Transaction> {
-        let index = self.head.checked_sub(1)?;
+    /// Transactions turning text of `from` revision into text of `to`, head stays in place
+    pub fn transactions_between(&self, from: usize, to: usize) -> Vec<&Transaction> {
+        let (undo, redo) = self.route(from, to);
+
+        self.transactions(&undo, &redo)
+    }
 
-        self.head -= 1;
+    /// Nodes undone going from `from` up to common ancestor and nodes redone going down from
+    /// there to `to`, both ordered from the bottom
+    fn route(&self, from: usize, to: usize) -> (Vec<usize>, Vec<usize>) {
+        let mut undo = self.ancestors(from);
+        let mut redo = self.ancestors(to);
 
-        Some(&self.commits[index].inversion)
+        let common = undo
+            .iter()
+            .rev()
+            .zip(redo.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        undo.truncate(undo.len() - common);
+        redo.truncate(redo.len() - common);
+
+        (undo, redo)
     }
 
-    pub fn redo(&mut self) -> Option<&Transaction> {
-        let head = self.head;
+    fn transactions(&self, undo: &[usize], redo: &[usize]) -> Vec<&Transaction> {
+        undo.iter()
+            .map(|node| &self.commits[node - 1].inversion)
+            .chain(
+                redo.iter()
+                    .rev()
+                    .map(|node| &self.commits[node - 1].transaction),
+            )
+            .collect()
+    }
 
-        if head < self.commits.len() {
-            self.head += 1;
-            Some(&self.commits[head].transaction)
-        } else {
-            None
+    fn commit(&self, node: usize) -> Option<&Commit> {
+        node.checked_sub(1).and_then(|idx| self.commits.get(idx))
+    }
+
+    fn redo_mut(&mut self, node: usize) -> &mut Option<usize> {
+        match node {
+            0 => &mut self.root_redo,
+            node => &mut self.commits[node - 1].redo,
         }
     }
+
+    /// `node` followed by its ancestors, up to the root
+    fn ancestors(&self, mut node: usize) -> Vec<usize> {
+        let mut ancestors = vec![node];
+
+        while let Some(commit) = self.commit(node) {
+            node = commit.parent;
+            ancestors.push(node);
+        }
+
+        ancestors
+    }
+
+    /// Time of head commit, initial text takes time of the first commit
+    fn head_time(&self) -> Duration {
+        self.commit(self.head)
+            .or_else(|| self.commits.first())
+            .map_or(Duration::ZERO, Commit::timestamp)
+    }
+
+    /// The newest node created not later than `time`
+    fn created_until(&self, time: Duration) -> usize {
+        self.commits
+            .partition_point(|commit| commit.timestamp <= time)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Commit {
     transaction: Transaction,
     inversion: Transaction,
     timestamp: Duration,
+    selections_before: SelectionSet,
+    selections_after: SelectionSet,
+    parent: usize,
+    /// Child redo goes to
+    redo: Option<usize>,
 }
 
 impl Commit {
-    pub fn new(text: &Rope, tx: Transaction) -> Self {
+    pub fn new(text: &Rope, tx: Transaction, before: SelectionSet, after: SelectionSet) -> Self {
         let timestamp = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .expect("Time wents backward");
 
-        Self::with_timestamp(text, tx, timestamp)
+        Self::with_timestamp(text, tx, before, after, timestamp)
     }
 
-    pub fn with_timestamp(text: &Rope, tx: Transaction, timestamp: Duration) -> Self {
+    pub fn with_timestamp(
+        text: &Rope,
+        tx: Transaction,
+        before: SelectionSet,
+        after: SelectionSet,
+        timestamp: Duration,
+    ) -> Self {
         let inversion = tx.undo(text);
 
         let commit = Self {
             transaction: tx,
             inversion,
             timestamp,
+            selections_before: before,
+            selections_after: after,
+            parent: 0,
+            redo: None,
         };
 
         log::debug!("Creating commit {commit:#?}");
@@ -85,23 +295,68 @@ impl Commit {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::selection::Selection;
+
+    fn cursor(pos: usize) -> SelectionSet {
+        SelectionSet::single(Selection::at_pos(pos))
+    }
 
     fn history() -> History {
         let mut history = History::default();
 
         for _ in 0..10 {
-            history
-                .commits
-                .push(Commit::new(&Rope::new(), Transaction::new(&Rope::new(), 0)));
+            let tx = Transaction::new(&Rope::new(), 0);
+            history.push(Commit::new(&Rope::new(), tx, cursor(0), cursor(0)));
         }
 
-        history.head = 10;
-
+        assert_eq!(history.head, 10);
         assert_eq!(history.commits.len(), 10);
 
         history
     }
 
+    /// Appends `s` to `text` as new commit created at `secs`, cursor moves to the end
+    fn edit(history: &mut History, text: &mut Rope, s: &str, secs: u64) {
+        let mut tx = Transaction::new(text, text.len_chars());
+        tx.insert(s);
+
+        let before = text.clone();
+        tx.apply(text);
+        history.push(Commit::with_timestamp(
+            &before,
+            tx,
+            cursor(before.len_chars()),
+            cursor(text.len_chars()),
+            Duration::from_secs(secs),
+        ));
+    }
+
+    /// Applies transactions, returns primary cursor
+    fn apply(text: &mut Rope, travel: (Vec<&Transaction>, Option<&SelectionSet>)) -> usize {
+        let (txs, selections) = travel;
+
+        for tx in txs {
+            tx.apply(text);
+        }
+
+        selections.map_or(usize::MAX, |s| s.primary().head())
+    }
+
+    /// "a" -> "ab", undo, "a" -> "ac"
+    fn branched() -> (History, Rope) {
+        let mut history = History::default();
+        let mut text = Rope::new();
+
+        edit(&mut history, &mut text, "a", 600);
+        edit(&mut history, &mut text, "b", 660);
+        history.undo().unwrap().0.apply(&mut text);
+        edit(&mut history, &mut text, "c", 720);
+
+        assert_eq!(text, "ac");
+
+        (history, text)
+    }
+
     #[test]
     fn undo() {
         let mut history = history();
@@ -117,4 +372,133 @@ mod test {
 
         assert_eq!(history.head, 10);
     }
+
+    #[test]
+    fn revision_after_branching() {
+        let text = Rope::from("a");
+        let commit = |history: &mut History| {
+            let mut tx = Transaction::new(&text, 0);
+            tx.insert_char('b');
+            history.create_commit(&text, tx, cursor(0), cursor(1));
+        };
+
+        let mut history = History::default();
+        assert_eq!(history.revision(), 0);
+
+        commit(&mut history);
+        let first = history.revision();
+        history.undo();
+        assert_eq!(history.revision(), 0);
+
+        commit(&mut history);
+        assert_ne!(
+            history.revision(),
+            first,
+            "new branch gets its own revision"
+        );
+    }
+
+    #[test]
+    fn branches_are_kept() {
+        let (mut history, mut text) = branched();
+
+        apply(&mut text, history.earlier(TimeTravel::Steps(1)));
+        assert_eq!(text, "ab", "undone branch");
+
+        apply(&mut text, history.earlier(TimeTravel::Steps(1)));
+        assert_eq!(text, "a");
+
+        apply(&mut text, history.later(TimeTravel::Steps(2)));
+        assert_eq!(text, "ac");
+
+        assert!(history.later(TimeTravel::Steps(1)).0.is_empty());
+    }
+
+    #[test]
+    fn redo_follows_last_branch() {
+        let (mut history, mut text) = branched();
+
+        history.undo().unwrap().0.apply(&mut text);
+        history.redo().unwrap().0.apply(&mut text);
+        assert_eq!(text, "ac");
+
+        apply(&mut text, history.jump_to(2));
+        assert_eq!(text, "ab");
+
+        history.undo().unwrap().0.apply(&mut text);
+        history.redo().unwrap().0.apply(&mut text);
+        assert_eq!(text, "ab");
+    }
+
+    #[test]
+    fn selections_of_target() {
+        let (mut history, mut text) = branched();
+
+        let (inversion, selections) = history.undo().unwrap();
+        inversion.apply(&mut text);
+        assert_eq!(*selections, cursor(1), "before the last edit");
+
+        let (tx, selections) = history.redo().unwrap();
+        tx.apply(&mut text);
+        assert_eq!(*selections, cursor(2), "after the last edit");
+
+        assert_eq!(apply(&mut text, history.jump_to(0)), 0);
+        assert_eq!(apply(&mut text, history.jump_to(2)), 2);
+        assert_eq!(apply(&mut text, history.jump_to(2)), usize::MAX);
+    }
+
+    #[test]
+    fn transactions_between_branches() {
+        let (history, _) = branched();
+
+        let mut text = Rope::from("ab");
+        for tx in history.transactions_between(2, 3) {
+            tx.apply(&mut text);
+        }
+
+        assert_eq!(text, "ac");
+        assert_eq!(history.revision(), 3);
+    }
+
+    #[test]
+    fn travel_by_time() {
+        let (mut history, mut text) = branched();
+
+        apply(
+            &mut text,
+            history.earlier(TimeTravel::Duration(Duration::from_secs(30))),
+        );
+        assert_eq!(text, "ab", "created a minute before");
+
+        apply(
+            &mut text,
+            history.earlier(TimeTravel::Duration(Duration::from_secs(300))),
+        );
+        assert_eq!(text, "");
+
+        apply(
+            &mut text,
+            history.later(TimeTravel::Duration(Duration::from_secs(90))),
+        );
+        assert_eq!(text, "ab");
+    }
+
+    #[test]
+    fn parse_time_travel() {
+        assert_eq!("3".parse(), Ok(TimeTravel::Steps(3)));
+        assert_eq!(
+            "30s".parse(),
+            Ok(TimeTravel::Duration(Duration::from_secs(30)))
+        );
+        assert_eq!(
+            "5m".parse(),
+            Ok(TimeTravel::Duration(Duration::from_secs(300)))
+        );
+        assert_eq!(
+            "2h".parse(),
+            Ok(TimeTravel::Duration(Duration::from_secs(7200)))
+        );
+        assert!("m".parse::<TimeTravel>().is_err());
+        assert!("5y".parse::<TimeTravel>().is_err());
+    }
 }
@@ -0,0 +1,134 @@
+//! Indentation of new lines, copied from the line above and adjusted by tokens of the
+//! language which open and close blocks
+use ropey::RopeSlice;
+use serde::Deserialize;
+
+/// Indentation rules of a language, every field can be left out in `languages.yaml`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Indent {
+    /// One level of indentation, a tab or spaces
+    pub unit: String,
+    /// Line ending with one of these is followed by line indented one level deeper, like `{`
+    pub increase_after: Vec<String>,
+    /// Line starting with one of these is indented one level less, like `}`
+    pub decrease_before: Vec<String>,
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Self {
+            unit: "    ".to_string(),
+            increase_after: Vec::new(),
+            decrease_before: Vec::new(),
+        }
+    }
+}
+
+impl Indent {
+    /// Indentation of line started by line break inserted at `pos`
+    pub fn new_line(&self, text: RopeSlice, pos: usize) -> String {
+        let line_idx = text.char_to_line(pos);
+        let line_start = text.line_to_char(line_idx);
+
+        let before = text.slice(line_start..pos).to_string();
+        let after = text.line(line_idx).slice(pos - line_start..).to_string();
+
+        let mut indentation = leading_whitespace(&before).to_string();
+
+        if self.opens_block(before.trim_end()) {
+            indentation.push_str(&self.unit);
+        }
+
+        // text moved to the new line may close the block
+        if self.closes_block(after.trim_start()) {
+            indentation.truncate(self.outdent(&indentation).len());
+        }
+
+        indentation
+    }
+
+    /// Indentation one level shallower than `indentation`
+    pub fn outdent<'a>(&self, indentation: &'a str) -> &'a str {
+        if let Some(outdented) = indentation.strip_suffix(self.unit.as_str()) {
+            return outdented;
+        }
+
+        if let Some(outdented) = indentation.strip_suffix('\t') {
+            return outdented;
+        }
+
+        let spaces = indentation.len() - indentation.trim_end_matches(' ').len();
+        let width = self.unit.chars().filter(|c| *c == ' ').count().max(1);
+
+        &indentation[..indentation.len() - spaces.min(width)]
+    }
+
+    /// Whether `word`, typed right after indentation of the line, closes a block
+    pub fn is_closing(&self, word: &str) -> bool {
+        self.decrease_before.iter().any(|token| token == word)
+    }
+
+    fn opens_block(&self, line: &str) -> bool {
+        self.increase_after
+            .iter()
+            .any(|token| line.ends_with(token.as_str()))
+    }
+
+    fn closes_block(&self, line: &str) -> bool {
+        self.decrease_before
+            .iter()
+            .any(|token| line.starts_with(token.as_str()))
+    }
+}
+
+/// Spaces and tabs at the start of `line`
+pub fn leading_whitespace(line: &str) -> &str {
+    let end = line
+        .find(|c| !matches!(c, ' ' | '\t'))
+        .unwrap_or(line.len());
+
+    &line[..end]
+}
+
+#[cfg(test)]
+mod test {
+    use ropey::Rope;
+
+    use super::*;
+
+    fn braces() -> Indent {
+        Indent {
+            increase_after: vec!["{".to_string()],
+            decrease_before: vec!["}".to_string()],
+            ..Indent::default()
+        }
+    }
+
+    #[test]
+    fn new_line_indentation() {
+        let text = Rope::from("  fn a() {\n  }\n");
+        let text = text.slice(..);
+
+        assert_eq!(Indent::default().new_line(text, 10), "  ", "copied");
+        assert_eq!(braces().new_line(text, 10), "      ", "block opened");
+        assert_eq!(braces().new_line(text, 9), "  ", "brace stays on the line");
+        assert_eq!(braces().new_line(text, 1), " ", "only indentation before");
+        assert_eq!(braces().new_line(text, 13), "", "closing brace follows");
+    }
+
+    #[test]
+    fn outdent() {
+        let indent = Indent::default();
+        assert_eq!(indent.outdent("        "), "    ");
+        assert_eq!(indent.outdent("  "), "");
+        assert_eq!(indent.outdent("\t\t"), "\t");
+
+        let tabs = Indent {
+            unit: "\t".to_string(),
+            ..Indent::default()
+        };
+        assert_eq!(tabs.outdent("\t  "), "\t ");
+        assert_eq!(tabs.outdent(""), "");
+    }
+}
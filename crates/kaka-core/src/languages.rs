@@ -1,24 +1,79 @@
-use std::{collections::HashMap, fs::File, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    path::Path,
+};
 
 use serde::Deserialize;
 
-#[derive(Debug)]
+use crate::indent::Indent;
+
+/// `languages.yaml` shipped with the editor
+const BUILTIN: &str = include_str!("../languages.yaml");
+
+#[derive(Debug, Default)]
 pub struct Languages {
-    pub languages: HashMap<String, Language>,
+    languages: BTreeMap<String, Language>,
+    /// Name of language of files with the extension
+    by_extension: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Language {
     pub extensions: Vec<String>,
     pub treesitter: String,
+    #[serde(default)]
+    pub indent: Indent,
 }
 
 impl Languages {
     pub fn from_yaml(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         let file = File::open(path)?;
-        let languages = serde_yaml::from_reader(file)?;
 
-        Ok(Self { languages })
+        let mut this = Self::default();
+        this.add(serde_yaml::from_reader(file)?);
+
+        Ok(this)
+    }
+
+    /// Languages known without any configuration
+    pub fn builtin() -> Self {
+        let mut this = Self::default();
+        this.add(serde_yaml::from_str(BUILTIN).expect("Builtin languages.yaml is valid"));
+
+        this
+    }
+
+    /// Languages of `other` replace the ones with the same name and take over their extensions
+    pub fn extend(&mut self, other: Self) {
+        self.add(other.languages);
+    }
+
+    /// Language of file chosen by its extension
+    pub fn for_path(&self, path: &Path) -> Option<&Language> {
+        let extension = path.extension()?.to_str()?;
+
+        self.languages.get(self.by_extension.get(extension)?)
+    }
+
+    /// Extension claimed by more languages belongs to the one added last, the last one by name
+    /// among languages added together
+    fn add(&mut self, languages: BTreeMap<String, Language>) {
+        for (name, language) in languages {
+            if let Some(replaced) = self.languages.remove(&name) {
+                for extension in replaced.extensions {
+                    if self.by_extension.get(&extension) == Some(&name) {
+                        self.by_extension.remove(&extension);
+                    }
+                }
+            }
+
+            for extension in &language.extensions {
+                self.by_extension.insert(extension.clone(), name.clone());
+            }
+
+            self.languages.insert(name, language);
+        }
     }
 }
 
@@ -28,9 +83,43 @@ mod test {
 
     #[test]
     fn parse_own_languages_yaml() {
-        let file = "../../usr.share.kaka/languages.yaml";
+        let file = "languages.yaml";
         let languages = Languages::from_yaml(file).unwrap();
 
         println!("Languages: {languages:#?}");
     }
+
+    #[test]
+    fn language_for_path() {
+        let languages = Languages::builtin();
+
+        let rust = languages.for_path(Path::new("src/main.rs")).unwrap();
+        assert_eq!(rust.treesitter, "tree-sitter-rust");
+        assert_eq!(rust.indent.unit, "    ");
+
+        let go = languages.for_path(Path::new("main.go")).unwrap();
+        assert_eq!(go.indent.unit, "\t");
+
+        assert!(languages.for_path(Path::new("README")).is_none());
+    }
+
+    #[test]
+    fn extension_of_more_languages() {
+        let yaml =
+            "b:\n  extensions: [x]\n  treesitter: b\na:\n  extensions: [x, y]\n  treesitter: a\n";
+
+        let mut languages = Languages::default();
+        languages.add(serde_yaml::from_str(yaml).unwrap());
+
+        let x = languages.for_path(Path::new("file.x")).unwrap();
+        assert_eq!(x.treesitter, "b", "last one by name");
+
+        let mut builtin = Languages::builtin();
+        builtin.extend(languages);
+
+        let rust = builtin.for_path(Path::new("main.rs")).unwrap();
+        assert_eq!(rust.treesitter, "tree-sitter-rust");
+        let y = builtin.for_path(Path::new("file.y")).unwrap();
+        assert_eq!(y.treesitter, "a", "added later");
+    }
 }
@@ -5,16 +5,23 @@
     clippy::use_self
 )]
 
+pub mod diff;
 pub mod document;
 pub mod graphemes;
 pub mod history;
+pub mod indent;
 pub mod languages;
+pub mod movement;
+pub mod search;
 pub mod selection;
 pub mod shapes;
 pub mod span;
+pub mod substitute;
 pub mod transaction;
 
 // re-export ropey
 pub use ropey;
 
+pub use encoding_rs;
+
 pub type SmartString = smartstring::SmartString<smartstring::LazyCompact>;
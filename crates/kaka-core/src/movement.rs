@@ -0,0 +1,549 @@
+//! Text motions operating on grapheme boundaries.
+//!
+//! Every function takes position of the cursor and returns position of the target, counts
+//! are applied by repeating the motion.
+use ropey::RopeSlice;
+
+use crate::graphemes::{next_grapheme_boundary, prev_grapheme_boundary};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+impl Direction {
+    pub const fn reverse(self) -> Self {
+        match self {
+            Self::Forward => Self::Backward,
+            Self::Backward => Self::Forward,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Whitespace,
+    Eol,
+    Word,
+    Punctuation,
+}
+
+impl CharClass {
+    pub fn of(c: char) -> Self {
+        match c {
+            '\n' | '\r' => Self::Eol,
+            c if c.is_whitespace() => Self::Whitespace,
+            c if c.is_alphanumeric() || c == '_' => Self::Word,
+            _ => Self::Punctuation,
+        }
+    }
+
+    pub const fn is_blank(self) -> bool {
+        matches!(self, Self::Whitespace | Self::Eol)
+    }
+}
+
+/// `Word` is a sequence of letters, digits and underscores or a sequence of other non-blank
+/// characters, `LongWord` (WORD) is a sequence of any non-blank characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordKind {
+    Word,
+    LongWord,
+}
+
+impl WordKind {
+    fn class(self, slice: RopeSlice, pos: usize) -> CharClass {
+        let class = CharClass::of(slice.char(pos));
+
+        match (self, class) {
+            (Self::LongWord, CharClass::Punctuation) => CharClass::Word,
+            _ => class,
+        }
+    }
+}
+
+/// Line containing only line break
+fn is_empty_line(slice: RopeSlice, pos: usize) -> bool {
+    slice.char(pos) == '\n' && (pos == 0 || slice.char(pos - 1) == '\n')
+}
+
+/// `w`, `W`
+pub fn next_word_start(slice: RopeSlice, mut pos: usize, count: usize, kind: WordKind) -> usize {
+    let len = slice.len_chars();
+
+    for _ in 0..count {
+        if pos >= len {
+            break;
+        }
+
+        let class = kind.class(slice, pos);
+
+        if !class.is_blank() {
+            while pos < len && kind.class(slice, pos) == class {
+                pos = next_grapheme_boundary(slice, pos);
+            }
+        } else if is_empty_line(slice, pos) {
+            pos = next_grapheme_boundary(slice, pos);
+        }
+
+        // empty line counts as a word
+        while pos < len && kind.class(slice, pos).is_blank() && !is_empty_line(slice, pos) {
+            pos = next_grapheme_boundary(slice, pos);
+        }
+    }
+
+    pos
+}
+
+/// `b`, `B`
+pub fn prev_word_start(slice: RopeSlice, mut pos: usize, count: usize, kind: WordKind) -> usize {
+    for _ in 0..count {
+        if pos == 0 {
+            break;
+        }
+
+        pos = prev_grapheme_boundary(slice, pos);
+
+        while pos > 0 && kind.class(slice, pos).is_blank() && !is_empty_line(slice, pos) {
+            pos = prev_grapheme_boundary(slice, pos);
+        }
+
+        let class = kind.class(slice, pos);
+
+        if !class.is_blank() {
+            while pos > 0 {
+                let prev = prev_grapheme_boundary(slice, pos);
+                if kind.class(slice, prev) != class {
+                    break;
+                }
+                pos = prev;
+            }
+        }
+    }
+
+    pos
+}
+
+/// `e`, `E`
+pub fn next_word_end(slice: RopeSlice, mut pos: usize, count: usize, kind: WordKind) -> usize {
+    let len = slice.len_chars();
+
+    for _ in 0..count {
+        let mut next = next_grapheme_boundary(slice, pos);
+
+        while next < len && kind.class(slice, next).is_blank() {
+            next = next_grapheme_boundary(slice, next);
+        }
+
+        if next >= len {
+            break;
+        }
+
+        pos = next;
+        let class = kind.class(slice, pos);
+
+        loop {
+            let next = next_grapheme_boundary(slice, pos);
+            if next >= len || kind.class(slice, next) != class {
+                break;
+            }
+            pos = next;
+        }
+    }
+
+    pos
+}
+
+/// `ge`, `gE`
+pub fn prev_word_end(slice: RopeSlice, mut pos: usize, count: usize, kind: WordKind) -> usize {
+    for _ in 0..count {
+        if pos >= slice.len_chars() {
+            pos = prev_grapheme_boundary(slice, pos);
+            continue;
+        }
+
+        let class = kind.class(slice, pos);
+
+        if !class.is_blank() {
+            while pos > 0 && kind.class(slice, pos) == class {
+                pos = prev_grapheme_boundary(slice, pos);
+            }
+        } else if pos > 0 {
+            pos = prev_grapheme_boundary(slice, pos);
+        }
+
+        while pos > 0 && kind.class(slice, pos).is_blank() && !is_empty_line(slice, pos) {
+            pos = prev_grapheme_boundary(slice, pos);
+        }
+    }
+
+    pos
+}
+
+/// `}`, moves to the first empty line after paragraph
+pub fn next_paragraph(slice: RopeSlice, pos: usize, count: usize) -> usize {
+    let last_line = slice.len_lines().saturating_sub(1);
+    let is_empty = |line_idx| slice.line(line_idx).chars().all(|c| c == '\n');
+
+    let mut line_idx = slice.char_to_line(pos);
+
+    for _ in 0..count {
+        while line_idx < last_line && is_empty(line_idx) {
+            line_idx += 1;
+        }
+
+        while line_idx < last_line && !is_empty(line_idx) {
+            line_idx += 1;
+        }
+    }
+
+    if line_idx == last_line && !is_empty(line_idx) {
+        slice.len_chars()
+    } else {
+        slice.line_to_char(line_idx)
+    }
+}
+
+/// `{`, moves to the first empty line before paragraph
+pub fn prev_paragraph(slice: RopeSlice, pos: usize, count: usize) -> usize {
+    let is_empty = |line_idx| slice.line(line_idx).chars().all(|c| c == '\n');
+
+    let mut line_idx = slice.char_to_line(pos);
+
+    for _ in 0..count {
+        while line_idx > 0 && is_empty(line_idx) {
+            line_idx -= 1;
+        }
+
+        while line_idx > 0 && !is_empty(line_idx) {
+            line_idx -= 1;
+        }
+    }
+
+    slice.line_to_char(line_idx)
+}
+
+/// Sentence starts after `.`, `!` or `?` followed by optional closing characters and
+/// whitespace. Empty lines are sentence boundaries as well.
+fn is_sentence_start(slice: RopeSlice, pos: usize) -> bool {
+    let c = slice.char(pos);
+
+    if c == '\n' {
+        return is_empty_line(slice, pos);
+    }
+
+    if c.is_whitespace() {
+        return false;
+    }
+
+    let mut idx = pos;
+    let mut newlines = 0;
+
+    while idx > 0 && slice.char(idx - 1).is_whitespace() {
+        if slice.char(idx - 1) == '\n' {
+            newlines += 1;
+        }
+        idx -= 1;
+    }
+
+    if idx == 0 || newlines > 1 {
+        return true;
+    }
+
+    if idx == pos {
+        return false;
+    }
+
+    while idx > 0 && matches!(slice.char(idx - 1), ')' | ']' | '"' | '\'') {
+        idx -= 1;
+    }
+
+    idx > 0 && matches!(slice.char(idx - 1), '.' | '!' | '?')
+}
+
+/// `)`
+pub fn next_sentence(slice: RopeSlice, mut pos: usize, count: usize) -> usize {
+    let len = slice.len_chars();
+
+    for _ in 0..count {
+        pos = (pos + 1..len)
+            .find(|&idx| is_sentence_start(slice, idx))
+            .unwrap_or(len);
+    }
+
+    pos
+}
+
+/// `(`
+pub fn prev_sentence(slice: RopeSlice, mut pos: usize, count: usize) -> usize {
+    for _ in 0..count {
+        pos = (0..pos)
+            .rev()
+            .find(|&idx| is_sentence_start(slice, idx))
+            .unwrap_or(0);
+    }
+
+    pos
+}
+
+/// In-line search of a character, `f`, `F`, `t` and `T`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FindChar {
+    pub ch: char,
+    pub direction: Direction,
+    /// Stop next to the found character
+    pub till: bool,
+}
+
+impl FindChar {
+    /// Search in the opposite direction, `,`
+    pub const fn reversed(self) -> Self {
+        Self {
+            direction: self.direction.reverse(),
+            ..self
+        }
+    }
+
+    /// Finds `count`th occurrence on the line containing `pos`.
+    ///
+    /// When repeating, till search skips character next to the cursor, otherwise it would
+    /// not move.
+    pub fn find(self, slice: RopeSlice, pos: usize, count: usize, repeat: bool) -> Option<usize> {
+        let line_idx = slice.char_to_line(pos);
+        let line_start = slice.line_to_char(line_idx);
+        let line = slice.line(line_idx);
+
+        // line break is not searchable
+        let line_len = match line.len_chars() {
+            len if line.get_char(len.saturating_sub(1)) == Some('\n') => len - 1,
+            len => len,
+        };
+        let line = line.slice(..line_len);
+
+        let mut buf = [0; 4];
+        let needle = &*self.ch.encode_utf8(&mut buf);
+        let matches = |idx: usize| line.slice(idx..next_grapheme_boundary(line, idx)) == needle;
+
+        let mut idx = pos - line_start;
+        let skip = usize::from(self.till && repeat);
+
+        let found = match self.direction {
+            Direction::Forward => {
+                for _ in 0..skip {
+                    idx = next_grapheme_boundary(line, idx);
+                }
+
+                for _ in 0..count {
+                    loop {
+                        idx = next_grapheme_boundary(line, idx);
+
+                        if idx >= line_len {
+                            return None;
+                        }
+
+                        if matches(idx) {
+                            break;
+                        }
+                    }
+                }
+
+                if self.till {
+                    prev_grapheme_boundary(line, idx)
+                } else {
+                    idx
+                }
+            }
+            Direction::Backward => {
+                for _ in 0..skip {
+                    idx = prev_grapheme_boundary(line, idx);
+                }
+
+                for _ in 0..count {
+                    loop {
+                        if idx == 0 {
+                            return None;
+                        }
+
+                        idx = prev_grapheme_boundary(line, idx);
+
+                        if matches(idx) {
+                            break;
+                        }
+                    }
+                }
+
+                if self.till {
+                    next_grapheme_boundary(line, idx)
+                } else {
+                    idx
+                }
+            }
+        };
+
+        Some(line_start + found)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ropey::Rope;
+
+    use super::*;
+
+    /// Follows motion `f` from `start`, collecting visited positions
+    fn hops(
+        text: &str,
+        start: usize,
+        n: usize,
+        f: impl Fn(RopeSlice, usize) -> usize,
+    ) -> Vec<usize> {
+        let rope = Rope::from(text);
+        let slice = rope.slice(..);
+
+        let mut pos = start;
+        (0..n)
+            .map(|_| {
+                pos = f(slice, pos);
+                pos
+            })
+            .collect()
+    }
+
+    #[test]
+    fn word_start() {
+        let text = "foo.bar  baz\n\n  qux";
+
+        assert_eq!(
+            hops(text, 0, 6, |s, p| next_word_start(s, p, 1, WordKind::Word)),
+            [3, 4, 9, 13, 16, 19]
+        );
+
+        assert_eq!(
+            hops(text, 19, 6, |s, p| prev_word_start(s, p, 1, WordKind::Word)),
+            [16, 13, 9, 4, 3, 0]
+        );
+
+        assert_eq!(
+            hops(text, 0, 3, |s, p| next_word_start(
+                s,
+                p,
+                1,
+                WordKind::LongWord
+            )),
+            [9, 13, 16]
+        );
+
+        assert_eq!(
+            hops(text, 9, 1, |s, p| prev_word_start(
+                s,
+                p,
+                1,
+                WordKind::LongWord
+            )),
+            [0]
+        );
+    }
+
+    #[test]
+    fn word_end() {
+        let text = "foo.bar  baz\n\n  qux";
+
+        assert_eq!(
+            hops(text, 0, 5, |s, p| next_word_end(s, p, 1, WordKind::Word)),
+            [2, 3, 6, 11, 18]
+        );
+
+        assert_eq!(
+            hops(text, 0, 2, |s, p| next_word_end(
+                s,
+                p,
+                1,
+                WordKind::LongWord
+            )),
+            [6, 11]
+        );
+
+        assert_eq!(
+            hops(text, 16, 5, |s, p| prev_word_end(s, p, 1, WordKind::Word)),
+            [13, 11, 6, 3, 2]
+        );
+    }
+
+    #[test]
+    fn word_grapheme_aware() {
+        // combining acute accent is part of a word
+        let text = "cafe\u{301} bar";
+
+        assert_eq!(
+            hops(text, 0, 1, |s, p| next_word_start(s, p, 1, WordKind::Word)),
+            [6]
+        );
+
+        assert_eq!(
+            hops(text, 0, 1, |s, p| next_word_end(s, p, 1, WordKind::Word)),
+            [3]
+        );
+    }
+
+    #[test]
+    fn word_count() {
+        let rope = Rope::from("a b c d");
+        let slice = rope.slice(..);
+
+        assert_eq!(next_word_start(slice, 0, 3, WordKind::Word), 6);
+        assert_eq!(prev_word_start(slice, 6, 2, WordKind::Word), 2);
+        assert_eq!(next_word_end(slice, 0, 2, WordKind::Word), 4);
+    }
+
+    #[test]
+    fn paragraph() {
+        let text = "a\nb\n\n\nc\n\nd";
+
+        assert_eq!(hops(text, 0, 3, |s, p| next_paragraph(s, p, 1)), [4, 8, 10]);
+
+        assert_eq!(hops(text, 9, 3, |s, p| prev_paragraph(s, p, 1)), [8, 5, 0]);
+    }
+
+    #[test]
+    fn sentence() {
+        let text = "One. Two? (Three!) \"Four.\"  Five\n\nSix";
+
+        assert_eq!(
+            hops(text, 0, 6, |s, p| next_sentence(s, p, 1)),
+            [5, 10, 19, 28, 33, 34]
+        );
+
+        assert_eq!(
+            hops(text, 35, 7, |s, p| prev_sentence(s, p, 1)),
+            [34, 33, 28, 19, 10, 5, 0]
+        );
+    }
+
+    #[test]
+    fn find_char() {
+        let rope = Rope::from("a,b,c\u{301},c\n,");
+        let slice = rope.slice(..);
+
+        let f = FindChar {
+            ch: ',',
+            direction: Direction::Forward,
+            till: false,
+        };
+        assert_eq!(f.find(slice, 0, 1, false), Some(1));
+        assert_eq!(f.find(slice, 0, 3, false), Some(6));
+        assert_eq!(f.find(slice, 0, 4, false), None, "search stays in line");
+
+        let t = FindChar { till: true, ..f };
+        assert_eq!(t.find(slice, 0, 1, false), Some(0));
+        assert_eq!(t.find(slice, 0, 1, true), Some(2));
+
+        let b = f.reversed();
+        assert_eq!(b.find(slice, 7, 1, false), Some(6));
+        assert_eq!(b.find(slice, 7, 2, false), Some(3));
+        assert_eq!(b.find(slice, 0, 1, false), None);
+
+        let c = FindChar { ch: 'c', ..f };
+        assert_eq!(c.find(slice, 0, 1, false), Some(7), "grapheme is not split");
+    }
+}
@@ -0,0 +1,571 @@
+//! Regex search running directly over rope chunks.
+//!
+//! Lazy DFAs from `regex-automata` are fed byte by byte, so the text is never copied into
+//! one contiguous buffer. Forward DFA finds the end of the leftmost match, then reverse
+//! DFA, anchored at that end, finds its start. Searching backwards, unanchored reverse DFA
+//! walks back from the cursor until it finds the start of a match.
+//!
+//! Lazy DFAs can't handle unicode word boundaries on non-ASCII text, they give up and the
+//! search falls back to the meta regex, running over a contiguous copy of the text.
+use std::{borrow::Cow, ops::Range, sync::Arc};
+
+use regex_automata::{
+    hybrid::{
+        dfa::{Cache as DfaCache, DFA},
+        regex::{Cache, Regex as HybridRegex},
+        LazyStateID,
+    },
+    meta,
+    util::{
+        pool::{Pool, PoolGuard},
+        start,
+    },
+    Anchored, Input,
+};
+use ropey::RopeSlice;
+use thiserror::Error;
+
+use crate::movement::Direction;
+
+#[derive(Debug, Error)]
+#[error("Invalid pattern: {0}")]
+pub struct InvalidPattern(pub(crate) String);
+
+type CreateCache = Box<dyn Fn() -> Cache + Send + Sync>;
+
+#[derive(Debug)]
+pub struct Regex {
+    pattern: String,
+    inner: Arc<HybridRegex>,
+    /// Caches reused by searches, so lazy DFAs keep states built by previous searches
+    caches: Pool<Cache, CreateCache>,
+    /// Used when lazy DFA quits
+    fallback: meta::Regex,
+}
+
+/// Lazy DFA gave up on the text
+struct Quit;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, InvalidPattern> {
+        let inner = HybridRegex::builder()
+            .dfa(DFA::config().unicode_word_boundary(true))
+            .build(pattern)
+            .map_err(|err| InvalidPattern(err.to_string()))?;
+        let fallback = meta::Regex::new(pattern).map_err(|err| InvalidPattern(err.to_string()))?;
+
+        let inner = Arc::new(inner);
+        let regex = Arc::clone(&inner);
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            inner,
+            caches: Pool::new(Box::new(move || regex.create_cache())),
+            fallback,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// First match starting at or after `from`, wraps around the end of text
+    pub fn find_next(&self, slice: RopeSlice, from: usize) -> Option<Range<usize>> {
+        let mut searcher = self.searcher(slice);
+        let from = slice.char_to_byte(from);
+
+        searcher
+            .find(from, slice.len_bytes())
+            .or_else(|| searcher.find(0, slice.len_bytes()))
+            .map(|range| to_char_range(slice, range))
+    }
+
+    /// Last match starting before `before`, wraps around the beginning of text
+    ///
+    /// Only text back to the match is read, matches are looked for among those ending by the
+    /// end of the line of `before`.
+    pub fn find_prev(&self, slice: RopeSlice, before: usize) -> Option<Range<usize>> {
+        let before = slice.char_to_byte(before);
+        let mut searcher = self.searcher(slice);
+
+        let found = match searcher.find_before(before) {
+            Ok(None) => searcher.find_before(slice.len_bytes() + 1),
+            found => found,
+        };
+
+        match found {
+            Ok(found) => found.map(|range| to_char_range(slice, range)),
+            Err(Quit) => {
+                drop(searcher);
+                self.scan_prev(slice, before)
+            }
+        }
+    }
+
+    /// Like [`Self::find_prev`] with `before` in bytes, going through all matches from the
+    /// beginning of text
+    fn scan_prev(&self, slice: RopeSlice, before: usize) -> Option<Range<usize>> {
+        let mut last = None;
+        let mut wrapped = None;
+
+        for range in self.find_iter_bytes(slice, 0, slice.len_bytes()) {
+            if range.start < before {
+                last = Some(range);
+            } else if last.is_some() {
+                break;
+            } else {
+                wrapped = Some(range);
+            }
+        }
+
+        last.or(wrapped).map(|range| to_char_range(slice, range))
+    }
+
+    /// Matches found between `range` bounds, text outside is used only as context
+    pub fn find_iter<'a>(
+        &'a self,
+        slice: RopeSlice<'a>,
+        range: Range<usize>,
+    ) -> impl Iterator<Item = Range<usize>> + 'a {
+        let start = slice.char_to_byte(range.start);
+        let end = slice.char_to_byte(range.end);
+
+        self.find_iter_bytes(slice, start, end)
+            .map(move |range| to_char_range(slice, range))
+    }
+
+    fn find_iter_bytes<'a>(
+        &'a self,
+        slice: RopeSlice<'a>,
+        start: usize,
+        end: usize,
+    ) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut searcher = self.searcher(slice);
+        let mut pos = start;
+        let mut last_end = None;
+
+        std::iter::from_fn(move || loop {
+            if pos > end {
+                return None;
+            }
+
+            let found = searcher.find(pos, end)?;
+
+            // skip empty match right after previous match
+            if found.is_empty() && Some(found.end) == last_end {
+                pos = next_char_boundary(slice, found.end);
+                continue;
+            }
+
+            pos = if found.is_empty() {
+                next_char_boundary(slice, found.end)
+            } else {
+                found.end
+            };
+            last_end = Some(found.end);
+
+            return Some(found);
+        })
+    }
+
+    fn searcher<'a>(&'a self, slice: RopeSlice<'a>) -> Searcher<'a> {
+        Searcher {
+            regex: self,
+            slice,
+            cache: self.caches.get(),
+            haystack: None,
+        }
+    }
+}
+
+/// Searches of one regex in one text
+struct Searcher<'a> {
+    regex: &'a Regex,
+    slice: RopeSlice<'a>,
+    cache: PoolGuard<'a, Cache, CreateCache>,
+    /// Contiguous text for the fallback, copied once the lazy DFA quits
+    haystack: Option<Cow<'a, str>>,
+}
+
+impl Searcher<'_> {
+    /// Leftmost match in bytes `start..end`
+    fn find(&mut self, start: usize, end: usize) -> Option<Range<usize>> {
+        if self.haystack.is_none() {
+            match self.find_lazy(start, end) {
+                Ok(found) => return found,
+                Err(Quit) => {
+                    let slice = self.slice;
+                    self.haystack = Some(
+                        slice
+                            .as_str()
+                            .map_or_else(|| Cow::Owned(slice.to_string()), Cow::Borrowed),
+                    );
+                }
+            }
+        }
+
+        let haystack = self.haystack.as_deref().unwrap_or_default();
+        let input = Input::new(haystack).span(start..end);
+
+        self.regex
+            .fallback
+            .search(&input)
+            .map(|found| found.range())
+    }
+
+    /// Match starting last before byte `limit` among those ending by the end of its line,
+    /// lazy DFAs only
+    fn find_before(&mut self, limit: usize) -> Result<Option<Range<usize>>, Quit> {
+        let len = self.slice.len_bytes();
+        let line_idx = self.slice.byte_to_line(limit.min(len));
+        let end = self
+            .slice
+            .line_to_byte((line_idx + 1).min(self.slice.len_lines()));
+
+        let rev_cache = self.cache.reverse_mut();
+        let reverse = self.regex.inner.reverse();
+
+        let Some(start) = find_last_start(reverse, rev_cache, self.slice, limit, end)? else {
+            return Ok(None);
+        };
+
+        // the match found backwards may be preferred less than one starting at the same place
+        self.find_lazy(start, len)
+    }
+
+    fn find_lazy(&mut self, start: usize, end: usize) -> Result<Option<Range<usize>>, Quit> {
+        let (fwd_cache, rev_cache) = self.cache.as_parts_mut();
+        let inner = &self.regex.inner;
+
+        let Some(match_end) = find_end(inner.forward(), fwd_cache, self.slice, start, end)? else {
+            return Ok(None);
+        };
+        let match_start = find_start(inner.reverse(), rev_cache, self.slice, start, match_end)?;
+
+        Ok(match_start.map(|match_start| match_start..match_end))
+    }
+}
+
+/// Pattern searched with `/` or `?`, repeated by `n` and `N`
+#[derive(Debug, Clone)]
+pub struct Search {
+    pub regex: Arc<Regex>,
+    pub direction: Direction,
+}
+
+impl Search {
+    pub fn new(regex: Regex, direction: Direction) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            direction,
+        }
+    }
+
+    #[must_use]
+    pub fn reversed(&self) -> Self {
+        Self {
+            regex: Arc::clone(&self.regex),
+            direction: self.direction.reverse(),
+        }
+    }
+
+    /// Start of `count`-th match from `pos` in search direction, wraps around the text
+    pub fn find(&self, slice: RopeSlice, pos: usize, count: usize) -> Option<usize> {
+        let mut pos = pos;
+
+        for _ in 0..count.max(1) {
+            let found = match self.direction {
+                Direction::Forward => self
+                    .regex
+                    .find_next(slice, (pos + 1).min(slice.len_chars())),
+                Direction::Backward => self.regex.find_prev(slice, pos),
+            };
+
+            pos = found?.start;
+        }
+
+        Some(pos)
+    }
+}
+
+fn byte_at(slice: RopeSlice, idx: usize) -> Option<u8> {
+    (idx < slice.len_bytes()).then(|| slice.byte(idx))
+}
+
+fn is_char_boundary(slice: RopeSlice, byte_idx: usize) -> bool {
+    // continuation bytes of UTF-8 are 0b10xxxxxx
+    byte_at(slice, byte_idx).is_none_or(|byte| byte & 0xc0 != 0x80)
+}
+
+fn next_char_boundary(slice: RopeSlice, byte_idx: usize) -> usize {
+    let char_idx = slice.byte_to_char(byte_idx);
+    slice
+        .char_to_byte((char_idx + 1).min(slice.len_chars()))
+        .max(byte_idx + 1)
+}
+
+fn to_char_range(slice: RopeSlice, range: Range<usize>) -> Range<usize> {
+    slice.byte_to_char(range.start)..slice.byte_to_char(range.end)
+}
+
+/// Follows transition for the byte after haystack, or end of input transition
+fn finish(
+    dfa: &DFA,
+    cache: &mut DfaCache,
+    sid: LazyStateID,
+    next: Option<u8>,
+) -> Result<LazyStateID, Quit> {
+    let sid = match next {
+        Some(byte) => dfa.next_state(cache, sid, byte),
+        None => dfa.next_eoi_state(cache, sid),
+    };
+
+    sid.ok().filter(|sid| !sid.is_quit()).ok_or(Quit)
+}
+
+/// Next state, quitting if the cache can't hold more states or the DFA can't continue
+fn next(dfa: &DFA, cache: &mut DfaCache, sid: LazyStateID, byte: u8) -> Result<LazyStateID, Quit> {
+    let sid = dfa.next_state(cache, sid, byte).map_err(|_| Quit)?;
+
+    if sid.is_quit() {
+        return Err(Quit);
+    }
+
+    Ok(sid)
+}
+
+/// End of the leftmost-first match, matches are reported by the DFA one byte late
+fn find_end(
+    dfa: &DFA,
+    cache: &mut DfaCache,
+    slice: RopeSlice,
+    start: usize,
+    end: usize,
+) -> Result<Option<usize>, Quit> {
+    let look_behind = start.checked_sub(1).and_then(|idx| byte_at(slice, idx));
+    let config = start::Config::new()
+        .anchored(Anchored::No)
+        .look_behind(look_behind);
+
+    let mut sid = dfa.start_state(cache, &config).map_err(|_| Quit)?;
+    let mut last_match = None;
+
+    for (at, byte) in (start..end).zip(slice.bytes_at(start)) {
+        sid = next(dfa, cache, sid, byte)?;
+
+        if sid.is_match() {
+            last_match = Some(at);
+        } else if sid.is_dead() {
+            return Ok(last_match);
+        }
+    }
+
+    sid = finish(dfa, cache, sid, byte_at(slice, end))?;
+    if sid.is_match() {
+        last_match = Some(end);
+    }
+
+    Ok(last_match)
+}
+
+/// Start of the match ending at `end`, found by reverse DFA walking back to `start`
+fn find_start(
+    dfa: &DFA,
+    cache: &mut DfaCache,
+    slice: RopeSlice,
+    start: usize,
+    end: usize,
+) -> Result<Option<usize>, Quit> {
+    let config = start::Config::new()
+        .anchored(Anchored::Yes)
+        .look_behind(byte_at(slice, end));
+
+    let mut sid = dfa.start_state(cache, &config).map_err(|_| Quit)?;
+    let mut last_match = None;
+
+    for (at, byte) in (start..end).rev().zip(slice.bytes_at(end).reversed()) {
+        sid = next(dfa, cache, sid, byte)?;
+
+        if sid.is_match() {
+            last_match = Some(at + 1);
+        } else if sid.is_dead() {
+            return Ok(last_match);
+        }
+    }
+
+    let before = start.checked_sub(1).and_then(|idx| byte_at(slice, idx));
+    sid = finish(dfa, cache, sid, before)?;
+    if sid.is_match() {
+        last_match = Some(start);
+    }
+
+    Ok(last_match)
+}
+
+/// Start of the last match starting before `limit` and ending by `end`, found by unanchored
+/// reverse DFA walking back from `end`
+fn find_last_start(
+    dfa: &DFA,
+    cache: &mut DfaCache,
+    slice: RopeSlice,
+    limit: usize,
+    end: usize,
+) -> Result<Option<usize>, Quit> {
+    let config = start::Config::new()
+        .anchored(Anchored::No)
+        .look_behind(byte_at(slice, end));
+
+    let mut sid = dfa.start_state(cache, &config).map_err(|_| Quit)?;
+
+    for (at, byte) in (0..end).rev().zip(slice.bytes_at(end).reversed()) {
+        sid = next(dfa, cache, sid, byte)?;
+
+        if sid.is_match() && at + 1 < limit && is_char_boundary(slice, at + 1) {
+            return Ok(Some(at + 1));
+        } else if sid.is_dead() {
+            return Ok(None);
+        }
+    }
+
+    sid = finish(dfa, cache, sid, None)?;
+
+    Ok((sid.is_match() && limit > 0).then_some(0))
+}
+
+#[cfg(test)]
+mod test {
+    use ropey::{Rope, RopeBuilder};
+
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> Vec<(usize, usize)> {
+        let rope = Rope::from(text);
+        let regex = Regex::new(pattern).unwrap();
+
+        regex
+            .find_iter(rope.slice(..), 0..rope.len_chars())
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    #[test]
+    fn find_iter() {
+        assert_eq!(matches("a+", "baaab a"), [(1, 4), (6, 7)]);
+        assert_eq!(matches("foo|foobar", "foobar"), [(0, 3)]);
+        assert!(matches("^b", "a\nb\nb").is_empty());
+        assert_eq!(matches("(?m)^b", "a\nb\nb"), [(2, 3), (4, 5)]);
+        assert_eq!(matches("x*", "ab"), [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(matches("ż+", "ążżb"), [(1, 3)], "positions are in chars");
+        assert_eq!(matches(r"\bab\b", "ab cab ab"), [(0, 2), (7, 9)]);
+    }
+
+    #[test]
+    fn word_boundary_in_non_ascii_text() {
+        assert_eq!(matches(r"\bfoo", "zażółć foo bar"), [(7, 10)]);
+        assert_eq!(matches(r"\bść\b", "ćść ść"), [(4, 6)]);
+
+        let rope = Rope::from("zażółć foo bar foo");
+        let regex = Regex::new(r"\bfoo\b").unwrap();
+        assert_eq!(regex.find_next(rope.slice(..), 8), Some(15..18));
+        assert_eq!(regex.find_prev(rope.slice(..), 15), Some(7..10));
+    }
+
+    #[test]
+    fn find_iter_range_uses_context() {
+        let rope = Rope::from("abc abc");
+        let regex = Regex::new(r"\babc").unwrap();
+
+        let mut found = regex.find_iter(rope.slice(..), 1..7);
+        assert_eq!(found.next(), Some(4..7));
+        assert_eq!(found.next(), None);
+    }
+
+    #[test]
+    fn find_across_chunks() {
+        let mut builder = RopeBuilder::new();
+        for _ in 0..2000 {
+            builder.append("lorem ipsum ");
+        }
+        builder.append("needle");
+        let rope = builder.finish();
+        assert!(rope.chunks().count() > 1);
+
+        let regex = Regex::new("ne+dle").unwrap();
+        let len = rope.len_chars();
+
+        assert_eq!(regex.find_next(rope.slice(..), 0), Some(len - 6..len));
+        assert_eq!(regex.find_prev(rope.slice(..), 10), Some(len - 6..len));
+    }
+
+    #[test]
+    fn find_next_prev_wrap() {
+        let rope = Rope::from("ab ab ab");
+        let slice = rope.slice(..);
+        let regex = Regex::new("ab").unwrap();
+
+        assert_eq!(regex.find_next(slice, 1), Some(3..5));
+        assert_eq!(regex.find_next(slice, 7), Some(0..2));
+        assert_eq!(regex.find_prev(slice, 3), Some(0..2));
+        assert_eq!(regex.find_prev(slice, 0), Some(6..8));
+    }
+
+    #[test]
+    fn find_prev_backwards() {
+        let find_prev = |pattern: &str, text: &str, before: usize| {
+            let rope = Rope::from(text);
+            Regex::new(pattern)
+                .unwrap()
+                .find_prev(rope.slice(..), before)
+        };
+
+        assert_eq!(
+            find_prev("b.d", "abcd", 2),
+            Some(1..4),
+            "match goes past cursor"
+        );
+        assert_eq!(find_prev("foo|foobar", "foobar x", 7), Some(0..3));
+        assert_eq!(find_prev("(?m)^b", "b\nab\nb", 5), Some(0..1));
+        assert_eq!(find_prev(r"\bab", "cab ab", 5), Some(4..6));
+        assert_eq!(
+            find_prev("x*", "ąę", 2),
+            Some(1..1),
+            "positions are in chars"
+        );
+        assert_eq!(
+            find_prev("a\nb", "a\nb\n", 1),
+            Some(0..3),
+            "wraps to match of more lines"
+        );
+
+        let mut builder = RopeBuilder::new();
+        builder.append("needle ");
+        for _ in 0..2000 {
+            builder.append("lorem ipsum ");
+        }
+        let rope = builder.finish();
+
+        let regex = Regex::new("ne+dle").unwrap();
+        assert_eq!(
+            regex.find_prev(rope.slice(..), rope.len_chars()),
+            Some(0..6)
+        );
+    }
+
+    #[test]
+    fn search_count() {
+        let rope = Rope::from("ab ab ab");
+        let slice = rope.slice(..);
+        let search = Search::new(Regex::new("ab").unwrap(), Direction::Forward);
+
+        assert_eq!(search.find(slice, 0, 1), Some(3));
+        assert_eq!(search.find(slice, 0, 2), Some(6));
+        assert_eq!(search.find(slice, 0, 3), Some(0));
+        assert_eq!(search.reversed().find(slice, 0, 1), Some(6));
+        assert_eq!(search.reversed().find(slice, 7, 2), Some(3));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(Regex::new("(").is_err());
+    }
+}
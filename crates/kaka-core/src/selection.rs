@@ -1,23 +1,29 @@
 use ropey::RopeSlice;
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::{Assoc, Transaction};
 
 pub type SelectionInclusiveRange = (usize, usize);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Selection {
     anchor: usize,
     head: usize,
+    /// Column preferred by vertical movement
+    column: usize,
 }
 
 impl Selection {
     pub const fn new(anchor: usize, head: usize) -> Self {
-        Self { anchor, head }
+        Self {
+            anchor,
+            head,
+            column: 0,
+        }
     }
 
     pub const fn at_pos(pos: usize) -> Self {
-        Self {
-            anchor: pos,
-            head: pos,
-        }
+        Self::new(pos, pos)
     }
 
     pub const fn anchor(self) -> usize {
@@ -49,9 +55,201 @@ impl Selection {
         rope.slice(range.0..=range.1)
     }
 
-    pub fn update_head(&mut self, pos: usize) {
+    pub const fn column(self) -> usize {
+        self.column
+    }
+
+    pub const fn with_column(mut self, column: usize) -> Self {
+        self.column = column;
+        self
+    }
+
+    pub const fn update_head(&mut self, pos: usize) {
+        self.head = pos;
+    }
+
+    pub const fn update_column(&mut self, column: usize) {
+        self.column = column;
+    }
+
+    /// Moves both anchor and head to `pos`
+    pub const fn collapse(&mut self, pos: usize) {
+        self.anchor = pos;
         self.head = pos;
     }
+
+    pub const fn overlaps(self, other: Self) -> bool {
+        let (start, end) = self.range();
+        let (other_start, other_end) = other.range();
+
+        start <= other_end && other_start <= end
+    }
+
+    /// Returns selection spanning over both `self` and `other`.
+    ///
+    /// Direction and column are taken from `self`.
+    pub fn union(self, other: Self) -> Self {
+        let start = self.start().min(other.start());
+        let end = self.end().max(other.end());
+
+        let selection = if self.head >= self.anchor {
+            Self::new(start, end)
+        } else {
+            Self::new(end, start)
+        };
+
+        selection.with_column(self.column)
+    }
+
+    pub fn map(self, tx: &Transaction, assoc: Assoc) -> Self {
+        Self {
+            anchor: tx.map_pos(self.anchor, assoc),
+            head: tx.map_pos(self.head, assoc),
+            column: self.column,
+        }
+    }
+}
+
+/// Non-empty set of selections, one of them is primary.
+///
+/// Selections are kept sorted and never overlap - overlapping ones are merged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectionSet {
+    selections: Vec<Selection>,
+    primary: usize,
+}
+
+impl SelectionSet {
+    pub fn single(selection: Selection) -> Self {
+        Self {
+            selections: vec![selection],
+            primary: 0,
+        }
+    }
+
+    /// Creates selection set from provided selections
+    ///
+    /// # Panics
+    ///
+    /// When `selections` is empty or `primary` is out of bounds
+    pub fn new(selections: impl IntoIterator<Item = Selection>, primary: usize) -> Self {
+        let selections = selections.into_iter().collect::<Vec<_>>();
+
+        assert!(
+            primary < selections.len(),
+            "Primary selection out of bounds"
+        );
+
+        let mut this = Self {
+            selections,
+            primary,
+        };
+
+        this.normalize();
+
+        this
+    }
+
+    pub fn primary(&self) -> Selection {
+        self.selections[self.primary]
+    }
+
+    pub fn primary_mut(&mut self) -> &mut Selection {
+        &mut self.selections[self.primary]
+    }
+
+    pub const fn primary_index(&self) -> usize {
+        self.primary
+    }
+
+    #[track_caller]
+    pub fn set_primary(&mut self, idx: usize) {
+        assert!(
+            idx < self.selections.len(),
+            "Primary selection out of bounds"
+        );
+        self.primary = idx;
+    }
+
+    pub const fn len(&self) -> usize {
+        self.selections.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.selections.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Selection> + ExactSizeIterator {
+        self.selections.iter()
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = SelectionInclusiveRange> + '_ {
+        self.selections.iter().map(|s| s.range())
+    }
+
+    /// Adds selection and makes it primary
+    pub fn push(&mut self, selection: Selection) {
+        self.selections.push(selection);
+        self.primary = self.selections.len() - 1;
+        self.normalize();
+    }
+
+    /// Drops all selections but primary
+    pub fn keep_primary(&mut self) {
+        let primary = self.primary();
+        self.selections.clear();
+        self.selections.push(primary);
+        self.primary = 0;
+    }
+
+    pub fn transform(&mut self, mut f: impl FnMut(Selection) -> Selection) {
+        for selection in self.selections.iter_mut() {
+            *selection = f(*selection);
+        }
+
+        self.normalize();
+    }
+
+    pub fn map(&mut self, tx: &Transaction, assoc: Assoc) {
+        self.transform(|s| s.map(tx, assoc));
+    }
+
+    /// Sorts selections and merges overlapping ones
+    pub fn normalize(&mut self) {
+        let primary = self.primary();
+
+        self.selections.sort_by_key(|s| s.start());
+
+        // find primary again after sort, identical selections are indistinguishable
+        self.primary = self
+            .selections
+            .iter()
+            .position(|s| *s == primary)
+            .expect("primary selection vanished");
+
+        let mut merged: Vec<Selection> = Vec::with_capacity(self.selections.len());
+        let mut new_primary = 0;
+
+        for (idx, selection) in self.selections.drain(..).enumerate() {
+            match merged.last_mut() {
+                Some(last) if last.overlaps(selection) => {
+                    *last = if idx == self.primary {
+                        selection.union(*last)
+                    } else {
+                        last.union(selection)
+                    };
+                }
+                _ => merged.push(selection),
+            }
+
+            if idx == self.primary {
+                new_primary = merged.len() - 1;
+            }
+        }
+
+        self.selections = merged;
+        self.primary = new_primary;
+    }
 }
 
 #[cfg(test)]
@@ -91,4 +289,72 @@ mod test {
         selection.update_head(4);
         assert_eq!(selection.range(), (4, 5));
     }
+
+    #[test]
+    fn set_sorted() {
+        let set = SelectionSet::new(
+            [
+                Selection::at_pos(8),
+                Selection::at_pos(2),
+                Selection::at_pos(5),
+            ],
+            0,
+        );
+
+        assert_eq!(set.ranges().collect::<Vec<_>>(), [(2, 2), (5, 5), (8, 8)]);
+        assert_eq!(set.primary(), Selection::at_pos(8));
+        assert_eq!(set.primary_index(), 2);
+    }
+
+    #[test]
+    fn set_merge_on_overlap() {
+        let set = SelectionSet::new(
+            [
+                Selection::new(0, 3),
+                Selection::new(6, 4),
+                Selection::new(3, 5),
+                Selection::at_pos(9),
+            ],
+            2,
+        );
+
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.ranges().collect::<Vec<_>>(), [(0, 6), (9, 9)]);
+        assert_eq!(set.primary_index(), 0);
+
+        let mut set = SelectionSet::single(Selection::at_pos(4));
+        set.push(Selection::at_pos(4));
+
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn set_map() {
+        let mut text = ropey::Rope::from("0123456789");
+
+        let mut set = SelectionSet::new([Selection::at_pos(2), Selection::at_pos(7)], 1);
+
+        // insert before first cursor and remove text between cursors
+        let mut tx = Transaction::new(&text, 4);
+        tx.delete(2);
+        tx.move_to(0);
+        tx.insert("ab");
+        tx.apply(&mut text);
+
+        assert_eq!(text, "ab01236789");
+
+        set.map(&tx, Assoc::After);
+
+        assert_eq!(set.ranges().collect::<Vec<_>>(), [(4, 4), (7, 7)]);
+        assert_eq!(set.primary(), Selection::at_pos(7));
+    }
+
+    #[test]
+    fn set_keep_primary() {
+        let mut set = SelectionSet::new([Selection::at_pos(2), Selection::at_pos(7)], 1);
+        set.keep_primary();
+
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.primary(), Selection::at_pos(7));
+    }
 }
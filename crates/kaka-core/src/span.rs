@@ -13,105 +13,80 @@ pub struct Span {
 bitflags::bitflags! {
     pub struct SpanKind: u64 {
         const SELECTION = 1 << 0;
+        const SEARCH_MATCH = 1 << 1;
     }
 }
 
+/// Splits text into spans, each one of them covered by the same set of ranges
 pub struct SpanIterator<'a> {
-    done: bool,
     cursor: usize,
-    selection_idx: usize,
     rope: RopeSlice<'a>,
-    selections: Vec<(usize, usize)>,
+    ranges: Vec<(Range<usize>, SpanKind)>,
 }
 
 impl<'a> SpanIterator<'a> {
     pub fn new(rope: RopeSlice<'a>, selections: impl IntoIterator<Item = (usize, usize)>) -> Self {
-        let mut selections = selections.into_iter().collect::<Vec<_>>();
-        selections.sort_by_key(|(start, _end)| *start);
+        let ranges = selections
+            .into_iter()
+            // selections are inclusive
+            .map(|(start, end)| {
+                (
+                    start..next_grapheme_boundary(rope, end),
+                    SpanKind::SELECTION,
+                )
+            })
+            .collect();
 
         Self {
-            done: rope.len_chars() == 0,
-            rope,
             cursor: 0,
-            selection_idx: 0,
-            selections,
+            rope,
+            ranges,
         }
     }
+
+    /// Marks ranges matched by search, they may overlap selections
+    #[must_use]
+    pub fn with_search_matches(mut self, matches: impl IntoIterator<Item = Range<usize>>) -> Self {
+        self.ranges.extend(
+            matches
+                .into_iter()
+                .filter(|range| !range.is_empty())
+                .map(|range| (range, SpanKind::SEARCH_MATCH)),
+        );
+        self
+    }
 }
 
 impl<'a> Iterator for SpanIterator<'a> {
     type Item = Span;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.done {
-            return None;
-        }
+        let len = self.rope.len_chars();
 
-        // fast path - no selections
-        if self.selections.is_empty() {
-            self.done = true;
-
-            return Some(Span {
-                kind: SpanKind::empty(),
-                range: (0..self.rope.len_chars()),
-            });
+        if self.cursor >= len {
+            return None;
         }
 
-        let selection = &self.selections[self.selection_idx];
-
-        let start = selection.0;
-        // selections are inclusive
-        let end = next_grapheme_boundary(self.rope, selection.1);
-
-        let span = if self.cursor == start {
-            let old_cursor = self.cursor;
-
-            self.cursor = end;
-
-            Span {
-                kind: SpanKind::SELECTION,
-                range: (old_cursor..self.cursor),
+        let start = self.cursor;
+        let mut end = len;
+        let mut kind = SpanKind::empty();
+
+        // span ends where any range starts or ends
+        for (range, range_kind) in &self.ranges {
+            if range.contains(&start) {
+                kind |= *range_kind;
+                end = end.min(range.end);
+            } else if range.start > start {
+                end = end.min(range.start);
             }
-        } else if self.cursor == end {
-            // end of selection, move to next character
-            let start = self.cursor;
-
-            self.selection_idx += 1;
-
-            let end = if self.selection_idx == self.selections.len() {
-                // all selections exhausted
-                self.done = true;
-
-                self.rope.len_chars()
-            } else {
-                self.selections[self.selection_idx].0
-            };
-
-            self.cursor = end;
-
-            Span {
-                kind: SpanKind::empty(),
-                range: (start..end),
-            }
-        } else if self.cursor == 0 {
-            // we are at the beginning and *before* selection
+        }
 
-            self.cursor = start;
+        self.cursor = end;
 
-            Span {
-                kind: SpanKind::empty(),
-                range: (0..start),
-            }
-        } else {
-            unreachable!();
-        };
-
-        if span.range.is_empty() {
-            self.done = true;
-            None
-        } else {
-            Some(span)
-        }
+        Some(Span {
+            kind,
+            range: (start..end),
+        })
     }
 }
 
@@ -368,4 +343,71 @@ mod test {
 
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn adjacent_selections() {
+        let rope = Rope::from_str("0123456789");
+        let len = rope.len_chars();
+        let selection1 = Selection::new(1, 2);
+        let selection2 = Selection::new(3, 3);
+
+        let mut iter =
+            SpanIterator::new(rope.slice(..), [selection1, selection2].map(|s| s.range()));
+
+        assert_eq!(
+            iter.next(),
+            Some(Span {
+                kind: SpanKind::empty(),
+                range: (0..1)
+            })
+        );
+
+        assert_eq!(
+            iter.next(),
+            Some(Span {
+                kind: SpanKind::SELECTION,
+                range: (1..3)
+            })
+        );
+
+        assert_eq!(
+            iter.next(),
+            Some(Span {
+                kind: SpanKind::SELECTION,
+                range: (3..4)
+            })
+        );
+
+        assert_eq!(
+            iter.next(),
+            Some(Span {
+                kind: SpanKind::empty(),
+                range: (4..len)
+            })
+        );
+
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn search_match_overlapping_selection() {
+        let rope = Rope::from_str("0123456789");
+        let selection = Selection::new(2, 4);
+
+        let iter = SpanIterator::new(rope.slice(..), [selection].map(|s| s.range()))
+            .with_search_matches([4..7, 8..8]);
+
+        let spans = iter.map(|s| (s.kind, s.range)).collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            [
+                (SpanKind::empty(), 0..2),
+                (SpanKind::SELECTION, 2..4),
+                (SpanKind::SELECTION | SpanKind::SEARCH_MATCH, 4..5),
+                (SpanKind::SEARCH_MATCH, 5..7),
+                (SpanKind::empty(), 7..10),
+            ]
+        );
+    }
 }
@@ -0,0 +1,157 @@
+//! Replacing regex matches inside a line, backs `:s`
+use std::ops::Range;
+
+use regex_automata::meta;
+
+use crate::search::InvalidPattern;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Group(usize),
+}
+
+/// Pattern with replacement template
+///
+/// Template may refer to capture groups with `\0`..`\9`, `&` is the whole match. `\n` and
+/// `\r` insert line break, `\t` inserts tab, any other escaped character is taken literally.
+#[derive(Debug)]
+pub struct Substitution {
+    regex: meta::Regex,
+    replacement: Vec<Piece>,
+}
+
+impl Substitution {
+    pub fn new(pattern: &str, replacement: &str) -> Result<Self, InvalidPattern> {
+        let regex = meta::Regex::new(pattern).map_err(|err| InvalidPattern(err.to_string()))?;
+
+        Ok(Self {
+            regex,
+            replacement: parse_replacement(replacement),
+        })
+    }
+
+    /// Matches in `line` paired with their replacements, ranges are char offsets in `line`
+    ///
+    /// Only the first match is replaced unless `global` is set.
+    pub fn replacements(&self, line: &str, global: bool) -> Vec<(Range<usize>, String)> {
+        let mut replacements = vec![];
+
+        // (byte, char) of the last converted offset
+        let mut offset = (0, 0);
+        let mut to_char = |byte: usize| {
+            offset.1 += line[offset.0..byte].chars().count();
+            offset.0 = byte;
+            offset.1
+        };
+
+        for caps in self.regex.captures_iter(line) {
+            let Some(span) = caps.get_group(0) else {
+                continue;
+            };
+
+            let mut replaced = String::new();
+            for piece in &self.replacement {
+                match piece {
+                    Piece::Literal(s) => replaced.push_str(s),
+                    Piece::Group(idx) => {
+                        if let Some(group) = caps.get_group(*idx) {
+                            replaced.push_str(&line[group.range()]);
+                        }
+                    }
+                }
+            }
+
+            let start = to_char(span.start);
+            let end = to_char(span.end);
+            replacements.push((start..end, replaced));
+
+            if !global {
+                break;
+            }
+        }
+
+        replacements
+    }
+}
+
+fn parse_replacement(replacement: &str) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut literal = String::new();
+    let mut chars = replacement.chars();
+
+    let group = |pieces: &mut Vec<Piece>, literal: &mut String, idx| {
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(std::mem::take(literal)));
+        }
+        pieces.push(Piece::Group(idx));
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => group(&mut pieces, &mut literal, 0),
+            '\\' => match chars.next() {
+                Some(d @ '0'..='9') => {
+                    group(&mut pieces, &mut literal, d as usize - '0' as usize);
+                }
+                Some('n' | 'r') => literal.push('\n'),
+                Some('t') => literal.push('\t'),
+                Some(c) => literal.push(c),
+                None => literal.push('\\'),
+            },
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn replace(pattern: &str, replacement: &str, line: &str, global: bool) -> String {
+        let substitution = Substitution::new(pattern, replacement).unwrap();
+        let mut line = line.chars().collect::<Vec<_>>();
+
+        let text = line.iter().collect::<String>();
+        for (range, replaced) in substitution.replacements(&text, global).into_iter().rev() {
+            line.splice(range, replaced.chars());
+        }
+
+        line.into_iter().collect()
+    }
+
+    #[test]
+    fn replace_first_or_all() {
+        assert_eq!(replace("o", "0", "foo boo", false), "f0o boo");
+        assert_eq!(replace("o", "0", "foo boo", true), "f00 b00");
+        assert_eq!(replace("x", "0", "foo", true), "foo");
+    }
+
+    #[test]
+    fn capture_groups() {
+        assert_eq!(
+            replace(r"(\w+)=(\w+)", r"\2=\1", "a=b c=d", true),
+            "b=a d=c"
+        );
+        assert_eq!(replace("b+", "[&]", "abbc", false), "a[bb]c");
+        assert_eq!(replace("b", r"\&\\", "abc", false), r"a&\c");
+        assert_eq!(replace(" ", r"\n", "a b", false), "a\nb");
+    }
+
+    #[test]
+    fn char_offsets() {
+        let substitution = Substitution::new("ż", "z").unwrap();
+
+        assert_eq!(
+            substitution.replacements("ąż ż", true),
+            [(1..2, "z".to_string()), (3..4, "z".to_string())]
+        );
+        assert_eq!(replace("ż", "z", "ąż ż", true), "ąz z");
+    }
+}
@@ -1,10 +1,12 @@
-use std::{borrow::Cow, cmp::Ordering, num::NonZeroUsize};
+use std::{borrow::Cow, cmp::Ordering, num::NonZeroUsize, ops::Range};
 
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
-use crate::{graphemes::nth_next_grapheme_boundary, SmartString};
+use crate::SmartString;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Change {
     /// Move forward by offset
     MoveForward(usize),
@@ -12,11 +14,22 @@ pub enum Change {
     /// Insert string
     Insert(SmartString),
 
-    /// Delete
+    /// Delete chars, not graphemes, so that positions can be mapped and changes reverted
+    /// without looking at the text
     Delete(usize),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Describes which side of text inserted exactly at mapped position the position sticks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// Stay in front of inserted text
+    Before,
+
+    /// Move past inserted text
+    After,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
     repeat: NonZeroUsize,
     len_before: usize,
@@ -36,24 +49,29 @@ impl Transaction {
         }
     }
 
-    pub fn merge(&mut self, mut other: Self) {
-        let head = self.changeset_head();
-        let other_head = other.changesets.remove(other.changesets.len() - 1);
+    pub fn merge(&mut self, other: Self) {
+        let mut changesets = other.changesets.into_iter();
+
+        if let Some(first) = changesets.next() {
+            let head = self.changeset_head();
 
-        match other_head.start_pos.cmp(&head.start_pos) {
-            Ordering::Equal => head.changes.extend(other_head.changes),
-            Ordering::Less | Ordering::Greater => self.changesets.push(other_head),
+            // continue head changeset if other starts where head ends
+            if first.start_pos == head.end_pos {
+                head.changes.extend(first.changes);
+                head.end_pos = first.end_pos;
+            } else {
+                self.changesets.push(first);
+            }
         }
 
-        self.changesets.extend(other.changesets);
+        self.changesets.extend(changesets);
         self.len_after = other.len_after;
     }
 
-    pub fn replace(&mut self, ch: char) {
-        self.delete(1);
-        let mut buf = [0; 4];
-        let string = ch.encode_utf8(&mut buf);
-        self.insert(string);
+    /// Replaces `len` chars at position, grapheme may span several of them
+    pub fn replace(&mut self, len: usize, replacement: impl Into<SmartString>) {
+        self.delete(len);
+        self.insert(replacement);
     }
 
     pub fn insert_char(&mut self, c: char) {
@@ -101,8 +119,10 @@ impl Transaction {
         }
     }
 
+    /// Deletes `len` chars, callers deleting graphemes find their boundaries first
     pub fn delete(&mut self, len: usize) {
         self.changeset_head().delete(len);
+        self.len_after = self.len_after.saturating_sub(len);
     }
 
     pub fn set_repeat(&mut self, repeat: usize) {
@@ -142,10 +162,17 @@ impl Transaction {
     pub fn undo(&self, original: &Rope) -> Self {
         debug_assert_eq!(original.len_chars(), self.len_before);
 
+        // every changeset has to be reverted against the text it was applied to
+        let mut text = original.clone();
+
         let mut revert = self
             .changesets
             .iter()
-            .map(|c| c.undo(original))
+            .map(|c| {
+                let undo = c.undo(&text);
+                c.apply(0, &mut text);
+                undo
+            })
             .collect::<Vec<_>>();
 
         revert.reverse();
@@ -153,11 +180,44 @@ impl Transaction {
         Self {
             repeat: self.repeat,
             len_before: self.len_after,
-            len_after: self.len_after,
+            len_after: self.len_before,
             changesets: revert,
         }
     }
 
+    /// Maps char position in text before applying the transaction to position in text after.
+    ///
+    /// Position inside of deleted text is moved to the place of deletion.
+    pub fn map_pos(&self, mut pos: usize, assoc: Assoc) -> usize {
+        let mut offset = None;
+        let pos1 = self.changesets[0].start_pos;
+
+        for _ in 0..self.repeat.get() {
+            let mut end = 0;
+
+            for change_set in self.changesets.iter() {
+                pos = change_set.map_pos_impl(offset.unwrap_or(0), pos, assoc);
+                end = change_set.end_pos as isize + offset.unwrap_or(0);
+            }
+
+            if offset.is_none() {
+                offset = Some(end - pos1 as isize);
+            }
+        }
+
+        pos
+    }
+
+    /// Maps char range through the transaction.
+    ///
+    /// Text inserted at range boundaries stays outside of mapped range.
+    pub fn map_range(&self, range: Range<usize>) -> Range<usize> {
+        let start = self.map_pos(range.start, Assoc::After);
+        let end = self.map_pos(range.end, Assoc::Before).max(start);
+
+        start..end
+    }
+
     pub fn changes_text(&self) -> bool {
         self.changesets.iter().any(|ch| ch.changes_text())
     }
@@ -169,7 +229,7 @@ impl Transaction {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChangeSet {
     start_pos: usize,
     end_pos: usize,
@@ -242,8 +302,7 @@ impl ChangeSet {
                     pos += content.chars().count();
                 }
                 Change::Delete(len) => {
-                    let range_end = nth_next_grapheme_boundary(rope.slice(..), pos, *len);
-                    rope.remove(pos..range_end);
+                    rope.remove(pos..pos + *len);
                 }
             }
         }
@@ -251,6 +310,53 @@ impl ChangeSet {
         pos
     }
 
+    /// Maps char position in text before applying the changeset to position in text after
+    pub fn map_pos(&self, pos: usize, assoc: Assoc) -> usize {
+        self.map_pos_impl(0, pos, assoc)
+    }
+
+    fn map_pos_impl(&self, offset: isize, mut pos: usize, assoc: Assoc) -> usize {
+        let start_pos = (offset + self.start_pos as isize) as usize;
+
+        if pos < start_pos {
+            return pos;
+        }
+
+        // `old` walks text before change, `new` walks text after change
+        let mut old = start_pos;
+        let mut new = start_pos;
+
+        for change in self.changes.iter() {
+            match change {
+                Change::MoveForward(count) => {
+                    if pos < old + count {
+                        return new + (pos - old);
+                    }
+
+                    old += count;
+                    new += count;
+                }
+                Change::Insert(content) => {
+                    if pos == old && assoc == Assoc::Before {
+                        return new;
+                    }
+
+                    new += content.chars().count();
+                }
+                Change::Delete(len) => {
+                    // deleted position lands where deletion happened
+                    if pos < old + len {
+                        pos = old + len;
+                    }
+
+                    old += len;
+                }
+            }
+        }
+
+        new + (pos - old)
+    }
+
     fn changes_text(&self) -> bool {
         self.changes
             .iter()
@@ -278,10 +384,8 @@ impl ChangeSet {
                     revert.delete(len);
                 }
                 Delete(len) => {
-                    let text = original.slice(..);
                     let pos = revert.end_pos;
-                    let range_end = nth_next_grapheme_boundary(text, pos, *len);
-                    let text = Cow::from(text.slice(pos..range_end));
+                    let text = Cow::from(original.slice(pos..pos + len));
                     revert.insert(text.into());
                 }
             };
@@ -299,10 +403,17 @@ mod test {
     fn replace() {
         let mut text = Rope::from("hello tx");
         let mut tx = Transaction::new(&text, 0);
-        tx.replace('a');
+        tx.replace(1, "a");
         tx.apply(&mut text);
 
         assert_eq!(text, "aello tx");
+
+        let mut text = Rope::from("e\u{301}x");
+        let mut tx = Transaction::new(&text, 0);
+        tx.replace(2, "a");
+        tx.apply(&mut text);
+
+        assert_eq!(text, "ax");
     }
 
     #[test]
@@ -320,6 +431,22 @@ mod test {
         assert_eq!(text, "lo tx");
     }
 
+    #[test]
+    fn delete_counts_chars() {
+        let original = Rope::from("e\u{301}x");
+        let mut text = original.clone();
+
+        let mut tx = Transaction::new(&text, 0);
+        tx.delete(1);
+        tx.apply(&mut text);
+
+        assert_eq!(text, "\u{301}x", "only the base char is deleted");
+        assert_eq!(tx.map_pos(2, Assoc::Before), 1);
+
+        tx.undo(&original).apply(&mut text);
+        assert_eq!(text, original);
+    }
+
     #[test]
     fn undo() {
         let original_text = Rope::from("hello tx");
@@ -366,4 +493,102 @@ mod test {
 
         assert_eq!(text, "");
     }
+
+    #[test]
+    fn map_pos_insert() {
+        let text = Rope::from("0123456789");
+        let mut tx = Transaction::new(&text, 3);
+        tx.insert("ab");
+
+        assert_eq!(tx.map_pos(0, Assoc::Before), 0);
+        assert_eq!(tx.map_pos(2, Assoc::After), 2);
+        assert_eq!(tx.map_pos(3, Assoc::Before), 3);
+        assert_eq!(tx.map_pos(3, Assoc::After), 5);
+        assert_eq!(tx.map_pos(4, Assoc::Before), 6);
+        assert_eq!(tx.map_pos(10, Assoc::Before), 12);
+    }
+
+    #[test]
+    fn map_pos_delete() {
+        let text = Rope::from("0123456789");
+        let mut tx = Transaction::new(&text, 2);
+        tx.delete(3);
+
+        assert_eq!(tx.map_pos(1, Assoc::After), 1);
+        assert_eq!(tx.map_pos(2, Assoc::After), 2);
+        assert_eq!(tx.map_pos(4, Assoc::Before), 2);
+        assert_eq!(tx.map_pos(5, Assoc::Before), 2);
+        assert_eq!(tx.map_pos(6, Assoc::After), 3);
+    }
+
+    #[test]
+    fn map_pos_replace() {
+        let mut text = Rope::from("0123456789");
+        let mut tx = Transaction::new(&text, 2);
+        tx.delete(3);
+        tx.insert("abcd");
+        tx.apply(&mut text);
+
+        assert_eq!(text, "01abcd56789");
+
+        // position from replaced text sticks to chosen side of replacement
+        assert_eq!(tx.map_pos(3, Assoc::Before), 2);
+        assert_eq!(tx.map_pos(3, Assoc::After), 6);
+        assert_eq!(tx.map_pos(5, Assoc::Before), 2);
+        assert_eq!(tx.map_pos(5, Assoc::After), 6);
+        assert_eq!(tx.map_pos(6, Assoc::Before), 7);
+    }
+
+    #[test]
+    fn map_pos_many_changesets() {
+        let mut text = Rope::from("0123456789");
+        let mut tx = Transaction::new(&text, 8);
+        tx.insert("x");
+        tx.move_to(1);
+        tx.delete(2);
+        tx.move_forward_by(2);
+        tx.insert("yy");
+        tx.apply(&mut text);
+
+        assert_eq!(text, "034yy567x89");
+
+        let mapped = (0..=10)
+            .map(|pos| tx.map_pos(pos, Assoc::After))
+            .collect::<Vec<_>>();
+
+        assert_eq!(mapped, [0, 1, 1, 1, 2, 5, 6, 7, 9, 10, 11]);
+    }
+
+    #[test]
+    fn map_pos_repeat() {
+        let mut text = Rope::from("0123");
+        let mut tx = Transaction::new(&text, 1);
+        tx.insert("ab");
+        tx.set_repeat(3);
+        tx.apply(&mut text);
+
+        assert_eq!(text, "0ababab123");
+
+        assert_eq!(tx.map_pos(1, Assoc::Before), 1);
+        assert_eq!(tx.map_pos(1, Assoc::After), 7);
+        assert_eq!(tx.map_pos(2, Assoc::After), 8);
+    }
+
+    #[test]
+    fn map_range() {
+        let text = Rope::from("0123456789");
+        let mut tx = Transaction::new(&text, 2);
+        tx.insert("ab");
+        tx.move_forward_by(2);
+        tx.insert("cd");
+
+        // insertions at boundaries do not extend the range
+        assert_eq!(tx.map_range(2..4), 4..6);
+
+        let mut tx = Transaction::new(&text, 3);
+        tx.delete(4);
+
+        assert_eq!(tx.map_range(1..5), 1..3);
+        assert_eq!(tx.map_range(4..6), 3..3);
+    }
 }
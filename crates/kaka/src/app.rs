@@ -1,18 +1,29 @@
 use std::io;
+use std::time::Duration;
 
-use crate::client::composer::EditorWidget;
+use crate::client::composer::{ConfirmWidget, EditorWidget, StatusLineWidget};
 use crate::client::Redraw;
 use crate::{
-    editor::{Buffer, Editor},
+    editor::{
+        default_recovery_dir, default_undo_dir, recovery_prompt, reload_prompt,
+        user_languages_file, Buffer, Clipboard, Editor, Watcher,
+    },
     logger, Canvas,
 };
 use crossterm::event::Event;
 use futures_util::{Stream, StreamExt};
-use kaka_core::{document::Document, ropey::Rope};
-use tokio::sync::mpsc;
+use kaka_core::{document::Document, languages::Languages, ropey::Rope};
+use tokio::{sync::mpsc, time::Instant};
 
 use crate::Client;
 
+/// How often journals of unsaved changes are brought up to date, files changed on disk are
+/// looked for as well in case the watcher missed them
+const JOURNAL_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Files are checked once they are quiet for this long, not in the middle of being written
+const FILE_CHECK_DELAY: Duration = Duration::from_millis(100);
+
 pub struct App<C> {
     client: Client<C>,
     editor: Editor,
@@ -20,10 +31,12 @@ pub struct App<C> {
 
 impl<C: Canvas> App<C> {
     pub fn new(client: Client<C>) -> Self {
-        Self {
-            client,
-            editor: Editor::init(),
-        }
+        let mut editor = Editor::init();
+        editor.undo_dir = default_undo_dir();
+        editor.recovery_dir = default_recovery_dir();
+        editor.registers.set_clipboard(Clipboard::detect());
+
+        Self { client, editor }
     }
 
     pub async fn run<
@@ -48,6 +61,22 @@ impl<C: Canvas> App<C> {
 
         logger::enable(log_tx);
 
+        let (fs_tx, mut fs_rx) = mpsc::unbounded_channel();
+
+        match Watcher::new(move |event| {
+            let _ = fs_tx.send(event);
+        }) {
+            Ok(watcher) => self.editor.watcher = Some(watcher),
+            Err(err) => log::warn!("Files changed on disk are noticed only periodically: {err}"),
+        }
+
+        if let Some(path) = user_languages_file().filter(|path| path.exists()) {
+            match Languages::from_yaml(&path) {
+                Ok(languages) => self.editor.languages.extend(languages),
+                Err(err) => log::error!("Can't read {}: {err}", path.display()),
+            }
+        }
+
         // open paths from argv
         let mut opened = 0;
         let mut failed = 0;
@@ -75,9 +104,20 @@ impl<C: Canvas> App<C> {
         self.client
             .composer_mut()
             .push_widget(EditorWidget::default());
+        self.client.composer_mut().push_widget(StatusLineWidget);
+
+        if let Some(prompt) = recovery_prompt(&mut self.editor) {
+            self.client.composer_mut().push_widget(prompt);
+        }
 
         self.render()?;
 
+        let mut journal_timer = tokio::time::interval(JOURNAL_INTERVAL);
+
+        let file_check = tokio::time::sleep(FILE_CHECK_DELAY);
+        tokio::pin!(file_check);
+        let mut file_check_pending = false;
+
         // enter event loop
         loop {
             let should_redraw = tokio::select! {
@@ -87,6 +127,23 @@ impl<C: Canvas> App<C> {
                 Some(log) = log_rx.recv() => {
                     self.on_log(log)
                 }
+                Some(event) = fs_rx.recv() => {
+                    if let Err(err) = event {
+                        log::warn!("Watching files: {err}");
+                    }
+
+                    file_check.as_mut().reset(Instant::now() + FILE_CHECK_DELAY);
+                    file_check_pending = true;
+                    Redraw(false)
+                }
+                () = &mut file_check, if file_check_pending => {
+                    file_check_pending = false;
+                    self.check_files()
+                }
+                _ = journal_timer.tick() => {
+                    self.editor.write_journals();
+                    self.check_files()
+                }
             };
 
             let exit = self.editor.should_exit();
@@ -102,6 +159,8 @@ impl<C: Canvas> App<C> {
             }
         }
 
+        self.editor.remove_journals();
+
         Ok(())
     }
 
@@ -113,6 +172,21 @@ impl<C: Canvas> App<C> {
         self.editor.on_log(log)
     }
 
+    fn check_files(&mut self) -> Redraw {
+        let reloaded = self.editor.check_files();
+
+        // queued conflicts are asked about once the prompt being shown is answered
+        let composer = self.client.composer_mut();
+        if !composer.has_widget::<ConfirmWidget>() {
+            if let Some(prompt) = reload_prompt(&mut self.editor) {
+                composer.push_widget(prompt);
+                return Redraw(true);
+            }
+        }
+
+        Redraw(reloaded)
+    }
+
     fn render(&mut self) -> anyhow::Result<()> {
         self.client.render(&mut self.editor)
     }
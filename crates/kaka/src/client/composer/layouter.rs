@@ -2,12 +2,21 @@ use kaka_core::shapes::Rect;
 
 pub const fn editor(viewport: Rect) -> Rect {
     Rect {
-        height: viewport.height - 1,
+        height: viewport.height.saturating_sub(2),
         y: 0,
         ..viewport
     }
 }
 
+pub const fn status_line(viewport: Rect) -> Rect {
+    Rect {
+        x: 0,
+        y: viewport.height.saturating_sub(2),
+        height: 1,
+        width: viewport.width,
+    }
+}
+
 pub const fn prompt(viewport: Rect) -> Rect {
     Rect {
         x: 0,
@@ -5,13 +5,15 @@ use std::{any::TypeId, fmt};
 
 use crossterm::event::Event;
 
+pub use widget::ConfirmWidget;
 pub use widget::EditorWidget;
 pub use widget::PromptWidget;
+pub use widget::StatusLineWidget;
 
 use kaka_core::shapes::{Point, Rect};
 
-use crate::editor::CommandData;
 use crate::editor::Editor;
+use crate::editor::{try_substitute, Args, CommandData};
 
 pub use self::widget::Widget;
 
@@ -24,16 +26,38 @@ pub struct Context<'a> {
 }
 
 impl<'a> Context<'a> {
-    pub fn invoke_command_by_name(&mut self, name: &str) {
-        if let Some(command) = self.editor.command_registry.typable_command_by_name(name) {
-            let mut ctx = CommandData {
-                editor: self.editor,
-                count: None,
-                callback: None,
-            };
-
-            command.call(&mut ctx);
+    /// Executes line typed in command mode, returned callback may push command's widget
+    pub fn execute_command_line(&mut self, line: &str) -> Option<Callback> {
+        let mut ctx = CommandData {
+            editor: self.editor,
+            count: None,
+            register: None,
+            key_argument: None,
+            args: Args::default(),
+            callback: None,
+        };
+
+        if try_substitute(&mut ctx, line) {
+            return ctx.callback;
         }
+
+        let line = line.trim_start();
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        let Some(command) = ctx.editor.command_registry.typable_command_by_name(name) else {
+            log::error!("Unknown command: {name}");
+            return None;
+        };
+
+        match command.signature().parse(args) {
+            Ok(args) => {
+                ctx.args = args;
+                command.call(&mut ctx);
+            }
+            Err(err) => log::error!(":{name}: {err}"),
+        }
+
+        ctx.callback
     }
 }
 
@@ -113,7 +137,30 @@ impl Composer {
         Ok(())
     }
 
+    /// Passes event to widgets, keys of macros it started are handled after it as if they were
+    /// typed
     pub fn handle_event(&mut self, event: Event, ctx: &mut Context) -> Redraw {
+        let recording = ctx.editor.macros.recording().is_some();
+        let key = match event {
+            Event::Key(key) => Some(key),
+            _ => None,
+        };
+
+        let mut redraw = self.dispatch_event(event, ctx);
+
+        // keys starting and stopping the recording are left out
+        if let Some(key) = key.filter(|_| recording && ctx.editor.macros.recording().is_some()) {
+            ctx.editor.macros.record(key);
+        }
+
+        while let Some(key) = ctx.editor.macros.next_key() {
+            redraw.0 |= self.dispatch_event(Event::Key(key), ctx).0;
+        }
+
+        redraw
+    }
+
+    fn dispatch_event(&mut self, event: Event, ctx: &mut Context) -> Redraw {
         let resized = if let Event::Resize(x, y) = event {
             let viewport = Rect::new(0, 0, x, y);
 
@@ -158,6 +205,12 @@ impl Composer {
             .push((TypeId::of::<W>(), (Box::new(widget), area)));
     }
 
+    pub fn has_widget<W: Widget>(&self) -> bool {
+        self.widgets
+            .iter()
+            .any(|(type_id, _)| *type_id == TypeId::of::<W>())
+    }
+
     pub fn remove_widget<W: Widget>(&mut self) {
         self.widgets
             .retain(|(type_id, _)| *type_id != TypeId::of::<W>());
@@ -179,7 +232,7 @@ impl Surfaces {
         }
     }
 
-    pub fn surface_mut(&mut self) -> &mut Surface {
+    pub const fn surface_mut(&mut self) -> &mut Surface {
         &mut self.surfaces[self.current_surface]
     }
 
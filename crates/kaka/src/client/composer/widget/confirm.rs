@@ -0,0 +1,74 @@
+use crossterm::event::{Event, KeyCode};
+use kaka_core::shapes::{Point, Rect};
+use unicode_width::UnicodeWidthStr;
+
+use crate::client::{
+    composer::{layouter, Cursor},
+    style::{Color, CursorKind, Style},
+    surface::Surface,
+};
+
+use super::{Context, EventOutcome, Widget};
+
+/// Returns next question, or `None` when there is nothing more to ask
+pub type OnAnswerCallback = Box<dyn FnMut(KeyCode, &mut Context) -> Option<String>>;
+
+/// Asks a question answered with single key, like `:s///c` does for every match
+pub struct ConfirmWidget {
+    question: String,
+    on_answer: OnAnswerCallback,
+    cursor: Cursor,
+}
+
+impl ConfirmWidget {
+    pub fn new(
+        question: impl Into<String>,
+        on_answer: impl FnMut(KeyCode, &mut Context) -> Option<String> + 'static,
+    ) -> Self {
+        Self {
+            question: question.into(),
+            on_answer: Box::new(on_answer),
+            cursor: Cursor(Point::new(0, 0), CursorKind::Line),
+        }
+    }
+}
+
+impl Widget for ConfirmWidget {
+    fn draw(&self, area: Rect, surface: &mut Surface, _ctx: &Context<'_>) {
+        surface.set_stringn(
+            Point::new(area.x, area.y),
+            &self.question,
+            area.width as usize,
+            Style::default().fg(Color::Red),
+        );
+    }
+
+    fn handle_event(&mut self, event: &Event, ctx: &mut Context) -> EventOutcome {
+        let answer = match event {
+            Event::Key(k) => k.code,
+            _ => return EventOutcome::ignored(),
+        };
+
+        match (self.on_answer)(answer, ctx) {
+            Some(question) => {
+                self.question = question;
+                EventOutcome::consumed()
+            }
+            None => EventOutcome::consumed().callback(|c| c.remove_widget::<Self>()),
+        }
+    }
+
+    fn area(&self, viewport: Rect) -> Rect {
+        layouter::prompt(viewport)
+    }
+
+    fn cursor(&self) -> Option<Cursor> {
+        Some(self.cursor)
+    }
+
+    fn update_state(&mut self, area: Rect, _context: &mut Context) {
+        let width = self.question.width().min(area.width as usize) as u16;
+
+        self.cursor = Cursor(Point::new(width + area.x, area.y), CursorKind::Line);
+    }
+}
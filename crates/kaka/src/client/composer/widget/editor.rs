@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use crossterm::event::{Event, KeyCode, KeyEvent};
 use kaka_core::{
+    graphemes::{char_width, column},
     shapes::{Point, Rect},
     span::{SpanIterator, SpanKind},
 };
@@ -14,12 +15,23 @@ use crate::{
         surface::Surface,
     },
     current, current_mut,
-    editor::{self, insert_mode_on_key, Buffer, Command, KeymapTreeElement, Keymaps},
+    editor::{
+        self, apply_pending_operator, insert_mode_on_key, replace_mode_on_key, resume_insert_mode,
+        switch_to_normal_mode, Buffer, Command, Editor, Inserted, Invocation, KeymapTreeElement,
+        Keymaps, LastChange, ModeKind, Registers, VisualSize,
+    },
 };
 
+const REGISTER_PREFIX: char = '"';
+
 pub struct EditorWidget {
     buffered_keys: Vec<KeyEvent>,
     count: Option<usize>,
+    /// Register selected with `"` for the next command
+    register: Option<char>,
+    awaiting_register: bool,
+    /// Change being typed, waiting for motion or end of insertion
+    change: Option<LastChange>,
     insert_on: bool,
     cursor: Cursor,
 }
@@ -29,6 +41,9 @@ impl Default for EditorWidget {
         Self {
             buffered_keys: vec![],
             count: None,
+            register: None,
+            awaiting_register: false,
+            change: None,
             insert_on: false,
             cursor: Cursor(Point::new(0, 0), CursorKind::Block),
         }
@@ -38,9 +53,40 @@ impl Default for EditorWidget {
 impl EditorWidget {
     fn reset(&mut self) {
         self.count = None;
+        self.register = None;
+        self.awaiting_register = false;
         self.buffered_keys.clear();
     }
 
+    /// `"` followed by register name selects register for the next command, returns whether
+    /// the key was taken
+    fn update_register(&mut self, buffer: &Buffer, event: KeyEvent) -> bool {
+        let selecting = (buffer.mode() == ModeKind::Normal || buffer.mode().is_visual())
+            && self.buffered_keys.is_empty();
+
+        if !selecting {
+            return false;
+        }
+
+        if self.awaiting_register {
+            self.awaiting_register = false;
+
+            match event.code {
+                KeyCode::Char(name) if Registers::is_valid(name) => self.register = Some(name),
+                _ => self.reset(),
+            }
+
+            return true;
+        }
+
+        if event.code == KeyCode::Char(REGISTER_PREFIX) {
+            self.awaiting_register = true;
+            return true;
+        }
+
+        false
+    }
+
     fn update_count(&mut self, event: KeyEvent) {
         if self.insert_on {
             return;
@@ -74,12 +120,63 @@ impl EditorWidget {
         }
     }
 
+    /// Keeps change being typed, it's stored for `.` once it's complete
+    ///
+    /// Change made in visual mode keeps `visual` size of the selection.
+    fn record_change(
+        &mut self,
+        editor: &mut Editor,
+        invocation: Invocation,
+        mode: ModeKind,
+        visual: Option<VisualSize>,
+    ) {
+        let current_mode = current!(editor).0.mode();
+
+        match mode {
+            ModeKind::Normal if invocation.command.repeatable() => {
+                self.change = Some(LastChange::new(invocation, self.register));
+            }
+            _ if visual.is_some() => {
+                let mut change = LastChange::new(invocation, self.register);
+                change.visual = visual;
+                self.change = Some(change);
+            }
+            ModeKind::OperatorPending => match self.change.as_mut() {
+                Some(change) if invocation.command.motion_kind().is_some() => {
+                    change.motion = Some(invocation);
+                }
+                _ => self.change = None,
+            },
+            // command mapped in insert mode, the one leaving it is not replayed
+            ModeKind::Insert | ModeKind::Replace if current_mode == mode => {
+                if let Some(change) = self.change.as_mut() {
+                    change.inserted.push(Inserted::Command(invocation));
+                }
+            }
+            _ => (),
+        }
+
+        let Some(change) = self.change.take() else {
+            return;
+        };
+
+        match current_mode {
+            ModeKind::Normal => editor.last_change = Some(change),
+            ModeKind::Insert | ModeKind::Replace | ModeKind::OperatorPending => {
+                self.change = Some(change);
+            }
+            ModeKind::Visual | ModeKind::VisualLine | ModeKind::VisualBlock => (),
+        }
+    }
+
+    /// Returns command with key argument if command is awaiting one
     fn find_command(
         &mut self,
         keymaps: &Keymaps,
         buffer: &Buffer,
         event: KeyEvent,
-    ) -> Option<Arc<Command>> {
+        recording: bool,
+    ) -> Option<(Arc<Command>, Option<KeyEvent>)> {
         if self.insert_on {
             return None;
         }
@@ -93,34 +190,44 @@ impl EditorWidget {
             )
         };
 
-        let mut keymap_element = match keymap_element {
-            Some(ke) => ke,
-            None => return None,
-        };
+        let mut keymap_element = keymap_element?;
 
         for buf_key in self.buffered_keys.iter().skip(1) {
             keymap_element = match keymap_element {
                 KeymapTreeElement::Node(k) => k.feed(*buf_key).unwrap(),
                 // keys are buffered...
-                KeymapTreeElement::Leaf(_) => unreachable!(),
+                _ => unreachable!(),
             };
         }
 
         let mut call = None;
         match keymap_element {
+            // buffered keys lead to command, this key is its argument
+            KeymapTreeElement::Argument(command) if chain => {
+                call = Some((Arc::clone(command), Some(event)));
+                self.buffered_keys.clear();
+            }
             KeymapTreeElement::Node(n) if chain => match n.feed(event) {
                 Some(KeymapTreeElement::Leaf(command)) => {
-                    call = Some(command.clone());
+                    call = Some((Arc::clone(command), None));
                     self.buffered_keys.clear();
                 }
                 // ...here
-                Some(KeymapTreeElement::Node(_)) => self.buffered_keys.push(event),
+                Some(_) => self.buffered_keys.push(event),
                 None => self.reset(),
             },
+            // key starting recording of macro stops it, no register is awaited
+            KeymapTreeElement::Argument(command)
+                if recording && command.name() == "record_macro" =>
+            {
+                call = Some((Arc::clone(command), None));
+            }
             // ...and here
-            KeymapTreeElement::Node(_) => self.buffered_keys.push(event),
+            KeymapTreeElement::Node(_) | KeymapTreeElement::Argument(_) => {
+                self.buffered_keys.push(event);
+            }
             KeymapTreeElement::Leaf(command) => {
-                call = Some(Arc::clone(command));
+                call = Some((Arc::clone(command), None));
                 self.buffered_keys.clear();
             }
         }
@@ -142,7 +249,33 @@ impl Widget for EditorWidget {
 
         let vscroll = buf.vscroll();
 
-        let selection_range = buf.selection().map(|s| s.range());
+        let selections = buf.selections();
+        let selection_ranges = if buf.mode().is_visual() {
+            buf.visual_ranges(doc)
+                .into_iter()
+                .filter(|range| !range.is_empty())
+                .map(|range| (range.start, range.end - 1))
+                .collect::<Vec<_>>()
+        } else {
+            // primary cursor is drawn by the terminal, mark only secondary ones
+            selections
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| *idx != selections.primary_index())
+                .map(|(_, s)| (s.head(), s.head()))
+                .collect()
+        };
+
+        // highlight matches of the last search in visible lines only
+        let last_line = (vscroll + max_y).min(text.len_lines());
+        let visible = text.line_to_char(vscroll)..text.line_to_char(last_line);
+        let search_matches = ctx
+            .editor
+            .last_search
+            .as_ref()
+            .map_or_else(Vec::new, |search| {
+                search.regex.find_iter(text.slice(..), visible).collect()
+            });
 
         let style = Style::default().fg(Color::Yellow).bg(Color::Black);
 
@@ -152,7 +285,7 @@ impl Widget for EditorWidget {
             let line_char = text.line_to_char(line_idx);
             let max_len = (area.width as usize).min(line.len_chars());
 
-            let selection_range = selection_range.and_then(|(start, end)| {
+            let line_selections = selection_ranges.iter().filter_map(|&(start, end)| {
                 let overlaps = start <= line_char + max_len && line_char <= end;
 
                 let start_in_line = start.saturating_sub(line_char).min(max_len);
@@ -161,22 +294,54 @@ impl Widget for EditorWidget {
                 (start_in_line != end_in_line || overlaps).then_some((start_in_line, end_in_line))
             });
 
-            SpanIterator::new(line, selection_range).for_each(|span| {
-                let style = if span.kind.contains(SpanKind::SELECTION) {
-                    style.bg(Color::Gray)
-                } else {
-                    style
-                };
-
-                let range = span.range;
-
-                surface.set_stringn(
-                    Point::new(area.x + range.start as u16, y as u16),
-                    &line.slice(range).to_string(),
-                    max_len,
-                    style,
-                );
-            });
+            let line_matches = search_matches
+                .iter()
+                .filter(|m| m.start < line_char + max_len && line_char < m.end)
+                .map(|m| {
+                    m.start.saturating_sub(line_char).min(max_len)..(m.end - line_char).min(max_len)
+                });
+
+            SpanIterator::new(line, line_selections)
+                .with_search_matches(line_matches)
+                .for_each(|span| {
+                    let mut style = style;
+
+                    if span.kind.contains(SpanKind::SEARCH_MATCH) {
+                        style = style.fg(Color::Black).bg(Color::Yellow);
+                    }
+
+                    if span.kind.contains(SpanKind::SELECTION) {
+                        style = style.bg(Color::Gray);
+                    }
+
+                    let range = span.range;
+
+                    // tabs are drawn as spaces reaching the next tab stop
+                    let x = column(line, range.start);
+                    if x >= area.width as usize {
+                        return;
+                    }
+
+                    let mut end = x;
+                    let mut string = String::new();
+
+                    for c in line.slice(range).chars() {
+                        let width = char_width(c, end);
+                        end += width;
+
+                        match c {
+                            '\t' => string.push_str(&" ".repeat(width)),
+                            c => string.push(c),
+                        }
+                    }
+
+                    surface.set_stringn(
+                        Point::new(area.x + x as u16, y as u16),
+                        string,
+                        (area.width as usize).saturating_sub(x),
+                        style,
+                    );
+                });
         }
     }
 
@@ -188,22 +353,89 @@ impl Widget for EditorWidget {
             _ => return EventOutcome::ignored(),
         };
 
-        self.update_count(key_event);
-        let command = self.find_command(&ctx.editor.keymaps, buf, key_event);
+        if self.update_register(buf, key_event) {
+            return EventOutcome::consumed();
+        }
+
+        let recording = ctx.editor.macros.recording().is_some();
+        let command = self.find_command(&ctx.editor.keymaps, buf, key_event, recording);
+
+        // digit may be an argument of the command
+        if command.is_none() {
+            self.update_count(key_event);
+        }
 
-        let is_insert = buf.mode().is_insert();
+        let mode = buf.mode();
+        let pending = buf.pending_operator();
+
+        let (buf, doc) = current!(ctx.editor);
+
+        // selection is gone once visual operator is applied
+        let visual = command
+            .as_ref()
+            .filter(|(command, _)| mode.is_visual() && command.repeatable())
+            .map(|_| VisualSize::new(buf, doc.text()));
+
+        // count typed before operator multiplies count of the motion
+        let count = match pending {
+            Some(pending) if command.is_some() => pending.count_with(self.count),
+            _ => self.count,
+        };
+
+        let (command, key_argument) = command.unzip();
+        let key_argument = key_argument.flatten();
 
         let mut context = editor::CommandData {
             editor: ctx.editor,
-            count: self.count,
+            count,
+            register: self.register,
+            key_argument,
+            args: editor::Args::default(),
             callback: None,
         };
 
+        let mut done = true;
+
         if let Some(command) = command {
             command.call(&mut context);
+
+            if let Some(kind) = command.motion_kind().filter(|_| pending.is_some()) {
+                apply_pending_operator(&mut context, kind);
+            }
+
+            let invocation = Invocation {
+                command,
+                count: self.count,
+                key_argument,
+            };
+            self.record_change(context.editor, invocation, mode, visual);
+
             self.reset();
-        } else if is_insert {
-            insert_mode_on_key(&mut context, key_event);
+        } else if matches!(mode, ModeKind::Insert | ModeKind::Replace) {
+            // key starting a mapping like `<C-r>` is not typed
+            if self.buffered_keys.is_empty() {
+                if mode.is_insert() {
+                    insert_mode_on_key(&mut context, key_event);
+                } else {
+                    replace_mode_on_key(&mut context, key_event);
+                }
+
+                if let Some(change) = self.change.as_mut() {
+                    change.inserted.push(Inserted::Key(key_event));
+                }
+            }
+        } else if pending.is_some() && self.buffered_keys.is_empty() && self.count.is_none() {
+            // key is not a motion, abort operator
+            switch_to_normal_mode(&mut context);
+            self.change = None;
+            self.reset();
+        } else {
+            done = false;
+        }
+
+        // command run by `<C-o>` is done, insertion continues
+        if done && !matches!(mode, ModeKind::Insert | ModeKind::Replace) {
+            resume_insert_mode(&mut context);
         }
 
         let callback = context.callback;
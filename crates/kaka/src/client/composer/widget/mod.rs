@@ -1,8 +1,12 @@
+mod confirm;
 mod editor;
 mod prompt;
+mod status_line;
 
+pub use confirm::ConfirmWidget;
 pub use editor::EditorWidget;
 pub use prompt::PromptWidget;
+pub use status_line::StatusLineWidget;
 
 use std::any::Any;
 
@@ -16,6 +20,7 @@ use super::{Context, Cursor, EventOutcome};
 pub trait Widget: Any {
     fn draw(&self, area: Rect, surface: &mut Surface, ctx: &Context<'_>);
 
+    #[allow(dead_code)]
     fn should_update(&self) -> bool {
         true
     }
@@ -8,35 +8,73 @@ use kaka_core::{
 use unicode_width::UnicodeWidthStr;
 
 use crate::client::{
-    composer::{layouter, Cursor},
+    composer::{layouter, Callback, Cursor},
     style::{Color, CursorKind, Style},
     surface::Surface,
 };
 
 use super::{Context, EventOutcome, Widget};
 
-pub type OnExecuteCallback = Box<dyn Fn(&PromptWidget, &mut Context)>;
+pub type OnExecuteCallback = Box<dyn Fn(&PromptWidget, &mut Context) -> Option<Callback>>;
+pub type PromptCallback = Box<dyn Fn(&PromptWidget, &mut Context)>;
 
 pub struct PromptWidget {
     greeter: Cow<'static, str>,
     buffer: SmartString,
     on_execute: OnExecuteCallback,
+    on_change: Option<PromptCallback>,
+    on_abort: Option<PromptCallback>,
     cursor: Cursor,
 }
 
 impl PromptWidget {
     pub fn new(
         greeter: impl Into<Cow<'static, str>>,
-        on_execute: impl Fn(&Self, &mut Context) + 'static,
+        on_execute: impl Fn(&Self, &mut Context) -> Option<Callback> + 'static,
     ) -> Self {
         Self {
             greeter: greeter.into(),
             buffer: SmartString::new_const(),
             on_execute: Box::new(on_execute),
+            on_change: None,
+            on_abort: None,
             cursor: Cursor(Point::new(0, 0), CursorKind::Line),
         }
     }
 
+    /// Fills prompt with initial text
+    #[must_use]
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.buffer.push_str(text);
+        self
+    }
+
+    /// Called after every edit of prompt text
+    #[must_use]
+    pub fn on_change(mut self, on_change: impl Fn(&Self, &mut Context) + 'static) -> Self {
+        self.on_change = Some(Box::new(on_change));
+        self
+    }
+
+    /// Called when prompt is closed without execution
+    #[must_use]
+    pub fn on_abort(mut self, on_abort: impl Fn(&Self, &mut Context) + 'static) -> Self {
+        self.on_abort = Some(Box::new(on_abort));
+        self
+    }
+
+    fn changed(&self, ctx: &mut Context) {
+        if let Some(on_change) = &self.on_change {
+            on_change(self, ctx);
+        }
+    }
+
+    fn aborted(&self, ctx: &mut Context) {
+        if let Some(on_abort) = &self.on_abort {
+            on_abort(self, ctx);
+        }
+    }
+
     pub fn text(&self) -> &str {
         &self.buffer
     }
@@ -60,20 +98,34 @@ impl Widget for PromptWidget {
             match k.code {
                 KeyCode::Char(ch) => {
                     self.buffer.push(ch);
+                    self.changed(ctx);
                     retain
                 }
                 KeyCode::Enter => {
-                    (self.on_execute)(self, ctx);
-                    remove()
+                    // executed command may push its own widget
+                    let callback = (self.on_execute)(self, ctx);
+
+                    EventOutcome::consumed().callback(|c| {
+                        c.remove_widget::<Self>();
+
+                        if let Some(callback) = callback {
+                            callback(c);
+                        }
+                    })
                 }
                 KeyCode::Backspace => {
                     if self.buffer.pop().is_some() {
+                        self.changed(ctx);
                         retain
                     } else {
+                        self.aborted(ctx);
                         remove()
                     }
                 }
-                KeyCode::Esc => remove(),
+                KeyCode::Esc => {
+                    self.aborted(ctx);
+                    remove()
+                }
                 _ => retain,
             }
         } else {
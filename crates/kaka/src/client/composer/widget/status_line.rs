@@ -0,0 +1,55 @@
+use kaka_core::{
+    document::{FileEncoding, LineEnding},
+    shapes::{Point, Rect},
+};
+
+use crate::{
+    client::{
+        composer::layouter,
+        style::{Color, Style},
+        surface::Surface,
+    },
+    current,
+};
+
+use super::{Context, Widget};
+
+/// Shows mode and name of the current document, `[+]` marks unsaved changes. Encoding other
+/// than UTF-8 and line endings other than `\n` are shown too.
+#[derive(Debug, Default)]
+pub struct StatusLineWidget;
+
+impl Widget for StatusLineWidget {
+    fn draw(&self, area: Rect, surface: &mut Surface, ctx: &Context<'_>) {
+        let (buf, doc) = current!(ctx.editor);
+
+        let mut status = format!(" {} {}", buf.mode(), doc.display_name());
+
+        if doc.is_modified() {
+            status.push_str(" [+]");
+        }
+
+        let encoding = doc.encoding();
+        if encoding != FileEncoding::default() {
+            let bom = if encoding.bom { " BOM" } else { "" };
+            status.push_str(&format!(" [{}{bom}]", encoding.name()));
+        }
+
+        if doc.line_ending() != LineEnding::Lf {
+            status.push_str(&format!(" [{}]", doc.line_ending()));
+        }
+
+        let style = Style::default().fg(Color::Black).bg(Color::Gray);
+
+        surface.set_stringn(
+            Point::new(area.x, area.y),
+            format!("{status:width$}", width = area.width as usize),
+            area.width as usize,
+            style,
+        );
+    }
+
+    fn area(&self, viewport: Rect) -> Rect {
+        layouter::status_line(viewport)
+    }
+}
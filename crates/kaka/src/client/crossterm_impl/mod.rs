@@ -1,6 +1,6 @@
 mod canvas;
 mod utils;
 
-pub(self) use utils::RawTerminalGuard;
+use utils::RawTerminalGuard;
 
 pub use canvas::CrosstermCanvas;
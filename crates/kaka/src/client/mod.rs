@@ -46,7 +46,7 @@ impl<C: Canvas> Client<C> {
         self.composer.handle_event(event, &mut ctx)
     }
 
-    pub fn composer_mut(&mut self) -> &mut Composer {
+    pub const fn composer_mut(&mut self) -> &mut Composer {
         &mut self.composer
     }
 }
@@ -21,12 +21,12 @@ impl Cell {
         self
     }
 
-    pub fn set_fg(&mut self, fg: Color) -> &mut Self {
+    pub const fn set_fg(&mut self, fg: Color) -> &mut Self {
         self.fg = fg;
         self
     }
 
-    pub fn set_bg(&mut self, bg: Color) -> &mut Self {
+    pub const fn set_bg(&mut self, bg: Color) -> &mut Self {
         self.bg = bg;
         self
     }
@@ -1,23 +1,28 @@
 use anyhow::{ensure, Result};
 use kaka_core::{
     document::{AsRope, Document, DocumentId},
-    graphemes::nth_next_grapheme_boundary,
-    selection::Selection,
+    graphemes::{
+        char_at_column, char_width, column, next_grapheme_boundary, nth_next_grapheme_boundary,
+    },
+    ropey::Rope,
+    selection::{Selection, SelectionSet},
+    transaction::{Assoc, Transaction},
 };
 
 use std::{
     cmp::Ordering,
     num::NonZeroUsize,
+    ops::Range,
     sync::atomic::{AtomicUsize, Ordering as MemoryOrdering},
 };
 
-use super::{mode::ModeData, ModeKind};
+use super::{command::PendingOperator, mode::ModeData, ModeKind};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BufferId(NonZeroUsize);
 
 impl BufferId {
-    pub const MAX: Self = Self(unsafe { NonZeroUsize::new_unchecked(usize::MAX) });
+    pub const MAX: Self = Self(NonZeroUsize::new(usize::MAX).unwrap());
 
     pub fn next() -> Self {
         pub static IDS: AtomicUsize = AtomicUsize::new(1);
@@ -36,18 +41,31 @@ pub struct Buffer {
     avail_modes: Vec<ModeKind>,
     current_mode: ModeData,
     immortal: bool,
-    saved_column: usize,
-    text_pos: usize,
+    selections: SelectionSet,
     line_idx: usize,
     line_char: usize,
     vscroll: usize,
+    /// Bounds of the last visual selection, referred to as `'<` and `'>`
+    visual_marks: Option<(usize, usize)>,
+    /// Insert or replace mode left by `<C-o>` continues once the normal mode command is done
+    resume_insert: Option<ModeKind>,
+    /// Count of insertion by more cursors and positions they started it at
+    insert_repeat: Option<(usize, Vec<usize>)>,
 }
 
 impl Buffer {
     pub fn new_text(pos: usize, document: &Document) -> Result<Self> {
         Self::new(
             pos,
-            [ModeKind::Normal, ModeKind::Insert, ModeKind::Visual], // todo bitflags?
+            [
+                ModeKind::Normal,
+                ModeKind::Insert,
+                ModeKind::Replace,
+                ModeKind::Visual,
+                ModeKind::VisualLine,
+                ModeKind::VisualBlock,
+                ModeKind::OperatorPending,
+            ], // todo bitflags?
             document,
             ModeKind::Normal,
             false,
@@ -57,7 +75,13 @@ impl Buffer {
     pub fn new_logging(document: &Document) -> Self {
         Self::new(
             0,
-            [ModeKind::Normal, ModeKind::Visual],
+            [
+                ModeKind::Normal,
+                ModeKind::Visual,
+                ModeKind::VisualLine,
+                ModeKind::VisualBlock,
+                ModeKind::OperatorPending,
+            ],
             document,
             ModeKind::Normal,
             true,
@@ -86,14 +110,18 @@ impl Buffer {
             document_id: document.id(),
             avail_modes,
             current_mode: ModeData::Normal,
-            text_pos: 0,
-            saved_column: 0,
+            selections: SelectionSet::single(Selection::at_pos(0)),
             line_idx: 0,
             line_char: 0,
             immortal,
             vscroll: 0,
+            visual_marks: None,
+            resume_insert: None,
+            insert_repeat: None,
         };
 
+        let start_mode = ModeData::new(start_mode)
+            .ok_or_else(|| anyhow::anyhow!("Buffer can't be started in {start_mode}"))?;
         this.set_mode_impl(start_mode)?;
         this.update_text_position(document, pos, Default::default());
 
@@ -114,19 +142,63 @@ impl Buffer {
 
     pub fn switch_mode(&mut self, mode: ModeKind) {
         // ignore error for now
-        self.set_mode_impl(mode).ok();
+        if let Some(mode) = ModeData::new(mode) {
+            self.set_mode_impl(mode).ok();
+        }
+    }
+
+    /// Enters operator pending mode, following motion selects text for the operator
+    pub fn switch_to_operator_pending(&mut self, pending: PendingOperator) {
+        // ignore error for now
+        self.set_mode_impl(ModeData::OperatorPending(pending)).ok();
+    }
+
+    pub const fn pending_operator(&self) -> Option<PendingOperator> {
+        match self.current_mode {
+            ModeData::OperatorPending(pending) => Some(pending),
+            _ => None,
+        }
+    }
+
+    /// Text overwritten in replace mode
+    pub const fn overwritten_mut(&mut self) -> Option<&mut Vec<Vec<String>>> {
+        match &mut self.current_mode {
+            ModeData::Replace(overwritten) => Some(overwritten),
+            _ => None,
+        }
+    }
+
+    /// Insertion in `mode` continues after the next command of normal mode
+    pub const fn set_resume_insert(&mut self, mode: ModeKind) {
+        self.resume_insert = Some(mode);
+    }
+
+    /// Returns mode insertion should continue in, the request is cleared
+    pub const fn take_resume_insert(&mut self) -> Option<ModeKind> {
+        self.resume_insert.take()
+    }
+
+    /// Text typed by each cursor from its position in `starts` is inserted `count` times in
+    /// total, kept until insert mode is left
+    pub fn set_insert_repeat(&mut self, count: usize, starts: Vec<usize>) {
+        self.insert_repeat = (count > 1).then_some((count, starts));
+    }
+
+    pub const fn take_insert_repeat(&mut self) -> Option<(usize, Vec<usize>)> {
+        self.insert_repeat.take()
     }
 
     pub const fn immortal(&self) -> bool {
         self.immortal
     }
 
-    pub const fn saved_column(&self) -> usize {
-        self.saved_column
+    pub fn saved_column(&self) -> usize {
+        self.selections.primary().column()
     }
 
-    pub const fn text_pos(&self) -> usize {
-        self.text_pos
+    /// Position of primary cursor
+    pub fn text_pos(&self) -> usize {
+        self.selections.primary().head()
     }
 
     pub const fn line_idx(&self) -> usize {
@@ -141,7 +213,7 @@ impl Buffer {
         self.vscroll
     }
 
-    pub fn update_vscroll(&mut self, max: usize) {
+    pub const fn update_vscroll(&mut self, max: usize) {
         let lower_bound = self.vscroll;
         let upper_bound = self.vscroll + max - 1;
 
@@ -152,12 +224,137 @@ impl Buffer {
         }
     }
 
-    pub const fn selection(&self) -> Option<&Selection> {
-        if let ModeData::Visual(selection) = &self.current_mode {
-            Some(selection)
-        } else {
-            None
+    pub const fn visual_marks(&self) -> Option<(usize, usize)> {
+        self.visual_marks
+    }
+
+    pub const fn selections(&self) -> &SelectionSet {
+        &self.selections
+    }
+
+    /// Text covered by selections in visual modes, in visual block mode there is a range for
+    /// every line of the block
+    pub fn visual_ranges(&self, rope: &impl AsRope) -> Vec<Range<usize>> {
+        let text = rope.as_rope();
+        let mut ranges = Vec::with_capacity(self.selections.len());
+
+        for &selection in self.selections.iter() {
+            let (start, end) = selection.range();
+
+            match self.mode() {
+                ModeKind::VisualLine => {
+                    let first_line = text.char_to_line(start);
+                    let last_line = text.char_to_line(end);
+
+                    ranges.push(text.line_to_char(first_line)..text.line_to_char(last_line + 1));
+                }
+                ModeKind::VisualBlock => ranges.extend(block_rows(text, selection)),
+                _ => ranges.push(start..next_grapheme_boundary(text.slice(..), end)),
+            }
+        }
+
+        ranges
+    }
+
+    /// Calls `f` for every cursor, each one of them is made primary for the time of the call
+    pub fn for_each_cursor<F>(&mut self, rope: &impl AsRope, mut f: F)
+    where
+        F: FnMut(&mut Self),
+    {
+        let primary = self.selections.primary_index();
+
+        for idx in 0..self.selections.len() {
+            self.selections.set_primary(idx);
+            self.sync_line(rope);
+            f(self);
+        }
+
+        self.selections.set_primary(primary);
+        self.selections.normalize();
+        self.sync_line(rope);
+    }
+
+    /// Maps all cursors through applied transaction
+    pub fn map_cursors(&mut self, rope: &impl AsRope, tx: &Transaction, assoc: Assoc) {
+        self.selections.map(tx, assoc);
+        self.sync_line(rope);
+    }
+
+    /// Replaces every selection with the result of `f`
+    pub fn transform_cursors<F>(&mut self, rope: &impl AsRope, f: F)
+    where
+        F: FnMut(Selection) -> Selection,
+    {
+        self.selections.transform(f);
+        self.sync_line(rope);
+    }
+
+    /// Moves anchor of primary selection, cursor stays in place
+    pub fn update_anchor(&mut self, pos: usize) {
+        let selection = self.selections.primary_mut();
+        *selection = Selection::new(pos, selection.head()).with_column(selection.column());
+    }
+
+    /// Replaces selections with ones saved in history, in normal mode
+    ///
+    /// Visual selection is restored as `'<` and `'>` marks with cursor at its start, the way
+    /// operators leave it. Visual mode is not entered again, `u` there changes case instead of
+    /// undoing the next change.
+    pub fn restore_selections(&mut self, rope: &impl AsRope, selections: SelectionSet) {
+        self.switch_mode(ModeKind::Normal);
+
+        let primary = selections.primary();
+        if primary.anchor() != primary.head() {
+            self.visual_marks = Some(primary.range());
         }
+
+        let max = rope.as_rope().len_chars().saturating_sub(1);
+
+        self.selections = selections;
+        self.selections
+            .transform(|s| Selection::at_pos(s.start().min(max)).with_column(s.column()));
+
+        self.for_each_cursor(rope, |buf| {
+            buf.update_text_position(rope, buf.text_pos(), UpdateBufPositionParams::default());
+        });
+    }
+
+    /// Drops all cursors but primary one
+    pub fn keep_primary_cursor(&mut self) {
+        self.selections.keep_primary();
+    }
+
+    /// Replaces selections with cursors at `positions`, the first one is primary
+    pub fn set_cursors(&mut self, rope: &impl AsRope, positions: impl IntoIterator<Item = usize>) {
+        let cursors = positions
+            .into_iter()
+            .map(Selection::at_pos)
+            .collect::<Vec<_>>();
+
+        if cursors.is_empty() {
+            return;
+        }
+
+        self.selections = SelectionSet::new(cursors, 0);
+        self.for_each_cursor(rope, |buf| {
+            buf.update_text_position(rope, buf.text_pos(), UpdateBufPositionParams::default());
+        });
+    }
+
+    /// Adds new cursor and makes it primary
+    pub fn add_cursor(&mut self, rope: &impl AsRope, pos: usize) {
+        let column = self.saved_column();
+
+        self.selections
+            .push(Selection::at_pos(pos).with_column(column));
+        self.sync_line(rope);
+    }
+
+    fn sync_line(&mut self, rope: &impl AsRope) {
+        let text = rope.as_rope();
+
+        self.line_idx = text.char_to_line(self.text_pos().min(text.len_chars()));
+        self.line_char = text.line_to_char(self.line_idx);
     }
 
     pub fn update_text_position(
@@ -200,11 +397,14 @@ impl Buffer {
             debug_assert_eq!(line_idx, text.char_to_line(new_pos), "line changed");
         }
 
-        if line_idx != self.line_idx {
-            self.line_char = text.line_to_char(line_idx);
-        }
+        // text may have changed under the cursor (undo), line start can't be trusted
+        self.line_char = text.line_to_char(line_idx);
 
         let line = text.line(line_idx);
+        let is_selecting = self.mode().is_selecting();
+
+        // operators may act on the last character of the line with exclusive motion
+        let allow_on_newline = allow_on_newline || self.mode() == ModeKind::OperatorPending;
 
         if !allow_on_newline
             && line.len_chars() > 1
@@ -214,34 +414,121 @@ impl Buffer {
         }
 
         self.line_idx = line_idx;
-        let old_pos = std::mem::replace(&mut self.text_pos, new_pos);
 
-        if update_saved_column {
-            let distance = self.text_pos - self.line_char;
+        let line_char = self.line_char;
+        let selection = self.selections.primary_mut();
 
-            self.saved_column = nth_next_grapheme_boundary(line, 0, distance);
+        if is_selecting {
+            selection.update_head(new_pos);
+        } else {
+            selection.collapse(new_pos);
         }
 
-        if old_pos != self.text_pos {
-            self.current_mode.update(pos);
+        if update_saved_column {
+            let distance = new_pos - line_char;
+
+            selection.update_column(nth_next_grapheme_boundary(line, 0, distance));
         }
 
-        (self.text_pos != pos).then_some(new_pos)
+        (new_pos != pos).then_some(new_pos)
     }
 
-    fn set_mode_impl(&mut self, mode: ModeKind) -> Result<()> {
+    fn set_mode_impl(&mut self, mode: ModeData) -> Result<()> {
+        let kind = mode.kind();
+
         anyhow::ensure!(
-            self.avail_modes.contains(&mode),
-            "Buffer is not capable to enter {mode}"
+            self.avail_modes.contains(&kind),
+            "Buffer is not capable to enter {kind}"
         );
 
-        self.current_mode = ModeData::new(mode, self.text_pos);
+        if kind != ModeKind::Insert {
+            self.insert_repeat = None;
+        }
+
+        if self.mode().is_visual() && !kind.is_visual() {
+            self.visual_marks = Some(self.selections.primary().range());
+        }
+
+        // leaving selecting mode collapses selections to cursors, visual modes share them
+        let visual_switch = self.mode().is_visual() && kind.is_visual();
+        if self.mode().is_selecting() && self.mode() != kind && !visual_switch {
+            self.selections.transform(|s| {
+                let mut s = s;
+                s.collapse(s.head());
+                s
+            });
+        }
+
+        self.current_mode = mode;
 
         Ok(())
     }
 }
 
-#[derive(Debug)]
+/// Same block with anchor at its top left corner and head at the bottom right one
+pub fn block_corners(text: &Rope, selection: Selection) -> Selection {
+    let (left, right) = block_columns(text, selection);
+
+    let (start, end) = selection.range();
+    let (top, bottom) = (text.char_to_line(start), text.char_to_line(end));
+    let last = line_len(text, bottom).saturating_sub(1);
+
+    Selection::new(
+        text.line_to_char(top) + char_at_column(text.line(top), left),
+        text.line_to_char(bottom) + char_at_column(text.line(bottom), right).min(last),
+    )
+    .with_column(selection.column())
+}
+
+/// Display columns of the left and right edge of block, characters at its corners are
+/// covered whole
+pub fn block_columns(text: &Rope, selection: Selection) -> (usize, usize) {
+    let edges = |pos: usize| {
+        let line_idx = text.char_to_line(pos);
+        let start = column(text.line(line_idx), pos - text.line_to_char(line_idx));
+        let width = text.get_char(pos).map_or(1, |c| char_width(c, start));
+
+        (start, start + width.max(1) - 1)
+    };
+
+    let (anchor, head) = (edges(selection.anchor()), edges(selection.head()));
+
+    (anchor.0.min(head.0), anchor.1.max(head.1))
+}
+
+/// Rows of rectangle with corners at anchor and head of selection, each one cut to its line
+///
+/// Rows are measured in display columns the way text is drawn, so a tab partly inside the
+/// block belongs to its row.
+fn block_rows(text: &Rope, selection: Selection) -> impl Iterator<Item = Range<usize>> + '_ {
+    let (left, right) = block_columns(text, selection);
+    let (start, end) = selection.range();
+
+    (text.char_to_line(start)..=text.char_to_line(end)).map(move |line_idx| {
+        let line = text.line(line_idx);
+        let line_start = text.line_to_char(line_idx);
+        let len = line_len(text, line_idx);
+
+        let row_start = char_at_column(line, left).min(len);
+        let row_end = (char_at_column(line, right) + 1).min(len);
+
+        line_start + row_start..line_start + row_end
+    })
+}
+
+/// Characters of line without its line break
+fn line_len(text: &Rope, line_idx: usize) -> usize {
+    let line = text.line(line_idx);
+
+    let mut len = line.len_chars();
+    if len > 0 && line.char(len - 1) == '\n' {
+        len -= 1;
+    }
+
+    len
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct UpdateBufPositionParams {
     /// Update saved column
     pub update_saved_column: bool,
@@ -283,24 +570,24 @@ mod test {
         *document.text_mut() = Rope::from("kaka\n");
 
         let buffer = Buffer::new_text(0, &document).unwrap();
-        assert_eq!(buffer.text_pos, 0);
-        assert_eq!(buffer.saved_column, 0);
+        assert_eq!(buffer.text_pos(), 0);
+        assert_eq!(buffer.saved_column(), 0);
 
         let buffer = Buffer::new_text(1, &document).unwrap();
-        assert_eq!(buffer.text_pos, 1);
-        assert_eq!(buffer.saved_column, 1);
+        assert_eq!(buffer.text_pos(), 1);
+        assert_eq!(buffer.saved_column(), 1);
 
         let buffer = Buffer::new_text(2, &document).unwrap();
-        assert_eq!(buffer.text_pos, 2);
-        assert_eq!(buffer.saved_column, 2);
+        assert_eq!(buffer.text_pos(), 2);
+        assert_eq!(buffer.saved_column(), 2);
 
         let buffer = Buffer::new_text(3, &document).unwrap();
-        assert_eq!(buffer.text_pos, 3);
-        assert_eq!(buffer.saved_column, 3);
+        assert_eq!(buffer.text_pos(), 3);
+        assert_eq!(buffer.saved_column(), 3);
 
         let buffer = Buffer::new_text(4, &document).unwrap();
-        assert_eq!(buffer.text_pos, 3, "Shouldn't be placed on newline");
-        assert_eq!(buffer.saved_column, 3);
+        assert_eq!(buffer.text_pos(), 3, "Shouldn't be placed on newline");
+        assert_eq!(buffer.saved_column(), 3);
 
         assert!(
             Buffer::new_text(5, &document).is_err(),
@@ -310,14 +597,14 @@ mod test {
         *document.text_mut() = Rope::from("kaka\nk");
         let buffer = Buffer::new_text(5, &document).unwrap();
 
-        assert_eq!(buffer.text_pos, 5);
-        assert_eq!(buffer.saved_column, 0);
+        assert_eq!(buffer.text_pos(), 5);
+        assert_eq!(buffer.saved_column(), 0);
 
         *document.text_mut() = Rope::from("kaka");
         let buffer = Buffer::new_text(3, &document).unwrap();
 
-        assert_eq!(buffer.text_pos, 3);
-        assert_eq!(buffer.saved_column, 3);
+        assert_eq!(buffer.text_pos(), 3);
+        assert_eq!(buffer.saved_column(), 3);
     }
 
     #[test]
@@ -331,4 +618,31 @@ mod test {
         buffer.switch_mode(ModeKind::Insert);
         assert!(buffer.mode().is_insert());
     }
+
+    #[test]
+    fn cursors_collapse_on_visual_leave() {
+        let mut document = Document::new_scratch();
+        *document.text_mut() = Rope::from("kaka\nkaka\n");
+
+        let mut buffer = Buffer::new_text(0, &document).unwrap();
+        buffer.add_cursor(&document, 5);
+        buffer.switch_mode(ModeKind::Visual);
+
+        buffer.for_each_cursor(&document, |buf| {
+            buf.update_text_position(&document, buf.text_pos() + 2, Default::default());
+        });
+
+        assert_eq!(
+            buffer.selections().ranges().collect::<Vec<_>>(),
+            [(0, 2), (5, 7)]
+        );
+        assert_eq!(buffer.text_pos(), 7);
+
+        buffer.switch_mode(ModeKind::Normal);
+
+        assert_eq!(
+            buffer.selections().ranges().collect::<Vec<_>>(),
+            [(2, 2), (7, 7)]
+        );
+    }
 }
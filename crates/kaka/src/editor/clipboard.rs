@@ -0,0 +1,382 @@
+//! System clipboard behind `"+` and `"*` registers, reached through external commands or
+//! OSC 52 escape sequence of the terminal
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Debug,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+
+use super::register::RegisterValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClipboardKind {
+    /// `"+`
+    Clipboard,
+    /// `"*`, primary selection where there is one
+    Selection,
+}
+
+pub trait ClipboardProvider: Debug + Send {
+    fn name(&self) -> Cow<'_, str>;
+    fn get(&mut self, kind: ClipboardKind) -> Result<String>;
+    fn set(&mut self, kind: ClipboardKind, text: &str) -> Result<()>;
+}
+
+/// Provider chosen by name, as in `:set clipboard=xclip`, `auto` detects one
+fn provider(name: &str) -> Result<Box<dyn ClipboardProvider>> {
+    let provider: Box<dyn ClipboardProvider> = match name {
+        "auto" => detect(),
+        "internal" => Box::<Memory>::default(),
+        "osc52" => Box::<Osc52>::default(),
+        _ => match COMMANDS.iter().find(|commands| commands.name == name) {
+            Some(commands) => Box::new(*commands),
+            None => bail!("Unknown clipboard provider: {name}"),
+        },
+    };
+
+    Ok(provider)
+}
+
+/// Commands of the running desktop when they are installed, OSC 52 otherwise
+fn detect() -> Box<dyn ClipboardProvider> {
+    let env = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+
+    let available = |name| {
+        COMMANDS
+            .iter()
+            .find(|commands| commands.name == name && executable(commands.copy[0].program))
+    };
+
+    let commands = if cfg!(target_os = "macos") {
+        available("pbcopy")
+    } else if env("WAYLAND_DISPLAY") {
+        available("wl-clipboard")
+    } else if env("DISPLAY") {
+        available("xclip").or_else(|| available("xsel"))
+    } else if env("TMUX") {
+        available("tmux")
+    } else {
+        None
+    };
+
+    commands.map_or_else(
+        || Box::<Osc52>::default() as Box<dyn ClipboardProvider>,
+        |commands| Box::new(*commands),
+    )
+}
+
+fn executable(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| Path::new(&dir).join(program).is_file())
+    })
+}
+
+/// Clipboard with values set by the editor, so that text of every cursor survives the round
+/// trip through it
+#[derive(Debug)]
+pub struct Clipboard {
+    provider: Box<dyn ClipboardProvider>,
+    set: HashMap<ClipboardKind, RegisterValue>,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new(Box::<Memory>::default())
+    }
+}
+
+impl Clipboard {
+    pub fn new(provider: Box<dyn ClipboardProvider>) -> Self {
+        Self {
+            provider,
+            set: HashMap::new(),
+        }
+    }
+
+    /// Provider of the running desktop
+    pub fn detect() -> Self {
+        Self::new(detect())
+    }
+
+    /// Provider named like in `:set clipboard=xclip`
+    pub fn from_name(name: &str) -> Result<Self> {
+        provider(name).map(Self::new)
+    }
+
+    pub fn name(&self) -> Cow<'_, str> {
+        self.provider.name()
+    }
+
+    pub fn get(&mut self, kind: ClipboardKind) -> Option<RegisterValue> {
+        let text = match self.provider.get(kind) {
+            Ok(text) => text,
+            Err(err) => {
+                log::error!("Clipboard {}: {err:#}", self.provider.name());
+                return None;
+            }
+        };
+
+        match self.set.get(&kind) {
+            Some(value) if value.joined() == text => Some(value.clone()),
+            _ => (!text.is_empty()).then(|| RegisterValue::from_text(text)),
+        }
+    }
+
+    pub fn set(&mut self, kind: ClipboardKind, value: RegisterValue) {
+        if let Err(err) = self.provider.set(kind, &value.joined()) {
+            log::error!("Clipboard {}: {err:#}", self.provider.name());
+        }
+
+        self.set.insert(kind, value);
+    }
+}
+
+/// Clipboard shared only inside of the editor
+#[derive(Debug, Default)]
+pub struct Memory {
+    clipboard: String,
+    selection: String,
+}
+
+impl ClipboardProvider for Memory {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("internal")
+    }
+
+    fn get(&mut self, kind: ClipboardKind) -> Result<String> {
+        Ok(match kind {
+            ClipboardKind::Clipboard => self.clipboard.clone(),
+            ClipboardKind::Selection => self.selection.clone(),
+        })
+    }
+
+    fn set(&mut self, kind: ClipboardKind, text: &str) -> Result<()> {
+        let stored = match kind {
+            ClipboardKind::Clipboard => &mut self.clipboard,
+            ClipboardKind::Selection => &mut self.selection,
+        };
+
+        text.clone_into(stored);
+        Ok(())
+    }
+}
+
+/// Terminal sets the clipboard, works over ssh too, reading it back isn't supported by most of
+/// them so the editor remembers what it set
+#[derive(Debug, Default)]
+pub struct Osc52 {
+    memory: Memory,
+}
+
+impl ClipboardProvider for Osc52 {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed("osc52")
+    }
+
+    fn get(&mut self, kind: ClipboardKind) -> Result<String> {
+        self.memory.get(kind)
+    }
+
+    fn set(&mut self, kind: ClipboardKind, text: &str) -> Result<()> {
+        let target = match kind {
+            ClipboardKind::Clipboard => 'c',
+            ClipboardKind::Selection => 'p',
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        write!(stdout, "\x1b]52;{target};{}\x07", base64(text.as_bytes()))?;
+        stdout.flush()?;
+
+        self.memory.set(kind, text)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Invocation {
+    program: &'static str,
+    args: &'static [&'static str],
+}
+
+impl Invocation {
+    const fn new(program: &'static str, args: &'static [&'static str]) -> Self {
+        Self { program, args }
+    }
+}
+
+/// Programs copying text from stdin and pasting it to stdout, indexed by `ClipboardKind`
+#[derive(Debug, Clone, Copy)]
+struct Commands {
+    name: &'static str,
+    copy: [Invocation; 2],
+    paste: [Invocation; 2],
+}
+
+const COMMANDS: &[Commands] = &[
+    Commands {
+        name: "pbcopy",
+        copy: [
+            Invocation::new("pbcopy", &[]),
+            Invocation::new("pbcopy", &[]),
+        ],
+        paste: [
+            Invocation::new("pbpaste", &[]),
+            Invocation::new("pbpaste", &[]),
+        ],
+    },
+    Commands {
+        name: "wl-clipboard",
+        copy: [
+            Invocation::new("wl-copy", &["--type", "text/plain"]),
+            Invocation::new("wl-copy", &["--primary", "--type", "text/plain"]),
+        ],
+        paste: [
+            Invocation::new("wl-paste", &["--no-newline"]),
+            Invocation::new("wl-paste", &["--no-newline", "--primary"]),
+        ],
+    },
+    Commands {
+        name: "xclip",
+        copy: [
+            Invocation::new("xclip", &["-i", "-selection", "clipboard"]),
+            Invocation::new("xclip", &["-i", "-selection", "primary"]),
+        ],
+        paste: [
+            Invocation::new("xclip", &["-o", "-selection", "clipboard"]),
+            Invocation::new("xclip", &["-o", "-selection", "primary"]),
+        ],
+    },
+    Commands {
+        name: "xsel",
+        copy: [
+            Invocation::new("xsel", &["-i", "-b"]),
+            Invocation::new("xsel", &["-i", "-p"]),
+        ],
+        paste: [
+            Invocation::new("xsel", &["-o", "-b"]),
+            Invocation::new("xsel", &["-o", "-p"]),
+        ],
+    },
+    Commands {
+        name: "tmux",
+        copy: [
+            Invocation::new("tmux", &["load-buffer", "-w", "-"]),
+            Invocation::new("tmux", &["load-buffer", "-w", "-"]),
+        ],
+        paste: [
+            Invocation::new("tmux", &["save-buffer", "-"]),
+            Invocation::new("tmux", &["save-buffer", "-"]),
+        ],
+    },
+];
+
+impl ClipboardProvider for Commands {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self.name)
+    }
+
+    fn get(&mut self, kind: ClipboardKind) -> Result<String> {
+        let Invocation { program, args } = self.paste[kind as usize];
+
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .with_context(|| format!("Can't run {program}"))?;
+
+        if !output.status.success() {
+            bail!("{program} failed with {}", output.status);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn set(&mut self, kind: ClipboardKind, text: &str) -> Result<()> {
+        let Invocation { program, args } = self.copy[kind as usize];
+
+        // programs serving the clipboard fork to background once they read the text
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Can't run {program}"))?;
+
+        let written = child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(text.as_bytes());
+        let status = child.wait()?;
+        written?;
+
+        if !status.success() {
+            bail!("{program} failed with {status}");
+        }
+
+        Ok(())
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn values_of_cursors_survive() {
+        let mut clipboard = Clipboard::default();
+        let value = RegisterValue::new(["a".to_string(), "b".to_string()], false);
+
+        clipboard.set(ClipboardKind::Clipboard, value.clone());
+        assert_eq!(clipboard.get(ClipboardKind::Clipboard), Some(value));
+        assert_eq!(clipboard.get(ClipboardKind::Selection), None);
+
+        // changed outside of the editor
+        clipboard
+            .provider
+            .set(ClipboardKind::Clipboard, "line\n")
+            .unwrap();
+        assert_eq!(
+            clipboard.get(ClipboardKind::Clipboard),
+            Some(RegisterValue::new(["line\n".to_string()], true))
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Path,
+    Int,
+    String,
+}
+
+impl fmt::Display for ArgKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Path => "path",
+            Self::Int => "number",
+            Self::String => "string",
+        };
+
+        f.write_str(kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arg {
+    Path(PathBuf),
+    Int(usize),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Param {
+    name: &'static str,
+    kind: ArgKind,
+    optional: bool,
+}
+
+/// Parameters accepted by typable command, optional ones follow required ones
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Signature {
+    params: Vec<Param>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ArgsError {
+    #[error("Missing argument <{0}>")]
+    Missing(&'static str),
+
+    #[error("Argument <{name}> should be a {kind}, got {value:?}")]
+    Invalid {
+        name: &'static str,
+        kind: ArgKind,
+        value: String,
+    },
+
+    #[error("Too many arguments, expected at most {0}")]
+    TooMany(usize),
+
+    #[error("Unterminated quote")]
+    UnterminatedQuote,
+}
+
+impl Signature {
+    pub const fn new() -> Self {
+        Self { params: vec![] }
+    }
+
+    #[must_use]
+    pub fn required(mut self, name: &'static str, kind: ArgKind) -> Self {
+        assert!(
+            self.params.iter().all(|p| !p.optional),
+            "Required parameter {name} follows optional one"
+        );
+
+        self.params.push(Param {
+            name,
+            kind,
+            optional: false,
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn optional(mut self, name: &'static str, kind: ArgKind) -> Self {
+        self.params.push(Param {
+            name,
+            kind,
+            optional: true,
+        });
+        self
+    }
+
+    /// Parses arguments typed after command name
+    pub fn parse(&self, input: &str) -> Result<Args, ArgsError> {
+        let tokens = tokenize(input)?;
+
+        if tokens.len() > self.params.len() {
+            return Err(ArgsError::TooMany(self.params.len()));
+        }
+
+        let mut tokens = tokens.into_iter();
+
+        let args = self
+            .params
+            .iter()
+            .map(|param| match tokens.next() {
+                Some(token) => parse_arg(param, token).map(Some),
+                None if param.optional => Ok(None),
+                None => Err(ArgsError::Missing(param.name)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Args(args))
+    }
+}
+
+fn parse_arg(param: &Param, token: String) -> Result<Arg, ArgsError> {
+    match param.kind {
+        ArgKind::Path => Ok(Arg::Path(token.into())),
+        ArgKind::String => Ok(Arg::String(token)),
+        ArgKind::Int => token.parse().map(Arg::Int).map_err(|_| ArgsError::Invalid {
+            name: param.name,
+            kind: param.kind,
+            value: token,
+        }),
+    }
+}
+
+/// Splits on whitespace, double quotes group words and backslash escapes next quote,
+/// whitespace or backslash, other backslashes are kept like in `C:\dir`
+fn tokenize(input: &str) -> Result<Vec<String>, ArgsError> {
+    let mut tokens = vec![];
+    let mut token = None::<String>;
+    let mut quoted = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = chars
+                    .next_if(|next| matches!(next, '"' | '\\') || next.is_whitespace())
+                    .unwrap_or('\\');
+                token.get_or_insert_with(String::new).push(escaped);
+            }
+            '"' => {
+                quoted = !quoted;
+                token.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => tokens.extend(token.take()),
+            c => token.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quoted {
+        return Err(ArgsError::UnterminatedQuote);
+    }
+
+    tokens.extend(token);
+
+    Ok(tokens)
+}
+
+/// Arguments parsed according to command's `Signature`, indexed like its parameters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args(Vec<Option<Arg>>);
+
+impl Args {
+    pub fn get(&self, idx: usize) -> Option<&Arg> {
+        self.0.get(idx).and_then(Option::as_ref)
+    }
+
+    pub fn path(&self, idx: usize) -> Option<&Path> {
+        match self.get(idx) {
+            Some(Arg::Path(path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self, idx: usize) -> Option<usize> {
+        match self.get(idx) {
+            Some(Arg::Int(int)) => Some(*int),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, idx: usize) -> Option<&str> {
+        match self.get(idx) {
+            Some(Arg::String(string)) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokenize_quotes_and_escapes() {
+        assert_eq!(tokenize("  a  b ").unwrap(), ["a", "b"]);
+        assert_eq!(tokenize(r#""a b" c\ d"#).unwrap(), ["a b", "c d"]);
+        assert_eq!(tokenize(r#""""#).unwrap(), [""]);
+        assert_eq!(tokenize(r#""a"#), Err(ArgsError::UnterminatedQuote));
+        assert_eq!(tokenize(r#"\"a\\ b\"#).unwrap(), [r#""a\"#, r"b\"]);
+        assert_eq!(
+            tokenize(r#"C:\dir\file "C:\my dir""#).unwrap(),
+            [r"C:\dir\file", r"C:\my dir"],
+            "backslash before other chars is kept"
+        );
+    }
+
+    #[test]
+    fn parse_by_signature() {
+        let signature = Signature::new()
+            .required("line", ArgKind::Int)
+            .optional("path", ArgKind::Path);
+
+        let args = signature.parse("12 foo.rs").unwrap();
+        assert_eq!(args.int(0), Some(12));
+        assert_eq!(args.path(1), Some(Path::new("foo.rs")));
+
+        let args = signature.parse("12").unwrap();
+        assert_eq!(args.int(0), Some(12));
+        assert_eq!(args.path(1), None);
+
+        assert_eq!(signature.parse(""), Err(ArgsError::Missing("line")));
+        assert_eq!(signature.parse("1 2 3"), Err(ArgsError::TooMany(2)));
+        assert_eq!(
+            signature.parse("x").unwrap_err().to_string(),
+            "Argument <line> should be a number, got \"x\""
+        );
+    }
+
+    #[test]
+    fn no_params() {
+        assert_eq!(Signature::new().parse(" "), Ok(Args::default()));
+        assert_eq!(Signature::new().parse("a"), Err(ArgsError::TooMany(0)));
+    }
+}
@@ -1,8 +1,12 @@
-use kaka_core::document::Document;
+use anyhow::bail;
+use kaka_core::document::{Document, Error};
 
-use crate::{current, editor::Buffer};
+use crate::{
+    current, current_mut,
+    editor::{buffer::UpdateBufPositionParams, Buffer, Editor},
+};
 
-use super::CommandData;
+use super::{options::parse_encoding, recovery_prompt, CommandData};
 
 pub fn buffer_next(ctx: &mut CommandData) {
     let curr = ctx.editor.current;
@@ -45,12 +49,23 @@ pub fn buffer_create(ctx: &mut CommandData) {
 }
 
 pub fn buffer_kill(ctx: &mut CommandData) {
-    let immortal = ctx
+    let buf = ctx.editor.buffers.get(&ctx.editor.current).unwrap();
+    let immortal = buf.immortal();
+    let doc_id = buf.document_id();
+
+    let shared = ctx
         .editor
         .buffers
-        .get(&ctx.editor.current)
-        .unwrap()
-        .immortal();
+        .values()
+        .any(|other| other.id() != buf.id() && other.document_id() == doc_id);
+
+    if !shared && ctx.editor.documents[&doc_id].is_modified() {
+        log::error!(
+            "No write since last change for {}",
+            ctx.editor.documents[&doc_id].display_name()
+        );
+        return;
+    }
 
     if !immortal {
         ctx.editor.buffers.remove(&ctx.editor.current);
@@ -64,12 +79,429 @@ pub fn buffer_kill(ctx: &mut CommandData) {
 }
 
 // commands impl
+/// Quits unless some document has unsaved changes
 pub fn close(ctx: &mut CommandData) {
+    let modified = ctx
+        .editor
+        .buffers
+        .values()
+        .map(|buf| &ctx.editor.documents[&buf.document_id()])
+        .find(|doc| doc.is_modified());
+
+    match modified {
+        Some(doc) => log::error!(
+            "No write since last change for {} (add ! to override)",
+            doc.display_name()
+        ),
+        None => ctx.editor.exit_code = Some(0),
+    }
+}
+
+/// `:q!`
+pub const fn force_close(ctx: &mut CommandData) {
     ctx.editor.exit_code = Some(0);
 }
 
+/// `:w [path]`, writing to path keeps document bound to its file unless it has none
 pub fn save(ctx: &mut CommandData) {
+    if let Err(err) = write(ctx, false) {
+        log::error!("{err}");
+    }
+}
+
+/// `:w! [path]`, writes also text with malformed sequences replaced when reading
+pub fn force_save(ctx: &mut CommandData) {
+    if let Err(err) = write(ctx, true) {
+        log::error!("{err}");
+    }
+}
+
+/// `:saveas <path>`, document is bound to the new file
+pub fn save_as(ctx: &mut CommandData) {
+    let path = ctx.args.path(0).expect("Path is required");
+    let indent = ctx.editor.indent_for(path);
+    let (_, doc) = current_mut!(ctx.editor);
+
+    match doc.save_as(path) {
+        Ok(()) => log::info!("Saved as {}", path.display()),
+        Err(err) => return log::error!("{err}"),
+    }
+
+    doc.set_indent(indent);
+
+    let (_, doc) = current!(ctx.editor);
+    ctx.editor.write_undo_file(doc);
+}
+
+/// `:wq [path]`, quits only when writing succeeded
+pub fn write_quit(ctx: &mut CommandData) {
+    match write(ctx, false) {
+        Ok(()) => close(ctx),
+        Err(err) => log::error!("{err}"),
+    }
+}
+
+/// `:e <path>`, switches to buffer when file is already open
+pub fn edit(ctx: &mut CommandData) {
+    let path = ctx.args.path(0).expect("Path is required");
+
+    if let Some(buf_id) = ctx.editor.buffer_of_path(path) {
+        ctx.editor.current = buf_id;
+    } else if let Err(err) = ctx.editor.open(path, true) {
+        log::error!("{err}");
+    } else if let Some(prompt) = recovery_prompt(ctx.editor) {
+        ctx.push_widget(prompt);
+    }
+}
+
+/// `:reopen <encoding>`, reads file again decoded with given encoding
+pub fn reopen_with_encoding(ctx: &mut CommandData) {
+    let label = ctx.args.string(0).expect("Encoding is required");
+
+    if let Err(err) = reopen(ctx.editor, label) {
+        log::error!("{err}");
+    }
+}
+
+fn reopen(editor: &mut Editor, label: &str) -> anyhow::Result<()> {
+    let encoding = parse_encoding(label)?;
+    let (buf, doc) = current_mut!(editor);
+
+    if doc.is_modified() {
+        bail!("No write since last change for {}", doc.display_name());
+    }
+
+    doc.reload_with_encoding(encoding)?;
+
+    let doc_id = buf.document_id();
+    let doc = &editor.documents[&doc_id];
+    let len = doc.text().len_chars();
+
+    for buf in editor.buffers.values_mut() {
+        if buf.document_id() == doc_id {
+            let pos = buf.text_pos().min(len);
+            buf.keep_primary_cursor();
+            buf.update_text_position(doc, pos, UpdateBufPositionParams::default());
+        }
+    }
+
+    Ok(())
+}
+
+fn write(ctx: &mut CommandData, force: bool) -> Result<(), Error> {
+    let indent = ctx.args.path(0).map(|path| ctx.editor.indent_for(path));
+    let (_, doc) = current_mut!(ctx.editor);
+
+    match ctx.args.path(0) {
+        Some(path) if !doc.is_scratch() => return doc.write_to(path),
+        Some(path) => {
+            doc.save_as(path)?;
+            doc.set_indent(indent.unwrap_or_default());
+        }
+        None if force => doc.force_save()?,
+        None => doc.save()?,
+    }
+
     let (_, doc) = current!(ctx.editor);
+    ctx.editor.write_undo_file(doc);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use kaka_core::indent::Indent;
+
+    use super::super::test::*;
+    use crate::{client::composer::Context, current, current_mut, editor::Editor};
+
+    fn execute(editor: &mut Editor, line: &str) {
+        let mut context = Context { editor };
+        context.execute_command_line(line);
+    }
+
+    #[test]
+    fn write_scratch_binds_path() {
+        let path = temp_path("write_scratch");
+        let mut editor = test_editor(0, "abc\n");
+
+        execute(&mut editor, "w");
+        assert!(!path.exists(), "scratch without path is not written");
+
+        execute(&mut editor, &format!("w {}", path.display()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+        assert_eq!(current!(editor).1.path(), Some(path.as_path()));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn write_copy_and_save_as() {
+        let original = temp_path("original");
+        let copy = temp_path("copy");
+        let renamed = temp_path("renamed");
+
+        let mut editor = test_editor(0, "abc\n");
+        execute(&mut editor, &format!("saveas {}", original.display()));
+
+        execute(&mut editor, &format!("w {}", copy.display()));
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "abc\n");
+        assert_eq!(current!(editor).1.path(), Some(original.as_path()));
+
+        execute(&mut editor, &format!("saveas {}", renamed.display()));
+        assert_eq!(current!(editor).1.path(), Some(renamed.as_path()));
+
+        for path in [original, copy, renamed] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn save_as_picks_indent() {
+        let scratch = temp_path("scratch.go");
+        let renamed = temp_path("renamed.go");
+
+        let mut editor = test_editor(0, "abc\n");
+        assert_eq!(editor.indent().unit, "    ");
 
-    doc.save().unwrap();
+        execute(&mut editor, &format!("w {}", scratch.display()));
+        assert_eq!(editor.indent().unit, "\t");
+
+        current_mut!(editor).1.set_indent(Indent::default());
+        execute(&mut editor, &format!("saveas {}", renamed.display()));
+        assert_eq!(editor.indent().unit, "\t");
+
+        for path in [scratch, renamed] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn write_quit() {
+        let path = temp_path("write_quit");
+        let mut editor = test_editor(0, "abc\n");
+
+        execute(&mut editor, "wq");
+        assert!(!editor.should_exit(), "nothing was written");
+
+        execute(&mut editor, &format!("wq {}", path.display()));
+        assert!(editor.should_exit());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edit_opens_or_switches() {
+        let path = temp_path("edit");
+        fs::write(&path, "file\n").unwrap();
+
+        let mut editor = test_editor(0, "scratch\n");
+        let scratch = editor.current;
+
+        execute(&mut editor, &format!("e {}", path.display()));
+        let opened = editor.current;
+        assert_ne!(opened, scratch);
+        assert_eq!(current!(editor).1.text(), "file\n");
+
+        editor.current = scratch;
+        execute(&mut editor, &format!("e {}", path.display()));
+        assert_eq!(editor.current, opened, "file is already open");
+
+        let spelled_differently = path
+            .parent()
+            .unwrap()
+            .join(".")
+            .join(path.file_name().unwrap());
+        editor.current = scratch;
+        execute(&mut editor, &format!("e {}", spelled_differently.display()));
+        assert_eq!(editor.current, opened, "same file under another path");
+        assert_eq!(editor.buffers.len(), 2);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mixed_line_breaks_round_trip() {
+        let path = temp_path("mixed_breaks");
+        fs::write(&path, "a\rb\r\nc\n").unwrap();
+
+        let mut editor = test_editor(0, "");
+        editor.open(&path, true).unwrap();
+        assert!(!current!(editor).1.is_modified());
+
+        execute(&mut editor, "w");
+        assert_eq!(fs::read(&path).unwrap(), b"a\rb\r\nc\n");
+
+        fs::write(&path, "a\r\nb\r\nc\n").unwrap();
+        let mut editor = test_editor(0, "");
+        editor.open(&path, true).unwrap();
+        assert!(
+            current!(editor).1.is_modified(),
+            "lone line feed would be written as dos one"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lossy_text_needs_forced_write() {
+        let path = temp_path("lossy");
+        let bytes = b"\xEF\xBB\xBFa\xFF\n";
+        fs::write(&path, bytes).unwrap();
+
+        let mut editor = test_editor(0, "");
+        editor.open(&path, true).unwrap();
+
+        execute(&mut editor, "w");
+        assert_eq!(fs::read(&path).unwrap(), bytes, "malformed byte is kept");
+
+        execute(&mut editor, "reopen latin2");
+        execute(&mut editor, "w");
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            bytes,
+            "valid in the other encoding"
+        );
+
+        execute(&mut editor, "reopen utf-8");
+        execute(&mut editor, "w!");
+        assert_eq!(fs::read(&path).unwrap(), "\u{FEFF}a\u{FFFD}\n".as_bytes());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn quit_refuses_unsaved_changes() {
+        let path = temp_path("quit");
+        let mut editor = test_editor(0, "abc\n");
+        assert!(!current!(editor).1.is_modified());
+
+        feed_keys(&mut editor, "x");
+        assert!(current!(editor).1.is_modified());
+
+        execute(&mut editor, "q");
+        assert!(!editor.should_exit());
+
+        execute(&mut editor, &format!("w {}", path.display()));
+        assert!(!current!(editor).1.is_modified());
+
+        feed_keys(&mut editor, "xu");
+        assert!(!current!(editor).1.is_modified(), "undo back to saved text");
+
+        execute(&mut editor, "q");
+        assert!(editor.should_exit());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn force_quit() {
+        let mut editor = test_editor(0, "abc\n");
+        feed_keys(&mut editor, "x");
+
+        execute(&mut editor, "q!");
+        assert!(editor.should_exit());
+    }
+
+    #[test]
+    fn insert_mode_changes_are_unsaved() {
+        let mut editor = test_editor(0, "abc\n");
+
+        feed_keys(&mut editor, "ia");
+        assert!(current!(editor).1.is_modified());
+    }
+
+    #[test]
+    fn kill_refuses_unsaved_changes() {
+        let mut editor = test_editor(0, "abc\n");
+        let buf_id = editor.current;
+        editor.open_scratch(false);
+
+        feed_keys(&mut editor, "x");
+        execute(&mut editor, "buffer_kill");
+        assert!(editor.buffers.contains_key(&buf_id));
+
+        feed_keys(&mut editor, "u");
+        execute(&mut editor, "buffer_kill");
+        assert!(!editor.buffers.contains_key(&buf_id));
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let path = temp_path("crlf");
+        fs::write(&path, "ab\r\ncd\r\n").unwrap();
+
+        let mut editor = test_editor(0, "");
+        execute(&mut editor, &format!("e {}", path.display()));
+        assert_eq!(current!(editor).1.text(), "ab\ncd\n");
+
+        feed_keys(&mut editor, "lx");
+        execute(&mut editor, "w");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\r\ncd\r\n");
+
+        execute(&mut editor, "set fileformat=unix");
+        execute(&mut editor, "w");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\ncd\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reopen_with_encoding() {
+        let path = temp_path("reopen");
+        fs::write(&path, b"\xB1\n").unwrap();
+
+        let mut editor = test_editor(0, "");
+        execute(&mut editor, &format!("e {}", path.display()));
+
+        execute(&mut editor, "reopen latin1");
+        assert_eq!(current!(editor).1.encoding().name(), "windows-1252");
+        assert_eq!(current!(editor).1.text(), "±\n");
+
+        execute(&mut editor, "reopen iso-8859-2");
+        assert_eq!(current!(editor).1.text(), "ą\n");
+        assert!(!current!(editor).1.is_modified());
+
+        execute(&mut editor, "w");
+        assert_eq!(fs::read(&path).unwrap(), b"\xB1\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn undo_history_survives_reopening() {
+        let path = temp_path("undo_history");
+        let undo_dir = temp_path("undo_dir");
+        fs::write(&path, "abc\n").unwrap();
+
+        let mut editor = test_editor(0, "");
+        editor.undo_dir = Some(undo_dir.clone());
+        execute(&mut editor, &format!("e {}", path.display()));
+        feed_keys(&mut editor, "xx");
+        execute(&mut editor, "w");
+
+        let mut editor = test_editor(0, "");
+        editor.undo_dir = Some(undo_dir.clone());
+        execute(&mut editor, &format!("e {}", path.display()));
+        assert_eq!(current!(editor).1.text(), "c\n");
+        assert!(!current!(editor).1.is_modified());
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "bc\n");
+        assert!(current!(editor).1.is_modified());
+
+        // changed outside of the editor, history no longer applies
+        fs::write(&path, "xyz\n").unwrap();
+        let mut editor = test_editor(0, "");
+        editor.undo_dir = Some(undo_dir.clone());
+        execute(&mut editor, &format!("e {}", path.display()));
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "xyz\n");
+
+        fs::remove_file(path).unwrap();
+        fs::remove_dir_all(undo_dir).unwrap();
+    }
 }
@@ -1,19 +1,154 @@
-use crate::{current_mut, editor::buffer::UpdateBufPositionParams};
+use kaka_core::history::TimeTravel;
+
+use crate::current_mut;
 
 use super::CommandData;
 
 pub fn undo(ctx: &mut CommandData) {
     let (buf, doc) = current_mut!(ctx.editor);
 
-    if let Some(pos) = doc.undo() {
-        buf.update_text_position(doc, pos, UpdateBufPositionParams::inserting_text());
+    if let Some(selections) = doc.undo() {
+        buf.restore_selections(doc, selections);
     }
 }
 
 pub fn redo(ctx: &mut CommandData) {
     let (buf, doc) = current_mut!(ctx.editor);
 
-    if let Some(pos) = doc.redo() {
-        buf.update_text_position(doc, pos, UpdateBufPositionParams::inserting_text());
+    if let Some(selections) = doc.redo() {
+        buf.restore_selections(doc, selections);
+    }
+}
+
+pub fn earlier(ctx: &mut CommandData) {
+    let Some(by) = time_travel(ctx) else { return };
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    if let Some(selections) = doc.earlier(by) {
+        buf.restore_selections(doc, selections);
+    }
+}
+
+pub fn later(ctx: &mut CommandData) {
+    let Some(by) = time_travel(ctx) else { return };
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    if let Some(selections) = doc.later(by) {
+        buf.restore_selections(doc, selections);
+    }
+}
+
+/// Typed time like `5m`, otherwise count of steps
+fn time_travel(ctx: &CommandData) -> Option<TimeTravel> {
+    match ctx.args.string(0) {
+        Some(time) => time.parse().map_err(|err| log::error!("{err}")).ok(),
+        None => Some(TimeTravel::Steps(ctx.count.unwrap_or(1))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use kaka_core::selection::Selection;
+
+    use super::super::test::*;
+    use crate::{
+        client::composer::Context,
+        current,
+        editor::{Editor, ModeKind},
+    };
+
+    fn selections(editor: &Editor) -> Vec<(usize, usize)> {
+        let (buf, _) = current!(editor);
+        buf.selections()
+            .iter()
+            .map(|s| (s.anchor(), s.head()))
+            .collect()
+    }
+
+    #[test]
+    fn undo_restores_visual_marks() {
+        let mut editor = test_keys(1, "abcd\n", "vld");
+        assert_eq!(current!(editor).1.text(), "ad\n");
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "abcd\n");
+        assert_eq!(current!(editor).0.mode(), ModeKind::Normal);
+        assert_eq!(current!(editor).0.visual_marks(), Some((1, 2)));
+        assert_eq!(selections(&editor), [(1, 1)]);
+
+        feed_keys(&mut editor, "<C-r>");
+        assert_eq!(current!(editor).1.text(), "ad\n");
+        assert_eq!(current!(editor).0.mode(), ModeKind::Normal);
+        assert_eq!(selections(&editor), [(1, 1)]);
+    }
+
+    #[test]
+    fn undo_visual_changes_repeatedly() {
+        let mut editor = test_keys(0, "ab\ncd\nef\n", "Vjdvlx");
+        assert_eq!(current!(editor).1.text(), "\n");
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "ef\n");
+        assert_eq!(current!(editor).0.visual_marks(), Some((0, 1)));
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "ab\ncd\nef\n", "not lowercased");
+        assert_eq!(current!(editor).0.mode(), ModeKind::Normal);
+        assert_eq!(selections(&editor), [(0, 0)]);
+
+        let mut editor = test_keys(4, "abc\nabc\n", "<C-v>kld");
+        assert_eq!(current!(editor).1.text(), "c\nc\n");
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "abc\nabc\n");
+        assert_eq!(current!(editor).0.visual_marks(), Some((0, 5)));
+        assert_eq!(selections(&editor), [(0, 0)]);
+    }
+
+    #[test]
+    fn undo_restores_cursors() {
+        let mut editor = test_keys(0, "ab\ncd\n", "<C-j>x");
+        assert_eq!(current!(editor).1.text(), "b\nd\n");
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "ab\ncd\n");
+        assert_eq!(selections(&editor), [(0, 0), (3, 3)]);
+        assert_eq!(
+            current!(editor).0.selections().primary(),
+            Selection::at_pos(3)
+        );
+    }
+
+    #[test]
+    fn undo_insertion_restores_cursor_before_it() {
+        let mut editor = test_keys(1, "ab\n", "Axyz<ESC>");
+        assert_eq!(current!(editor).1.text(), "abxyz\n");
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(selections(&editor), [(1, 1)]);
+
+        feed_keys(&mut editor, "<C-r>");
+        assert_eq!(selections(&editor), [(4, 4)]);
+    }
+
+    #[test]
+    fn chronological_history_keeps_branches() {
+        let mut editor = test_keys(0, "abc", "xulx");
+        assert_eq!(current!(editor).1.text(), "ac");
+
+        feed_keys(&mut editor, "g-");
+        assert_eq!(current!(editor).1.text(), "bc", "undone branch");
+
+        feed_keys(&mut editor, "g-");
+        assert_eq!(current!(editor).1.text(), "abc");
+
+        feed_keys(&mut editor, "2g+");
+        assert_eq!(current!(editor).1.text(), "ac");
+
+        Context {
+            editor: &mut editor,
+        }
+        .execute_command_line("earlier 10m");
+        assert_eq!(current!(editor).1.text(), "abc");
     }
 }
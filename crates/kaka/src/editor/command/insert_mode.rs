@@ -1,5 +1,17 @@
-use crossterm::event::{KeyCode, KeyEvent};
-use kaka_core::{document::TransactionLeave, transaction::Transaction};
+use std::ops::Range;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use kaka_core::{
+    document::TransactionLeave,
+    graphemes::{
+        self, ensure_grapheme_boundary_prev, next_grapheme_boundary, prev_grapheme_boundary,
+    },
+    indent::{leading_whitespace, Indent},
+    movement::{prev_word_start, WordKind},
+    ropey::RopeSlice,
+    selection::Selection,
+    transaction::{Assoc, Transaction},
+};
 
 use crate::{
     current_mut,
@@ -8,48 +20,766 @@ use crate::{
 
 use super::CommandData;
 
+/// Change made by key at one cursor
+enum Edit {
+    /// Text in range is replaced, cursor follows the change
+    Replace(Range<usize>, String),
+    /// Cursor moves, `keep_column` leaves its saved column for moves between lines
+    Move { pos: usize, keep_column: bool },
+}
+
 pub fn insert_mode_on_key(ctx: &mut CommandData, event: KeyEvent) {
+    // unmapped keys with modifiers are not typed
+    if event
+        .modifiers
+        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    {
+        return;
+    }
+
+    let indent = ctx.editor.indent();
+
+    edit_cursors(ctx, |text, selection| {
+        let pos = selection.head();
+
+        let edit = match event.code {
+            KeyCode::Char(c) => type_char(text, pos, c, &indent),
+            KeyCode::Enter => Edit::Replace(pos..pos, format!("\n{}", indent.new_line(text, pos))),
+            KeyCode::Tab => Edit::Replace(pos..pos, indent.unit.clone()),
+            KeyCode::Backspace if pos > 0 => {
+                Edit::Replace(prev_grapheme_boundary(text, pos)..pos, String::new())
+            }
+            KeyCode::Delete if pos < text.len_chars() => {
+                Edit::Replace(pos..next_grapheme_boundary(text, pos), String::new())
+            }
+            code => return move_on_key(text, selection, code),
+        };
+
+        Some(edit)
+    });
+}
+
+/// Cursor moved by arrow keys, `Home` and `End` while typing
+fn move_on_key(text: RopeSlice, selection: &Selection, code: KeyCode) -> Option<Edit> {
+    let pos = selection.head();
+    let line_idx = text.char_to_line(pos);
+    let line_start = text.line_to_char(line_idx);
+    let line_end = line_end(text, line_idx);
+    let last_line = text.char_to_line(text.len_chars().saturating_sub(1));
+
+    let moved = |pos| Edit::Move {
+        pos,
+        keep_column: false,
+    };
+
+    let edit = match code {
+        KeyCode::Left if pos > line_start => moved(prev_grapheme_boundary(text, pos)),
+        KeyCode::Right if pos < line_end => moved(next_grapheme_boundary(text, pos)),
+        KeyCode::Home => moved(line_start),
+        KeyCode::End => moved(line_end),
+        KeyCode::Up if line_idx > 0 => vertical_move(text, line_idx - 1, selection.column()),
+        KeyCode::Down if line_idx < last_line => {
+            vertical_move(text, line_idx + 1, selection.column())
+        }
+        _ => return None,
+    };
+
+    Some(edit)
+}
+
+/// Line with only indentation before the typed character goes one level back once it starts
+/// with word closing a block, like `}`
+fn type_char(text: RopeSlice, pos: usize, c: char, indent: &Indent) -> Edit {
+    if indent.decrease_before.is_empty() {
+        return Edit::Replace(pos..pos, c.to_string());
+    }
+
+    let line_start = text.line_to_char(text.char_to_line(pos));
+    let mut typed = text.slice(line_start..pos).to_string();
+    typed.push(c);
+
+    let indentation = leading_whitespace(&typed);
+    let word = &typed[indentation.len()..];
+
+    if indentation.is_empty() || !indent.is_closing(word) {
+        return Edit::Replace(pos..pos, c.to_string());
+    }
+
+    let outdented = format!("{}{word}", indent.outdent(indentation));
+    Edit::Replace(line_start..pos, outdented)
+}
+
+/// `<C-w>`, deletes word before cursors, at line start the line break
+pub fn delete_word_before(ctx: &mut CommandData) {
+    delete_before(ctx, |text, pos| {
+        let line_start = text.line_to_char(text.char_to_line(pos));
+
+        if pos > line_start {
+            prev_word_start(text, pos, 1, WordKind::Word).max(line_start)
+        } else {
+            prev_grapheme_boundary(text, pos)
+        }
+    });
+}
+
+/// `<C-u>`, deletes text before cursors up to indentation of the line, the indentation too
+/// when there is nothing else, at line start the line break
+pub fn delete_to_line_start(ctx: &mut CommandData) {
+    delete_before(ctx, |text, pos| {
+        let line_idx = text.char_to_line(pos);
+        let line_start = text.line_to_char(line_idx);
+        let indent_end = line_start
+            + text
+                .line(line_idx)
+                .chars()
+                .take_while(|c| matches!(c, ' ' | '\t'))
+                .count();
+
+        if pos > indent_end {
+            indent_end
+        } else if pos > line_start {
+            line_start
+        } else {
+            prev_grapheme_boundary(text, pos)
+        }
+    });
+}
+
+/// Deletes text between position given by `start_of` and cursors, replace mode goes back
+/// over it the way backspace does
+fn delete_before(ctx: &mut CommandData, start_of: impl Fn(RopeSlice, usize) -> usize) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    if buf.mode() == ModeKind::Replace {
+        let text = doc.text().slice(..);
+
+        // graphemes between start and cursor, each one takes a backspace
+        let counts = buf
+            .selections()
+            .iter()
+            .map(|selection| {
+                let pos = selection.head();
+                let mut start = start_of(text, pos);
+                let mut count = 0;
+
+                while start < pos {
+                    start = next_grapheme_boundary(text, start);
+                    count += 1;
+                }
+
+                count
+            })
+            .collect::<Vec<_>>();
+
+        let steps = counts.iter().copied().max().unwrap_or(0);
+        for step in 0..steps {
+            overwrite(ctx, |idx| {
+                counts
+                    .get(idx)
+                    .is_some_and(|&count| count > step)
+                    .then_some(KeyCode::Backspace)
+            });
+        }
+
+        return;
+    }
+
+    edit_cursors(ctx, |text, selection| {
+        let pos = selection.head();
+        let start = start_of(text, pos);
+
+        (start < pos).then(|| Edit::Replace(start..pos, String::new()))
+    });
+}
+
+/// `<C-r>`, inserts text of register named by the key argument, every cursor gets its own
+/// value when their numbers match
+pub fn insert_register(ctx: &mut CommandData) {
+    let Some(KeyEvent {
+        code: KeyCode::Char(name),
+        ..
+    }) = ctx.key_argument
+    else {
+        return;
+    };
+
+    let Some(value) = ctx.editor.registers.get(name) else {
+        return;
+    };
+
+    let (buf, _) = current_mut!(ctx.editor);
+    let per_cursor = value.values.len() == buf.selections().len();
+    let joined = value.joined();
+    let mut values = value.values;
+
+    // text is typed over the one under cursors
+    if buf.mode() == ModeKind::Replace {
+        let typed = (0..buf.selections().len())
+            .map(|idx| {
+                let value = if per_cursor { &values[idx] } else { &joined };
+                value.chars().collect()
+            })
+            .collect::<Vec<Vec<_>>>();
+
+        let steps = typed.iter().map(Vec::len).max().unwrap_or(0);
+        for step in 0..steps {
+            overwrite(ctx, |idx| {
+                typed.get(idx)?.get(step).map(|&c| match c {
+                    '\n' => KeyCode::Enter,
+                    c => KeyCode::Char(c),
+                })
+            });
+        }
+
+        return;
+    }
+
+    edit_cursors(ctx, |_, selection| {
+        let pos = selection.head();
+
+        // cursors are visited from the last one
+        let text = if per_cursor {
+            values.pop().unwrap_or_default()
+        } else {
+            joined.clone()
+        };
+
+        Some(Edit::Replace(pos..pos, text))
+    });
+}
+
+/// Position of line break ending the line, the end of text on the last line
+fn line_end(text: RopeSlice, line_idx: usize) -> usize {
+    let line_start = text.line_to_char(line_idx);
+    let mut end = text.line_to_char(line_idx + 1);
+
+    for line_break in ['\n', '\r'] {
+        if end > line_start && text.char(end - 1) == line_break {
+            end -= 1;
+        }
+    }
+
+    end
+}
+
+/// Moves to saved column of the cursor on another line, or to its end when it's shorter
+fn vertical_move(text: RopeSlice, line_idx: usize, column: usize) -> Edit {
+    let pos = (text.line_to_char(line_idx) + column).min(line_end(text, line_idx));
+
+    Edit::Move {
+        pos: ensure_grapheme_boundary_prev(text, pos),
+        keep_column: true,
+    }
+}
+
+/// Applies edits of all cursors to the insertion in progress, cursors are visited from the
+/// last one so that positions of preceding ones stay valid
+fn edit_cursors(
+    ctx: &mut CommandData,
+    mut edit: impl FnMut(RopeSlice, &Selection) -> Option<Edit>,
+) {
     let (buf, doc) = current_mut!(ctx.editor);
 
-    debug_assert!(matches!(buf.mode(), ModeKind::Insert));
+    // text overwritten in replace mode is tracked by `overwrite`, it only moves cursors with
+    // this
+    if !matches!(buf.mode(), ModeKind::Insert | ModeKind::Replace) {
+        return;
+    }
 
     doc.with_transaction(|doc, insert_tx| {
         let text = doc.text_mut();
+        let selections = buf.selections().iter().copied().collect::<Vec<_>>();
 
-        let pos = buf.text_pos();
-        let mut tx = Transaction::new(text, pos);
+        let mut tx = Transaction::new(text, selections[selections.len() - 1].head());
+        let mut moves = vec![None; selections.len()];
 
-        match event.code {
-            KeyCode::Char(c) => {
-                tx.insert_char(c);
-            }
-            KeyCode::Backspace => {
-                if pos > 0 {
-                    tx.move_backward_by(1);
-                    tx.delete(1);
+        for (idx, selection) in selections.iter().enumerate().rev() {
+            match edit(text.slice(..), selection) {
+                Some(Edit::Replace(range, replacement)) => {
+                    tx.move_to(range.start);
+                    tx.replace(range.len(), replacement);
                 }
+                Some(Edit::Move { pos, keep_column }) => moves[idx] = Some((pos, keep_column)),
+                None => (),
             }
-            KeyCode::Enter => {
-                tx.insert_char('\n');
-            }
-            KeyCode::Left => {
-                if pos > 0 {
-                    tx.move_backward_by(1);
+        }
+
+        tx.apply(text);
+        buf.map_cursors(doc, &tx, Assoc::After);
+
+        let mut moves = moves.into_iter();
+        buf.for_each_cursor(doc, |buf| {
+            let (pos, keep_column) = match moves.next().flatten() {
+                Some((pos, keep_column)) => (tx.map_pos(pos, Assoc::After), keep_column),
+                None => (buf.text_pos(), false),
+            };
+
+            let params = UpdateBufPositionParams {
+                update_saved_column: !keep_column,
+                ..UpdateBufPositionParams::inserting_text()
+            };
+
+            buf.update_text_position(doc, pos, params);
+        });
+
+        insert_tx.merge(tx);
+
+        TransactionLeave::Keep
+    });
+}
+
+/// Inserts text typed by each of more cursors again, count of insertion with single cursor
+/// is applied by its transaction instead
+pub(super) fn repeat_insertion(ctx: &mut CommandData) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    let Some((count, starts)) = buf.take_insert_repeat() else {
+        return;
+    };
+
+    if starts.len() != buf.selections().len() {
+        return log::warn!("Count of insertion ignored, its cursors were merged");
+    }
+
+    let mut typed = Vec::with_capacity(starts.len());
+
+    doc.with_transaction(|doc, tx| {
+        let text = doc.text();
+
+        typed = buf
+            .selections()
+            .iter()
+            .zip(&starts)
+            .map(|(selection, start)| {
+                let end = selection.head();
+                let start = tx.map_pos(*start, Assoc::Before).min(end);
+
+                text.slice(start..end).to_string().repeat(count - 1)
+            })
+            .collect();
+
+        TransactionLeave::Keep
+    });
+
+    // cursors are visited from the last one
+    let mut typed = typed.into_iter().rev();
+
+    edit_cursors(ctx, |_, selection| {
+        let pos = selection.head();
+        let text = typed.next()?;
+
+        (!text.is_empty()).then_some(Edit::Replace(pos..pos, text))
+    });
+}
+
+/// Appends spaces to lines of cursors ending before display `column`, cursors stay after them
+pub(super) fn pad_to_column(ctx: &mut CommandData, column: usize) {
+    edit_cursors(ctx, |text, selection| {
+        let pos = selection.head();
+        let line_idx = text.char_to_line(pos);
+        let line_start = text.line_to_char(line_idx);
+        let width = graphemes::column(text.line(line_idx), line_end(text, line_idx) - line_start);
+
+        (width < column).then(|| Edit::Replace(pos..pos, " ".repeat(column - width)))
+    });
+}
+
+/// Typed characters overwrite graphemes under cursors, backspace restores them
+pub fn replace_mode_on_key(ctx: &mut CommandData, event: KeyEvent) {
+    // unmapped keys with modifiers are not typed
+    if event
+        .modifiers
+        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    {
+        return;
+    }
+
+    let is_move = matches!(
+        event.code,
+        KeyCode::Left | KeyCode::Right | KeyCode::Home | KeyCode::End | KeyCode::Up | KeyCode::Down
+    );
+
+    // text left behind stays replaced, backspace only moves over it
+    if is_move {
+        if let Some(overwritten) = current_mut!(ctx.editor).0.overwritten_mut() {
+            overwritten.clear();
+        }
+
+        edit_cursors(ctx, |text, selection| {
+            move_on_key(text, selection, event.code)
+        });
+        return;
+    }
+
+    overwrite(ctx, |_| Some(event.code));
+}
+
+/// Key of replace mode pressed at every cursor `key_of` gives one for, by index
+fn overwrite(ctx: &mut CommandData, key_of: impl Fn(usize) -> Option<KeyCode>) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    let Some(overwritten) = buf.overwritten_mut() else {
+        return;
+    };
+    let mut overwritten = std::mem::take(overwritten);
+
+    doc.with_transaction(|doc, replace_tx| {
+        let text = doc.text_mut();
+
+        let cursors = buf
+            .selections()
+            .iter()
+            .map(|s| s.head())
+            .collect::<Vec<_>>();
+
+        overwritten.resize_with(cursors.len(), Vec::new);
+
+        // go backwards, so positions of preceding cursors stay valid
+        let mut tx = Transaction::new(text, cursors[cursors.len() - 1]);
+        // cursors moved back by backspace, edits move them forward
+        let mut offsets = vec![0isize; cursors.len()];
+
+        for (idx, pos) in cursors.iter().copied().enumerate().rev() {
+            let slice = text.slice(..);
+
+            let Some(code) = key_of(idx) else {
+                continue;
+            };
+
+            match code {
+                KeyCode::Char(c) => {
+                    // line break is not overwritten, text is added at the end of line
+                    let end = match slice.get_char(pos) {
+                        None | Some('\n' | '\r') => pos,
+                        Some(_) => next_grapheme_boundary(slice, pos),
+                    };
+
+                    overwritten[idx].push(slice.slice(pos..end).to_string());
+                    tx.move_to(pos);
+                    tx.replace(end - pos, c.encode_utf8(&mut [0; 4]) as &str);
                 }
-            }
-            KeyCode::Right => {
-                if pos < text.len_chars() - 1 {
-                    tx.move_forward_by(1);
+                KeyCode::Enter => {
+                    overwritten[idx].push(String::new());
+                    tx.move_to(pos);
+                    tx.insert_char('\n');
                 }
+                KeyCode::Backspace if pos > 0 => {
+                    let start = prev_grapheme_boundary(slice, pos);
+
+                    match overwritten[idx].pop() {
+                        Some(original) => {
+                            offsets[idx] = -(original.chars().count() as isize);
+                            tx.move_to(start);
+                            tx.replace(pos - start, original);
+                        }
+                        // text typed before replace mode is kept
+                        None => offsets[idx] = -((pos - start) as isize),
+                    }
+                }
+                _ => (),
             }
-            _ => { /* TODO */ }
-        };
+        }
 
-        let pos = tx.apply(text);
-        buf.update_text_position(doc, pos, UpdateBufPositionParams::inserting_text());
+        tx.apply(text);
+        buf.map_cursors(doc, &tx, Assoc::After);
 
-        insert_tx.merge(tx);
+        let mut offsets = offsets.into_iter();
+        buf.for_each_cursor(doc, |buf| {
+            let offset = offsets.next().unwrap_or(0);
+            let pos = buf.text_pos().saturating_add_signed(offset);
+
+            buf.update_text_position(doc, pos, UpdateBufPositionParams::inserting_text());
+        });
+
+        replace_tx.merge(tx);
 
         TransactionLeave::Keep
     });
+
+    if let Some(stacks) = buf.overwritten_mut() {
+        *stacks = overwritten;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::KeyModifiers;
+
+    use super::super::test::*;
+    use super::*;
+    use crate::{
+        current,
+        editor::command::{
+            add_cursor_below, switch_to_insert_mode_inplace, switch_to_normal_mode, undo,
+        },
+    };
+
+    fn type_x(ctx: &mut CommandData) {
+        insert_mode_on_key(ctx, KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE));
+    }
+
+    fn backspace(ctx: &mut CommandData) {
+        insert_mode_on_key(ctx, KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn insert_all_cursors() {
+        let text = "012\n456\n";
+
+        test_cmds(
+            1,
+            text,
+            &[
+                add_cursor_below,
+                switch_to_insert_mode_inplace,
+                type_x,
+                type_x,
+            ],
+            |buf: B, doc: D| {
+                assert_eq!(doc.text(), "0xx12\n4xx56\n");
+                assert_eq!(
+                    buf.selections().ranges().collect::<Vec<_>>(),
+                    [(3, 3), (9, 9)]
+                );
+            },
+        );
+
+        test_cmds(
+            1,
+            text,
+            &[
+                add_cursor_below,
+                switch_to_insert_mode_inplace,
+                type_x,
+                backspace,
+                backspace,
+            ],
+            |buf: B, doc: D| {
+                assert_eq!(doc.text(), "12\n56\n");
+                assert_eq!(
+                    buf.selections().ranges().collect::<Vec<_>>(),
+                    [(0, 0), (3, 3)]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn insert_session_is_single_undo_step() {
+        test_cmds(
+            1,
+            "012\n456\n",
+            &[
+                add_cursor_below,
+                switch_to_insert_mode_inplace,
+                type_x,
+                backspace,
+                type_x,
+                switch_to_normal_mode,
+                undo,
+            ],
+            |_: B, doc: D| {
+                assert_eq!(doc.text(), "012\n456\n");
+            },
+        );
+    }
+
+    #[test]
+    fn editing_keys() {
+        let editor = test_keys(0, "abc\n", "i<DEL><ESC>");
+        assert_eq!(current!(editor).1.text(), "bc\n");
+
+        let editor = test_keys(2, "e\u{301}b\n", "i<BS><ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "b\n",
+            "whole grapheme is deleted"
+        );
+
+        let editor = test_keys(1, "abc\n", "i<END>x<HOME>y<ESC>");
+        assert_eq!(current!(editor).1.text(), "yabcx\n");
+
+        let editor = test_keys(1, "abc\n", "i<TAB><ESC>");
+        assert_eq!(current!(editor).1.text(), "a    bc\n");
+
+        let editor = test_keys(0, "ab\n", "ia<C-x>b<ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "abab\n",
+            "unmapped key is not typed"
+        );
+    }
+
+    #[test]
+    fn vertical_moves_keep_column() {
+        let editor = test_keys(3, "abcd\nx\nabcd\n", "i<DOWN><DOWN>y<ESC>");
+        assert_eq!(current!(editor).1.text(), "abcd\nx\nabcyd\n");
+
+        let editor = test_keys(1, "abcd\nx\n", "i<UP>y<DOWN><DOWN>z<ESC>");
+        assert_eq!(current!(editor).1.text(), "aybcd\nxz\n");
+    }
+
+    #[test]
+    fn delete_before_cursor() {
+        let editor = test_keys(0, "ab cd\n", "A<C-w>z<ESC>");
+        assert_eq!(current!(editor).1.text(), "ab z\n");
+
+        let editor = test_keys(0, "ab\n", "Ax.yz<C-w><C-w><ESC>");
+        assert_eq!(current!(editor).1.text(), "abx\n");
+
+        let editor = test_keys(0, "ab\ncd\n", "jI<C-w><ESC>");
+        assert_eq!(current!(editor).1.text(), "abcd\n", "line break is deleted");
+
+        let editor = test_keys(0, "  ab cd\n", "A<C-u><ESC>");
+        assert_eq!(current!(editor).1.text(), "  \n", "indentation is kept");
+
+        let editor = test_keys(0, "  ab\n", "A<C-u><C-u><ESC>");
+        assert_eq!(current!(editor).1.text(), "\n");
+    }
+
+    #[test]
+    fn insert_register() {
+        let editor = test_keys(0, "ab\n", "yli<C-r>\"<ESC>");
+        assert_eq!(current!(editor).1.text(), "aab\n");
+
+        let editor = test_keys(0, "ab\n", "i<C-r>xc<ESC>");
+        assert_eq!(current!(editor).1.text(), "cab\n", "empty register");
+    }
+
+    #[test]
+    fn normal_command_from_insert_mode() {
+        let editor = test_keys(0, "abc def\n", "ix<C-o>wy<ESC>");
+        assert_eq!(current!(editor).1.text(), "xabc ydef\n");
+
+        let editor = test_keys(0, "abc def\n", "ix<C-o>dwy<ESC>");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "xydef\n");
+        assert!(buf.mode() == ModeKind::Normal);
+
+        let editor = test_keys(0, "abc\n", "ix<C-o>u");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "abc\n", "insertion is undone on its own");
+        assert!(buf.mode() == ModeKind::Insert);
+    }
+
+    #[test]
+    fn auto_indent() {
+        let editor = test_keys(0, "  ab\n", "A<CR>x<ESC>");
+        assert_eq!(current!(editor).1.text(), "  ab\n  x\n");
+
+        let editor = test_keys(3, "  ab\n", "i<CR><ESC>");
+        assert_eq!(current!(editor).1.text(), "  a\n  b\n");
+
+        let path = temp_path("indent.rs");
+        std::fs::write(&path, "fn a() {\n}\n").unwrap();
+
+        let mut editor = test_editor(0, "");
+        editor.open(&path, true).unwrap();
+        feed_keys(&mut editor, "A<CR>x;<CR>if<TAB>{<CR>}<ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "fn a() {\n    x;\n    if    {\n    }\n}\n"
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn replace_mode() {
+        let editor = test_keys(0, "abc\n", "Rxy<ESC>");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "xyc\n");
+        assert_eq!(buf.text_pos(), 1);
+
+        let editor = test_keys(0, "ab\n", "Rxyz<ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "xyz\n",
+            "line break is not overwritten"
+        );
+
+        let editor = test_keys(0, "e\u{301}b\n", "Rx<ESC>");
+        assert_eq!(current!(editor).1.text(), "xb\n");
+
+        let editor = test_keys(1, "abcd\n", "Rxy<ESC>l.");
+        assert_eq!(current!(editor).1.text(), "axyxy\n");
+
+        let editor = test_keys(0, "abcd\n", "Rxy<ESC>u");
+        assert_eq!(current!(editor).1.text(), "abcd\n");
+    }
+
+    #[test]
+    fn replace_mode_backspace() {
+        let editor = test_keys(0, "ab\n", "Rxyz<BS><BS><BS><ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "ab\n",
+            "original text is restored"
+        );
+
+        let editor = test_keys(0, "e\u{301}b\n", "Rxy<BS><BS><ESC>");
+        assert_eq!(current!(editor).1.text(), "e\u{301}b\n");
+
+        let editor = test_keys(1, "ab\n", "R<BS>x<ESC>");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "xb\n", "text before replace mode is kept");
+        assert_eq!(buf.text_pos(), 0);
+    }
+
+    #[test]
+    fn replace_mode_moves() {
+        let editor = test_keys(1, "abc\n", "R<LEFT>x<ESC>");
+        assert_eq!(current!(editor).1.text(), "xbc\n");
+
+        let editor = test_keys(0, "abcd\n", "Rx<RIGHT>y<ESC>");
+        assert_eq!(current!(editor).1.text(), "xbyd\n");
+
+        let editor = test_keys(0, "abc\nde\n", "Rx<DOWN>y<UP><HOME>z<ESC>");
+        assert_eq!(current!(editor).1.text(), "zbc\ndy\n");
+
+        let editor = test_keys(0, "abc\n", "Rx<END>y<ESC>");
+        assert_eq!(current!(editor).1.text(), "xbcy\n");
+
+        let editor = test_keys(0, "abc\n", "Rxy<LEFT><BS>z<ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "zyc\n",
+            "text left behind is not restored"
+        );
+
+        let editor = test_keys(0, "abc\n", "Rx<RIGHT>y<ESC>u");
+        assert_eq!(current!(editor).1.text(), "abc\n");
+
+        let editor = test_keys(0, "123\n", "Ra<C-x>b<ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "ab3\n",
+            "unmapped key is not typed"
+        );
+    }
+
+    #[test]
+    fn replace_mode_mappings() {
+        let editor = test_keys(0, "abcd ef\n", "Rxy<C-w>z<ESC>");
+        assert_eq!(current!(editor).1.text(), "zbcd ef\n");
+
+        let editor = test_keys(3, "ab cd\n", "Rxy<C-u><ESC>");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "ab cd\n", "overwritten text is restored");
+        assert_eq!(buf.text_pos(), 0);
+
+        let editor = test_keys(3, "abcd\n", "ylhhhR<C-r>\"<ESC>");
+        assert_eq!(current!(editor).1.text(), "dbcd\n");
+
+        let editor = test_keys(3, "abcd\n", "ylhhhR<C-r>\"<BS><ESC>");
+        assert_eq!(current!(editor).1.text(), "abcd\n");
+
+        let editor = test_keys(0, "abcd ef\n", "R<C-o>ex<ESC>");
+        assert_eq!(current!(editor).1.text(), "abcx ef\n");
+
+        let editor = test_keys(0, "abcd\n", "Rx<C-o>lz");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "xbzd\n");
+        assert!(buf.mode() == ModeKind::Replace);
+    }
 }
@@ -0,0 +1,137 @@
+use crossterm::event::KeyCode;
+
+use crate::editor::{
+    utils::{format_key, parse_keys},
+    Registers,
+};
+
+use super::CommandData;
+
+/// `q{register}` records typed keys into register, `q` stops the recording
+///
+/// Recording with a key that can't be written down, like `CapsLock`, is dropped.
+pub fn record_macro(ctx: &mut CommandData) {
+    if let Some((register, keys)) = ctx.editor.macros.stop_recording() {
+        match keys.into_iter().map(format_key).collect::<Option<String>>() {
+            Some(text) => ctx.editor.registers.record(register, text),
+            None => log::error!("Recording to register {register} aborted, key has no notation"),
+        }
+
+        return;
+    }
+
+    match ctx.key_argument.map(|key| key.code) {
+        Some(KeyCode::Char(register)) if Registers::is_valid(register) => {
+            ctx.editor.macros.start_recording(register);
+        }
+        _ => (),
+    }
+}
+
+/// `@{register}` replays keys stored in register, `@@` the last replayed macro again
+///
+/// Macro can't replay itself, not even as the last key to repeat until a motion fails.
+pub fn replay_macro(ctx: &mut CommandData) {
+    let register = match ctx.key_argument.map(|key| key.code) {
+        Some(KeyCode::Char('@')) => match ctx.editor.macros.last_replayed() {
+            Some(register) => register,
+            None => {
+                log::error!("No macro replayed yet");
+                return;
+            }
+        },
+        Some(KeyCode::Char(register)) if Registers::is_valid(register) => register,
+        _ => return,
+    };
+
+    let Some(value) = ctx.editor.registers.get(register) else {
+        log::error!("Nothing in register {register}");
+        return;
+    };
+
+    let keys = match parse_keys(&value.joined()) {
+        Ok(keys) => keys,
+        Err(err) => {
+            log::error!("Can't replay register {register}: {err}");
+            return;
+        }
+    };
+
+    let count = ctx.count.unwrap_or(1);
+    if !ctx.editor.macros.replay(register, keys, count) {
+        log::error!("Macro in register {register} calls itself");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::{Event, KeyEvent, KeyModifiers};
+    use kaka_core::shapes::Rect;
+
+    use super::super::test::*;
+    use super::*;
+    use crate::{
+        client::composer::{Composer, Context, EditorWidget},
+        current,
+        editor::Editor,
+    };
+
+    /// Keys go through composer, which replays macros
+    fn type_keys(editor: &mut Editor, keys: &str) {
+        let mut composer = Composer::new(Rect::new(0, 0, 80, 24));
+        composer.push_widget(EditorWidget::default());
+        let mut context = Context { editor };
+
+        for key in parse_keys(keys).unwrap() {
+            composer.handle_event(Event::Key(key), &mut context);
+        }
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut editor = test_editor(0, "abc\nabc\nabc\nabc\n");
+        type_keys(&mut editor, "qaxjq@a@@");
+        assert_eq!(current!(editor).1.text(), "bc\nbc\nbc\nabc\n");
+
+        let macro_text = editor.registers.get('a').map(|value| value.joined());
+        assert_eq!(macro_text.as_deref(), Some("xj"));
+        let unnamed = editor.registers.get('"').map(|value| value.joined());
+        assert_eq!(unnamed.as_deref(), Some("a"), "recording doesn't yank");
+
+        let mut editor = test_editor(0, "1\n2\n3\n4\n");
+        type_keys(&mut editor, "qaI- <ESC>jq2@a");
+        assert_eq!(current!(editor).1.text(), "- 1\n- 2\n- 3\n4\n");
+    }
+
+    #[test]
+    fn edited_macro() {
+        let mut editor = test_editor(0, "dw\none two\n");
+        type_keys(&mut editor, "\"byyj@b");
+        assert_eq!(current!(editor).1.text(), "dw\ntwo\n");
+    }
+
+    #[test]
+    fn key_without_notation() {
+        let mut editor = test_editor(0, "abc\n");
+        type_keys(&mut editor, "qax");
+
+        let caps_lock = KeyEvent::new(KeyCode::CapsLock, KeyModifiers::NONE);
+        editor.macros.record(caps_lock);
+        type_keys(&mut editor, "q");
+
+        assert!(editor.registers.get('a').is_none(), "recording aborted");
+        assert_eq!(editor.macros.recording(), None);
+
+        let mut editor = test_editor(0, "abc\n");
+        type_keys(&mut editor, "qa<PAGEDOWN><C-INSERT>q");
+        let macro_text = editor.registers.get('a').map(|value| value.joined());
+        assert_eq!(macro_text.as_deref(), Some("<PAGEDOWN><C-INSERT>"));
+    }
+
+    #[test]
+    fn recursive_macro() {
+        let mut editor = test_editor(0, "abcd\n");
+        type_keys(&mut editor, "qax@aq@a");
+        assert_eq!(current!(editor).1.text(), "cd\n");
+    }
+}
@@ -1,22 +1,47 @@
+mod args;
 mod buffer_mgmt;
 mod history;
 mod insert_mode;
+mod macros;
 mod mode_switch;
 mod movement;
+mod multi_cursor;
+mod operator;
+mod options;
+mod range;
+mod recovery;
 pub mod registry;
+mod reload;
+mod repeat;
+mod search;
+mod substitute;
 mod text_manipulation;
+mod text_object;
 
+pub use args::{ArgKind, Args, Signature};
 pub use buffer_mgmt::*;
 pub use history::*;
 pub use insert_mode::*;
+pub use macros::*;
 pub use mode_switch::*;
 pub use movement::*;
+pub use multi_cursor::*;
+pub use operator::*;
+pub use options::*;
+pub use recovery::*;
+pub use reload::*;
+pub use repeat::*;
+pub use search::*;
+pub use substitute::*;
 pub use text_manipulation::*;
+pub use text_object::*;
 
 pub use registry::Registry as CommandRegistry;
 
 use std::{borrow::Cow, fmt::Debug};
 
+use crossterm::event::KeyEvent;
+
 use crate::client::composer::{Callback, Widget};
 
 use super::Editor;
@@ -26,6 +51,12 @@ pub type CommandFn = fn(&mut CommandData);
 pub struct CommandData<'a> {
     pub editor: &'a mut Editor,
     pub count: Option<usize>,
+    /// Register selected with `"` before the command
+    pub register: Option<char>,
+    /// Key pressed after mapping of command awaiting argument
+    pub key_argument: Option<KeyEvent>,
+    /// Arguments typed after command name in command mode
+    pub args: Args,
     pub callback: Option<Callback>,
 }
 
@@ -37,12 +68,29 @@ impl<'a> CommandData<'a> {
     }
 }
 
+/// Describes which text is covered by a motion when used after an operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// Character under the target position is not included
+    Exclusive,
+    /// Character under the target position is included
+    Inclusive,
+    /// Whole lines between start and target position are included
+    Linewise,
+    /// Inclusive when moving forward, exclusive when moving backward, like `f` and `F`
+    Find,
+}
+
 #[derive(Clone)]
 pub struct Command {
     name: Cow<'static, str>,
     aliases: Vec<Cow<'static, str>>,
     typable: bool,
     mappable: bool,
+    motion_kind: Option<MotionKind>,
+    key_argument: bool,
+    repeatable: bool,
+    signature: Signature,
     fun: CommandFn,
 }
 
@@ -52,6 +100,10 @@ impl PartialEq for Command {
             && self.aliases == other.aliases
             && self.typable == other.typable
             && self.mappable == other.mappable
+            && self.motion_kind == other.motion_kind
+            && self.key_argument == other.key_argument
+            && self.repeatable == other.repeatable
+            && self.signature == other.signature
             && std::ptr::eq(
                 self.fun as *const fn(&mut CommandData),
                 other.fun as *const _,
@@ -73,9 +125,39 @@ impl Command {
             fun,
             mappable,
             typable,
+            motion_kind: None,
+            key_argument: false,
+            repeatable: false,
+            signature: Signature::new(),
         }
     }
 
+    /// Arguments accepted when command is typed in command mode
+    #[must_use]
+    pub fn with_signature(mut self, signature: Signature) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// Command waits for the next key, which is passed as `CommandData::key_argument`
+    pub const fn with_key_argument(mut self) -> Self {
+        self.key_argument = true;
+        self
+    }
+
+    /// Marks command as a motion, it can be used to select text for pending operator
+    pub const fn with_motion_kind(mut self, kind: MotionKind) -> Self {
+        self.motion_kind = Some(kind);
+        self
+    }
+
+    /// Command changes text, it is repeated by `.` along with its motion or text typed in
+    /// insert mode it started
+    pub const fn with_repeat(mut self) -> Self {
+        self.repeatable = true;
+        self
+    }
+
     pub fn call(&self, context: &mut CommandData) {
         (self.fun)(context);
     }
@@ -95,6 +177,22 @@ impl Command {
     pub fn aliases(&self) -> &[Cow<'static, str>] {
         &self.aliases
     }
+
+    pub const fn motion_kind(&self) -> Option<MotionKind> {
+        self.motion_kind
+    }
+
+    pub const fn key_argument(&self) -> bool {
+        self.key_argument
+    }
+
+    pub const fn repeatable(&self) -> bool {
+        self.repeatable
+    }
+
+    pub const fn signature(&self) -> &Signature {
+        &self.signature
+    }
 }
 
 impl Debug for Command {
@@ -102,6 +200,10 @@ impl Debug for Command {
         f.debug_struct("Command")
             .field("name", &self.name)
             .field("aliases", &self.aliases)
+            .field("motion_kind", &self.motion_kind)
+            .field("key_argument", &self.key_argument)
+            .field("repeatable", &self.repeatable)
+            .field("signature", &self.signature)
             .field("fun", &(self.fun as *const CommandFn))
             .finish()
     }
@@ -111,11 +213,15 @@ impl Debug for Command {
 pub mod test {
     use super::*;
 
+    use std::path::PathBuf;
+
+    use crossterm::event::Event;
     use kaka_core::{document::Document, ropey::Rope};
 
     use crate::{
+        client::composer::{Context, EditorWidget, Widget},
         current,
-        editor::{Buffer, Editor},
+        editor::{utils::parse_mapping, Buffer, Editor},
     };
 
     // to save characters typed :P
@@ -127,6 +233,15 @@ pub mod test {
         text: impl AsRef<str>,
         command: fn(&mut CommandData),
         check: C,
+    ) {
+        test_cmds(start_position, text, &[command], check);
+    }
+
+    pub fn test_cmds<C: FnOnce(&Buffer, &Document)>(
+        start_position: usize,
+        text: impl AsRef<str>,
+        commands: &[fn(&mut CommandData)],
+        check: C,
     ) {
         let mut editor = Editor::init();
 
@@ -140,13 +255,57 @@ pub mod test {
         let mut data = CommandData {
             editor: &mut editor,
             count: Some(1),
+            register: None,
+            key_argument: None,
+            args: Args::default(),
             callback: None,
         };
 
-        command(&mut data);
+        for command in commands {
+            command(&mut data);
+        }
 
         let (buf, doc) = current!(data.editor);
 
         check(buf, doc);
     }
+
+    /// Path in temporary directory unique to the test process, nothing is left there
+    pub fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("kaka-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Editor with single scratch buffer holding `text`
+    pub fn test_editor(start_position: usize, text: impl AsRef<str>) -> Editor {
+        let mut editor = Editor::init();
+
+        let mut document = Document::new_scratch();
+        *document.text_mut() = Rope::from(text.as_ref());
+
+        let buffer = Buffer::new_text(start_position, &document).unwrap();
+
+        editor.add_buffer_and_document(buffer, document, true);
+
+        editor
+    }
+
+    /// Feeds keys to the editor widget, the way user would type them
+    pub fn feed_keys(editor: &mut Editor, keys: &str) {
+        let mut widget = EditorWidget::default();
+        let mut context = Context { editor };
+
+        for key in parse_mapping(keys).unwrap() {
+            widget.handle_event(&Event::Key(key), &mut context);
+        }
+    }
+
+    pub fn test_keys(start_position: usize, text: impl AsRef<str>, keys: &str) -> Editor {
+        let mut editor = test_editor(start_position, text);
+        feed_keys(&mut editor, keys);
+
+        editor
+    }
 }
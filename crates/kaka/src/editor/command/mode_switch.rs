@@ -1,12 +1,18 @@
-use kaka_core::{document::TransactionLeave, graphemes::next_grapheme_boundary};
+use kaka_core::{
+    document::{Document, TransactionLeave},
+    graphemes::next_grapheme_boundary,
+};
 
 use crate::{
     client::composer::PromptWidget,
     current_mut,
-    editor::{buffer::UpdateBufPositionParams, ModeKind},
+    editor::{
+        buffer::{block_columns, UpdateBufPositionParams},
+        Buffer, ModeKind,
+    },
 };
 
-use super::CommandData;
+use super::{pad_to_column, repeat_insertion, CommandData};
 
 #[derive(Debug, Clone, Copy)]
 enum Switch {
@@ -34,86 +40,228 @@ pub fn switch_to_insert_mode_line_end(ctx: &mut CommandData) {
 
 fn switch_to_insert_mode_impl(ctx: &mut CommandData, switch: Switch) {
     use Switch::*;
-    let repeat = ctx.count.unwrap_or(1).max(1);
-
     let (buf, doc) = current_mut!(ctx.editor);
+    let before = buf.selections().clone();
+
+    let count = ctx.count.unwrap_or(1).max(1);
 
     buf.switch_mode(ModeKind::Insert);
 
-    let pos = buf.text_pos();
+    buf.for_each_cursor(doc, |buf| {
+        let pos = buf.text_pos();
 
-    let line = doc.text().line(buf.line_idx());
-    let line_char = buf.line_char();
-    let line_len = line.len_chars();
+        let line = doc.text().line(buf.line_idx());
+        let line_char = buf.line_char();
+        let line_len = line.len_chars();
 
-    let approx_new_pos = match switch {
-        Inplace => pos,
-        LineStart => line_char,
-        After => line_char + next_grapheme_boundary(line, pos - line_char),
-        LineEnd => line_char + line_len,
-    };
+        let approx_new_pos = match switch {
+            Inplace => pos,
+            LineStart => line_char,
+            After => line_char + next_grapheme_boundary(line, pos - line_char),
+            LineEnd => line_char + line_len,
+        };
 
-    let insert_after_cursor = matches!(switch, After | LineEnd);
+        let insert_after_cursor = matches!(switch, After | LineEnd);
 
-    let params = UpdateBufPositionParams {
-        update_saved_column: true,
-        line_keep: insert_after_cursor,
-        allow_on_newline: insert_after_cursor,
-    };
+        let params = UpdateBufPositionParams {
+            update_saved_column: true,
+            line_keep: insert_after_cursor,
+            allow_on_newline: insert_after_cursor,
+        };
 
-    let pos = buf
-        .update_text_position(doc, approx_new_pos, params)
-        .unwrap_or(approx_new_pos);
+        buf.update_text_position(doc, approx_new_pos, params);
+    });
+
+    // transaction repeats insertion of single cursor, more cursors repeat their own text
+    let repeat = if buf.selections().len() > 1 {
+        let starts = buf.selections().iter().map(|s| s.head()).collect();
+        buf.set_insert_repeat(count, starts);
+        1
+    } else {
+        count
+    };
 
-    doc.open_transaction(pos);
+    doc.open_transaction(buf.text_pos(), &before);
     doc.with_transaction(|_, tx| {
         tx.set_repeat(repeat);
         TransactionLeave::Keep
     });
 }
 
+/// `R`, typed text overwrites the text under cursors
+pub fn switch_to_replace_mode(ctx: &mut CommandData) {
+    let (buf, doc) = current_mut!(ctx.editor);
+    let before = buf.selections().clone();
+
+    buf.switch_mode(ModeKind::Replace);
+    doc.open_transaction(buf.text_pos(), &before);
+}
+
 pub fn switch_to_normal_mode(ctx: &mut CommandData) {
+    repeat_insertion(ctx);
     let (buf, doc) = current_mut!(ctx.editor);
 
-    let was_insert = buf.mode().is_insert();
+    let was_insert = matches!(buf.mode(), ModeKind::Insert | ModeKind::Replace);
     buf.switch_mode(ModeKind::Normal);
 
     // move one cell left when exiting insert mode and commit transaction
     if was_insert {
-        buf.update_text_position(
-            doc,
-            buf.text_pos().saturating_sub(1),
-            UpdateBufPositionParams {
-                line_keep: true,
-                ..Default::default()
-            },
-        );
+        buf.for_each_cursor(doc, |buf| {
+            buf.update_text_position(
+                doc,
+                buf.text_pos().saturating_sub(1),
+                UpdateBufPositionParams {
+                    line_keep: true,
+                    ..Default::default()
+                },
+            );
+        });
+
+        commit_insertion(buf, doc);
+    }
+}
 
-        doc.with_transaction(|doc, tx| {
-            tx.apply_repeats(doc.text_mut());
+/// `<C-o>` in insert and replace mode, runs one command of normal mode and continues the
+/// insertion
+pub fn execute_normal_command(ctx: &mut CommandData) {
+    repeat_insertion(ctx);
+    let (buf, doc) = current_mut!(ctx.editor);
 
-            TransactionLeave::Commit
-        });
+    let mode = buf.mode();
+    if !matches!(mode, ModeKind::Insert | ModeKind::Replace) {
+        return;
+    }
+
+    // unlike escape, cursor stays where the insertion ended
+    buf.switch_mode(ModeKind::Normal);
+    buf.set_resume_insert(mode);
+    commit_insertion(buf, doc);
+}
+
+/// Continues insertion left by `<C-o>` once its command is done, operator still waits for
+/// motion and visual mode for operator
+pub fn resume_insert_mode(ctx: &mut CommandData) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    match buf.mode() {
+        ModeKind::OperatorPending => (),
+        mode if mode.is_visual() => (),
+        ModeKind::Normal => {
+            if let Some(mode) = buf.take_resume_insert() {
+                let before = buf.selections().clone();
+
+                buf.switch_mode(mode);
+                doc.open_transaction(buf.text_pos(), &before);
+            }
+        }
+        // command started insertion on its own
+        _ => {
+            buf.take_resume_insert();
+        }
     }
 }
 
+fn commit_insertion(buf: &mut Buffer, doc: &mut Document) {
+    doc.with_transaction(|doc, tx| {
+        tx.apply_repeats(doc.text_mut());
+
+        TransactionLeave::Commit(buf.selections().clone())
+    });
+}
+
 pub fn switch_to_visual_mode(ctx: &mut CommandData) {
+    switch_to_visual_impl(ctx, ModeKind::Visual);
+}
+
+pub fn switch_to_visual_line_mode(ctx: &mut CommandData) {
+    switch_to_visual_impl(ctx, ModeKind::VisualLine);
+}
+
+pub fn switch_to_visual_block_mode(ctx: &mut CommandData) {
+    switch_to_visual_impl(ctx, ModeKind::VisualBlock);
+}
+
+/// Other visual modes keep the selection, switching to the current one leaves it
+fn switch_to_visual_impl(ctx: &mut CommandData, mode: ModeKind) {
     let (buf, _) = current_mut!(ctx.editor);
 
-    buf.switch_mode(ModeKind::Visual);
+    if buf.mode() == mode {
+        buf.switch_mode(ModeKind::Normal);
+    } else {
+        buf.switch_mode(mode);
+    }
+}
+
+/// `I` in visual mode, inserts before selection, on every line of the block in visual block
+/// mode
+pub fn visual_insert(ctx: &mut CommandData) {
+    visual_insert_impl(ctx, Switch::Inplace);
+}
+
+/// `A` in visual mode, appends after selection, on every line of the block in visual block
+/// mode, short lines are padded with spaces to reach past the block
+pub fn visual_append(ctx: &mut CommandData) {
+    visual_insert_impl(ctx, Switch::After);
+}
+
+fn visual_insert_impl(ctx: &mut CommandData, switch: Switch) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    if !buf.mode().is_visual() {
+        return;
+    }
+
+    let append_to_block = buf.mode() == ModeKind::VisualBlock && matches!(switch, Switch::After);
+    let (_, right) = block_columns(doc.text(), buf.selections().primary());
+
+    let text = doc.text();
+    let positions = buf
+        .visual_ranges(doc)
+        .into_iter()
+        .filter_map(|range| match switch {
+            // text is appended to lines not reaching the block too, at their end
+            Switch::After if append_to_block => {
+                let line_start = text.line_to_char(text.char_to_line(range.start));
+                Some(range.end.saturating_sub(1).max(line_start))
+            }
+            // lines not reaching the block are skipped
+            _ if range.is_empty() => None,
+            Switch::After => Some(range.end - 1),
+            _ => Some(range.start),
+        })
+        .collect::<Vec<_>>();
+
+    buf.switch_mode(ModeKind::Normal);
+    buf.set_cursors(doc, positions);
+
+    switch_to_insert_mode_impl(ctx, switch);
+
+    if append_to_block {
+        pad_to_column(ctx, right + 1);
+    }
 }
 
 pub fn command_mode(ctx: &mut CommandData) {
-    ctx.push_widget(PromptWidget::new(":", |this, ctx| {
-        let command_name = this.text();
-        ctx.invoke_command_by_name(command_name);
-    }));
+    let (buf, _) = current_mut!(ctx.editor);
+
+    // command acts on lines of visual selection
+    let range = if buf.mode().is_visual() {
+        buf.switch_mode(ModeKind::Normal);
+        "'<,'>"
+    } else {
+        ""
+    };
+
+    ctx.push_widget(
+        PromptWidget::new(":", |this, ctx| ctx.execute_command_line(this.text())).with_text(range),
+    );
 }
 
 #[cfg(test)]
 mod test {
     use super::super::test::*;
     use super::*;
+    use crate::{current, editor::command::add_cursor_below};
 
     #[test]
     fn enter_insert_mode_transaction_opened() {
@@ -292,4 +440,75 @@ mod test {
             assert_eq!(buf.text_pos(), 12);
         });
     }
+
+    #[test]
+    fn visual_block_insert() {
+        let editor = test_keys(1, "0123\n4567\n", "<C-v>jIx<ESC>");
+        assert_eq!(current!(editor).1.text(), "0x123\n4x567\n");
+
+        let editor = test_keys(1, "0123\n4567\n", "<C-v>jlAx<ESC>");
+        assert_eq!(current!(editor).1.text(), "012x3\n456x7\n");
+
+        let editor = test_keys(2, "0123\n4\n5678\n", "<C-v>jjIx<ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "01x23\n4\n56x78\n",
+            "short lines are skipped"
+        );
+
+        let editor = test_keys(1, "0123\n4567\n", "vjAx<ESC>");
+        assert_eq!(current!(editor).1.text(), "0123\n45x67\n");
+
+        let editor = test_keys(2, "0123\n4\n\n5678\n", "<C-v>ljjjAx<ESC>");
+        assert_eq!(
+            current!(editor).1.text(),
+            "0123x\n4   x\n    x\n5678x\n",
+            "short lines are padded"
+        );
+
+        let editor = test_keys(2, "0123\n4\n5678\n", "<C-v>jjlAx<ESC>u");
+        assert_eq!(current!(editor).1.text(), "0123\n4\n5678\n");
+
+        let editor = test_keys(12, "\tab\n0123456789\n", "<C-v>kAx<ESC>");
+        assert_eq!(current!(editor).1.text(), "\tabx\n0123456789x\n");
+
+        let editor = test_keys(12, "\ta\n0123456789\n", "<C-v>kAx<ESC>");
+        assert_eq!(current!(editor).1.text(), "\ta x\n0123456789x\n");
+    }
+
+    #[test]
+    fn enter_insert_mode_all_cursors() {
+        let text = "012\n4567\n9AB\n";
+
+        test_cmds(
+            1,
+            text,
+            &[add_cursor_below, switch_to_insert_mode_line_end],
+            |buf: B, _: D| {
+                assert_eq!(
+                    buf.selections().ranges().collect::<Vec<_>>(),
+                    [(3, 3), (8, 8)]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn insert_count_all_cursors() {
+        let text = "012\n4567\n";
+
+        let editor = test_keys(1, text, "<C-j>3ixy<ESC>");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "0xyxyxy12\n4xyxyxy567\n");
+        assert_eq!(
+            buf.selections().ranges().collect::<Vec<_>>(),
+            [(6, 6), (16, 16)]
+        );
+
+        let editor = test_keys(1, text, "<C-j>2Aab<BS><ESC>");
+        assert_eq!(current!(editor).1.text(), "012aa\n4567aa\n");
+
+        let editor = test_keys(1, text, "<C-j>3ix<ESC>u");
+        assert_eq!(current!(editor).1.text(), text, "single undo step");
+    }
 }
@@ -1,6 +1,9 @@
+use crossterm::event::KeyCode;
 use kaka_core::{
     document::Document,
     graphemes::{nth_next_grapheme_boundary, nth_prev_grapheme_boundary},
+    movement::{self, Direction, FindChar, WordKind},
+    ropey::RopeSlice,
 };
 
 use crate::{
@@ -8,48 +11,52 @@ use crate::{
     editor::{buffer::UpdateBufPositionParams, Buffer},
 };
 
-use super::CommandData;
+use super::{cancel_pending_operator, CommandData};
 
 pub fn move_left(ctx: &mut CommandData) {
     let count = ctx.count.unwrap_or(1);
     let (buf, doc) = current_mut!(ctx.editor);
 
-    let text = doc.text();
+    buf.for_each_cursor(doc, |buf| {
+        let text = doc.text();
 
-    let new_pos = nth_prev_grapheme_boundary(text.slice(..), buf.text_pos(), count);
-    buf.update_text_position(
-        doc,
-        new_pos,
-        UpdateBufPositionParams {
-            line_keep: true,
-            allow_on_newline: false,
-            ..Default::default()
-        },
-    );
+        let new_pos = nth_prev_grapheme_boundary(text.slice(..), buf.text_pos(), count);
+        buf.update_text_position(
+            doc,
+            new_pos,
+            UpdateBufPositionParams {
+                line_keep: true,
+                allow_on_newline: false,
+                ..Default::default()
+            },
+        );
+    });
 }
 
 pub fn move_right(ctx: &mut CommandData) {
     let count = ctx.count.unwrap_or(1);
     let (buf, doc) = current_mut!(ctx.editor);
 
-    let pos = buf.text_pos();
-    let text = doc.text();
-    let curr_line = buf.line_idx();
-    let line_start = buf.line_char();
-    let line = text.line(curr_line);
-
-    let new_pos =
-        line_start + nth_next_grapheme_boundary(line, pos.saturating_sub(line_start), count);
-
-    buf.update_text_position(
-        doc,
-        new_pos,
-        UpdateBufPositionParams {
-            line_keep: true,
-            allow_on_newline: false,
-            ..Default::default()
-        },
-    );
+    buf.for_each_cursor(doc, |buf| {
+        let pos = buf.text_pos();
+        let text = doc.text();
+        let curr_line = buf.line_idx();
+        let line_start = buf.line_char();
+        let line = text.line(curr_line);
+
+        let new_pos =
+            line_start + nth_next_grapheme_boundary(line, pos.saturating_sub(line_start), count);
+
+        buf.update_text_position(
+            doc,
+            new_pos,
+            UpdateBufPositionParams {
+                line_keep: true,
+                allow_on_newline: false,
+                ..Default::default()
+            },
+        );
+    });
 }
 
 pub fn move_up(ctx: &mut CommandData) {
@@ -77,6 +84,167 @@ pub fn goto_line_default_bottom(ctx: &mut CommandData) {
     goto_line_impl(ctx, GotoLine::Fixed(line));
 }
 
+/// `:goto <line>`, lines are counted from 1
+pub fn goto_line(ctx: &mut CommandData) {
+    if let Some(line) = ctx.args.int(0) {
+        goto_line_impl(ctx, GotoLine::Fixed(line.saturating_sub(1)));
+    }
+}
+
+pub fn move_next_word_start(ctx: &mut CommandData) {
+    text_motion_impl(ctx, |s, p, c| {
+        movement::next_word_start(s, p, c, WordKind::Word)
+    });
+}
+
+pub fn move_prev_word_start(ctx: &mut CommandData) {
+    text_motion_impl(ctx, |s, p, c| {
+        movement::prev_word_start(s, p, c, WordKind::Word)
+    });
+}
+
+pub fn move_next_word_end(ctx: &mut CommandData) {
+    text_motion_impl(ctx, |s, p, c| {
+        movement::next_word_end(s, p, c, WordKind::Word)
+    });
+}
+
+pub fn move_prev_word_end(ctx: &mut CommandData) {
+    text_motion_impl(ctx, |s, p, c| {
+        movement::prev_word_end(s, p, c, WordKind::Word)
+    });
+}
+
+pub fn move_next_long_word_start(ctx: &mut CommandData) {
+    text_motion_impl(ctx, |s, p, c| {
+        movement::next_word_start(s, p, c, WordKind::LongWord)
+    });
+}
+
+pub fn move_prev_long_word_start(ctx: &mut CommandData) {
+    text_motion_impl(ctx, |s, p, c| {
+        movement::prev_word_start(s, p, c, WordKind::LongWord)
+    });
+}
+
+pub fn move_next_long_word_end(ctx: &mut CommandData) {
+    text_motion_impl(ctx, |s, p, c| {
+        movement::next_word_end(s, p, c, WordKind::LongWord)
+    });
+}
+
+pub fn move_next_paragraph(ctx: &mut CommandData) {
+    text_motion_impl(ctx, movement::next_paragraph);
+}
+
+pub fn move_prev_paragraph(ctx: &mut CommandData) {
+    text_motion_impl(ctx, movement::prev_paragraph);
+}
+
+pub fn move_next_sentence(ctx: &mut CommandData) {
+    text_motion_impl(ctx, movement::next_sentence);
+}
+
+pub fn move_prev_sentence(ctx: &mut CommandData) {
+    text_motion_impl(ctx, movement::prev_sentence);
+}
+
+fn text_motion_impl<F>(ctx: &mut CommandData, motion: F)
+where
+    F: Fn(RopeSlice, usize, usize) -> usize,
+{
+    let count = ctx.count.unwrap_or(1);
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    buf.for_each_cursor(doc, |buf| {
+        let new_pos = motion(doc.text().slice(..), buf.text_pos(), count);
+
+        buf.update_text_position(doc, new_pos, Default::default());
+    });
+}
+
+pub fn find_next_char(ctx: &mut CommandData) {
+    find_char_impl(ctx, Direction::Forward, false);
+}
+
+pub fn find_prev_char(ctx: &mut CommandData) {
+    find_char_impl(ctx, Direction::Backward, false);
+}
+
+pub fn till_next_char(ctx: &mut CommandData) {
+    find_char_impl(ctx, Direction::Forward, true);
+}
+
+pub fn till_prev_char(ctx: &mut CommandData) {
+    find_char_impl(ctx, Direction::Backward, true);
+}
+
+pub fn repeat_find(ctx: &mut CommandData) {
+    if let Some(find) = ctx.editor.last_find {
+        goto_char(ctx, find, true);
+    } else {
+        let (buf, _) = current_mut!(ctx.editor);
+        cancel_pending_operator(buf);
+    }
+}
+
+pub fn repeat_find_reverse(ctx: &mut CommandData) {
+    if let Some(find) = ctx.editor.last_find {
+        goto_char(ctx, find.reversed(), true);
+    } else {
+        let (buf, _) = current_mut!(ctx.editor);
+        cancel_pending_operator(buf);
+    }
+}
+
+fn find_char_impl(ctx: &mut CommandData, direction: Direction, till: bool) {
+    let ch = match ctx.key_argument.map(|key| key.code) {
+        Some(KeyCode::Char(ch)) => ch,
+        Some(KeyCode::Tab) => '\t',
+        _ => {
+            let (buf, _) = current_mut!(ctx.editor);
+            cancel_pending_operator(buf);
+            return;
+        }
+    };
+
+    let find = FindChar {
+        ch,
+        direction,
+        till,
+    };
+
+    ctx.editor.last_find = Some(find);
+    goto_char(ctx, find, false);
+}
+
+fn goto_char(ctx: &mut CommandData, find: FindChar, repeat: bool) {
+    let count = ctx.count.unwrap_or(1);
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    let mut found = false;
+
+    buf.for_each_cursor(doc, |buf| {
+        let text = doc.text().slice(..);
+
+        if let Some(pos) = find.find(text, buf.text_pos(), count, repeat) {
+            found = true;
+            buf.update_text_position(doc, pos, Default::default());
+        }
+    });
+
+    if !found {
+        cancel_pending_operator(buf);
+    }
+}
+
+/// Moves `count - 1` lines down, makes doubled operators (`dd`, `>>`) act on whole lines
+pub fn current_line(ctx: &mut CommandData) {
+    let offset = ctx.count.unwrap_or(1).saturating_sub(1);
+
+    goto_line_impl(ctx, GotoLine::Offset(offset as i128));
+}
+
 #[derive(Debug, Clone, Copy)]
 enum GotoLine {
     Fixed(usize),
@@ -102,31 +270,38 @@ impl GotoLine {
 fn goto_line_impl(ctx: &mut CommandData, goto_line: GotoLine) {
     let (buf, doc) = current_mut!(ctx.editor);
 
-    let text = doc.text();
+    buf.for_each_cursor(doc, |buf| {
+        let text = doc.text();
 
-    let goto_line_idx = goto_line.to_line(buf, doc);
-    let goto_line_start = text.line_to_char(goto_line_idx);
-    let goto_line_end = text.line_to_char(goto_line_idx + 1).saturating_sub(1);
+        let goto_line_idx = goto_line.to_line(buf, doc);
+        let goto_line_start = text.line_to_char(goto_line_idx);
+        let goto_line_end = text.line_to_char(goto_line_idx + 1).saturating_sub(1);
 
-    let mut new_pos = (goto_line_start + buf.saved_column()).min(goto_line_end);
+        let mut new_pos = (goto_line_start + buf.saved_column()).min(goto_line_end);
 
-    new_pos = new_pos.max(goto_line_start);
+        new_pos = new_pos.max(goto_line_start);
 
-    buf.update_text_position(
-        doc,
-        new_pos,
-        UpdateBufPositionParams {
-            update_saved_column: false,
-            allow_on_newline: false,
-            line_keep: false,
-        },
-    );
+        buf.update_text_position(
+            doc,
+            new_pos,
+            UpdateBufPositionParams {
+                update_saved_column: false,
+                allow_on_newline: false,
+                line_keep: false,
+            },
+        );
+    });
 }
 
 #[cfg(test)]
 mod test {
     use super::super::test::*;
     use super::*;
+    use crate::{
+        client::composer::Context,
+        current,
+        editor::command::{add_cursor_below, switch_to_visual_mode},
+    };
 
     #[test]
     fn move_left_prevented_on_pos_0() {
@@ -327,4 +502,157 @@ mod test {
             assert_eq!(buf.text_pos(), 15);
         });
     }
+
+    #[test]
+    fn word_motions() {
+        let text = "foo.bar baz\nqux";
+
+        test_cmd(0, text, move_next_word_start, |buf: B, _: D| {
+            assert_eq!(buf.text_pos(), 3);
+        });
+        test_cmd(0, text, move_next_long_word_start, |buf: B, _: D| {
+            assert_eq!(buf.text_pos(), 8);
+        });
+        test_cmd(8, text, move_next_word_end, |buf: B, _: D| {
+            assert_eq!(buf.text_pos(), 10);
+            assert_eq!(buf.saved_column(), 10);
+        });
+        test_cmd(12, text, move_prev_word_end, |buf: B, _: D| {
+            assert_eq!(buf.text_pos(), 10);
+        });
+        test_cmd(14, text, move_next_word_start, |buf: B, _: D| {
+            // end of text, cursor stays on last character
+            assert_eq!(buf.text_pos(), 14);
+        });
+    }
+
+    #[test]
+    fn find_char_motions() {
+        let text = "a,b,c,d\n";
+
+        let editor = test_keys(0, text, "f,;");
+        let (buf, _) = current!(editor);
+        assert_eq!(buf.text_pos(), 3);
+
+        let editor = test_keys(0, text, "2f,,");
+        let (buf, _) = current!(editor);
+        assert_eq!(buf.text_pos(), 1);
+
+        let editor = test_keys(0, text, "t,;;");
+        let (buf, _) = current!(editor);
+        assert_eq!(buf.text_pos(), 4);
+
+        let editor = test_keys(6, text, "F,");
+        let (buf, _) = current!(editor);
+        assert_eq!(buf.text_pos(), 5);
+
+        let editor = test_keys(6, text, "T,");
+        let (buf, _) = current!(editor);
+        assert_eq!(buf.text_pos(), 6, "till stays when next to the character");
+
+        let editor = test_keys(0, text, "fz");
+        let (buf, _) = current!(editor);
+        assert_eq!(buf.text_pos(), 0);
+    }
+
+    #[test]
+    fn find_char_operator() {
+        let text = "a,b,c,d\n";
+
+        let editor = test_keys(0, text, "df,");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "b,c,d\n");
+
+        let editor = test_keys(0, text, "dt,");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), ",b,c,d\n");
+
+        let editor = test_keys(4, text, "dF,");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "a,bc,d\n");
+
+        let editor = test_keys(4, text, "dfz");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), text);
+        assert_eq!(buf.mode(), crate::editor::ModeKind::Normal);
+
+        let editor = test_keys(0, text, "f,d;");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "ac,d\n");
+    }
+
+    #[test]
+    fn word_motions_extend_selection() {
+        let text = "foo bar baz\n";
+
+        test_cmds(
+            0,
+            text,
+            &[
+                switch_to_visual_mode,
+                move_next_word_start,
+                move_next_word_end,
+            ],
+            |buf: B, _: D| {
+                assert_eq!(buf.selections().ranges().collect::<Vec<_>>(), [(0, 6)]);
+            },
+        );
+    }
+
+    #[test]
+    fn move_all_cursors() {
+        let text = "0123\n5\n789A\n";
+
+        test_cmds(2, text, &[add_cursor_below, move_down], |buf: B, _: D| {
+            assert_eq!(
+                buf.selections().ranges().collect::<Vec<_>>(),
+                [(5, 5), (9, 9)]
+            );
+        });
+
+        test_cmds(
+            2,
+            text,
+            &[add_cursor_below, add_cursor_below, move_up],
+            |buf: B, _: D| {
+                // cursors reaching the same position are merged
+                assert_eq!(
+                    buf.selections().ranges().collect::<Vec<_>>(),
+                    [(2, 2), (5, 5)]
+                );
+            },
+        );
+
+        test_cmds(
+            1,
+            text,
+            &[add_cursor_below, move_right, move_right],
+            |buf: B, _: D| {
+                assert_eq!(
+                    buf.selections().ranges().collect::<Vec<_>>(),
+                    [(3, 3), (5, 5)]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn goto_line_typed() {
+        let mut editor = test_editor(0, "0\n1\n2\n3\n");
+        let mut context = Context {
+            editor: &mut editor,
+        };
+
+        context.execute_command_line("goto 3");
+        assert_eq!(current!(context.editor).0.line_idx(), 2);
+
+        // invalid arguments don't run the command
+        context.execute_command_line("goto x");
+        context.execute_command_line("goto");
+        context.execute_command_line("goto 1 2");
+        assert_eq!(current!(context.editor).0.line_idx(), 2);
+
+        context.execute_command_line("goto_line 1");
+        assert_eq!(current!(context.editor).0.line_idx(), 0);
+    }
 }
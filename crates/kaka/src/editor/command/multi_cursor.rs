@@ -0,0 +1,91 @@
+use crate::{current_mut, editor::buffer::UpdateBufPositionParams};
+
+use super::CommandData;
+
+pub fn add_cursor_below(ctx: &mut CommandData) {
+    add_cursor_impl(ctx, 1);
+}
+
+pub fn add_cursor_above(ctx: &mut CommandData) {
+    add_cursor_impl(ctx, -1);
+}
+
+pub fn keep_primary_cursor(ctx: &mut CommandData) {
+    let (buf, _) = current_mut!(ctx.editor);
+
+    buf.keep_primary_cursor();
+}
+
+fn add_cursor_impl(ctx: &mut CommandData, direction: isize) {
+    let count = ctx.count.unwrap_or(1);
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    let text = doc.text();
+    let last_line = text.len_lines().saturating_sub(1);
+
+    for _ in 0..count {
+        let line_idx = match buf.line_idx().checked_add_signed(direction) {
+            Some(line_idx) if line_idx <= last_line => line_idx,
+            _ => break,
+        };
+
+        let line_start = text.line_to_char(line_idx);
+        let line_end = text.line_to_char(line_idx + 1).saturating_sub(1);
+        let pos = (line_start + buf.saved_column())
+            .min(line_end)
+            .max(line_start);
+
+        buf.add_cursor(doc, pos);
+        buf.update_text_position(
+            doc,
+            pos,
+            UpdateBufPositionParams {
+                update_saved_column: false,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::*;
+    use super::*;
+
+    #[test]
+    fn add_cursors() {
+        let text = "0123\n56\n89AB";
+
+        test_cmd(2, text, add_cursor_below, |buf: B, _: D| {
+            assert_eq!(
+                buf.selections().ranges().collect::<Vec<_>>(),
+                [(2, 2), (6, 6)]
+            );
+            assert_eq!(buf.text_pos(), 6);
+            assert_eq!(buf.saved_column(), 2);
+        });
+
+        test_cmds(
+            10,
+            text,
+            &[add_cursor_above, add_cursor_above, add_cursor_above],
+            |buf: B, _: D| {
+                assert_eq!(
+                    buf.selections().ranges().collect::<Vec<_>>(),
+                    [(2, 2), (6, 6), (10, 10)]
+                );
+                assert_eq!(buf.text_pos(), 2);
+            },
+        );
+
+        test_cmds(
+            0,
+            text,
+            &[add_cursor_below, keep_primary_cursor],
+            |buf: B, _: D| {
+                assert_eq!(buf.selections().len(), 1);
+                assert_eq!(buf.text_pos(), 5);
+            },
+        );
+    }
+}
@@ -0,0 +1,723 @@
+use std::ops::Range;
+
+use kaka_core::{
+    document::{Document, TransactionLeave},
+    graphemes::next_grapheme_boundary,
+    indent::{leading_whitespace, Indent},
+    ropey::Rope,
+    selection::{Selection, SelectionSet},
+    transaction::Assoc,
+};
+
+use crate::{
+    current, current_mut,
+    editor::{
+        buffer::{block_corners, UpdateBufPositionParams},
+        register::{RegisterValue, UNNAMED},
+        Buffer, Editor, ModeKind,
+    },
+};
+
+use super::{
+    text_manipulation::{delete_ranges, replace_ranges},
+    CommandData, MotionKind,
+};
+
+/// Action performed on text selected by motion or in visual mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    ToggleCase,
+    Lowercase,
+    Uppercase,
+}
+
+/// Operator waiting for motion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingOperator {
+    pub operator: Operator,
+    /// Count typed before operator
+    pub count: Option<usize>,
+    /// Register selected before operator
+    pub register: Option<char>,
+}
+
+impl PendingOperator {
+    /// Combines operator count with motion count, `2d3w` acts on 6 words
+    pub fn count_with(self, count: Option<usize>) -> Option<usize> {
+        match (self.count, count) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1))),
+        }
+    }
+}
+
+pub fn operator_delete(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::Delete);
+}
+
+pub fn operator_change(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::Change);
+}
+
+pub fn operator_yank(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::Yank);
+}
+
+pub fn operator_indent(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::Indent);
+}
+
+pub fn operator_outdent(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::Outdent);
+}
+
+pub fn operator_toggle_case(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::ToggleCase);
+}
+
+pub fn operator_lowercase(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::Lowercase);
+}
+
+pub fn operator_uppercase(ctx: &mut CommandData) {
+    operator_impl(ctx, Operator::Uppercase);
+}
+
+/// Applies operator on text selected by motion, called by the editor after motion command
+pub fn apply_pending_operator(ctx: &mut CommandData, kind: MotionKind) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    let pending = match buf.pending_operator() {
+        Some(pending) => pending,
+        None => return,
+    };
+
+    let ranges = motion_ranges(buf, doc.text(), kind);
+
+    let register = pending.register.unwrap_or(UNNAMED);
+
+    apply_operator(
+        ctx,
+        pending.operator,
+        register,
+        ranges,
+        kind == MotionKind::Linewise,
+    );
+}
+
+/// Motion failed, pending operator is dropped
+pub(super) fn cancel_pending_operator(buf: &mut Buffer) {
+    if buf.pending_operator().is_some() {
+        buf.switch_mode(ModeKind::Normal);
+    }
+}
+
+fn operator_impl(ctx: &mut CommandData, operator: Operator) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    if buf.mode().is_visual() {
+        let ranges = buf.visual_ranges(doc);
+        let linewise = buf.mode() == ModeKind::VisualLine;
+        let register = ctx.register.unwrap_or(UNNAMED);
+        apply_operator(ctx, operator, register, ranges, linewise);
+    } else {
+        buf.switch_to_operator_pending(PendingOperator {
+            operator,
+            count: ctx.count,
+            register: ctx.register,
+        });
+    }
+}
+
+/// Text covered by every selection, selection head is the target of the motion
+fn motion_ranges(buf: &Buffer, text: &Rope, kind: MotionKind) -> Vec<Range<usize>> {
+    buf.selections()
+        .iter()
+        .map(|selection| {
+            let (start, end) = selection.range();
+
+            match kind {
+                MotionKind::Exclusive => {
+                    let end_line = text.char_to_line(end);
+                    let end_line_start = text.line_to_char(end_line);
+
+                    // motion ending at the beginning of the next line (like `dw` on the
+                    // last word) does not take line break
+                    let leading_blank = text
+                        .slice(end_line_start..end)
+                        .chars()
+                        .all(|c| c == ' ' || c == '\t');
+
+                    if start < end_line_start && leading_blank {
+                        start..end_line_start - 1
+                    } else {
+                        start..end
+                    }
+                }
+                MotionKind::Find if selection.head() < selection.anchor() => start..end,
+                MotionKind::Inclusive | MotionKind::Find => {
+                    start..next_grapheme_boundary(text.slice(..), end)
+                }
+                MotionKind::Linewise => {
+                    let first_line = text.char_to_line(start);
+                    let last_line = text.char_to_line(end);
+
+                    text.line_to_char(first_line)..text.line_to_char(last_line + 1)
+                }
+            }
+        })
+        .collect()
+}
+
+fn apply_operator(
+    ctx: &mut CommandData,
+    operator: Operator,
+    register: char,
+    mut ranges: Vec<Range<usize>>,
+    linewise: bool,
+) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    // restored by undo, pending operator selections only reach to the motion target
+    let mut before = buf.selections().clone();
+    if !buf.mode().is_visual() {
+        before.transform(|s| Selection::at_pos(s.anchor()).with_column(s.column()));
+    }
+
+    // block is restored from its top left corner, the selection may start at top right one
+    if buf.mode() == ModeKind::VisualBlock {
+        before.transform(|s| block_corners(doc.text(), s));
+    }
+
+    // operators leave cursors at the start of affected text
+    if buf.mode() == ModeKind::VisualBlock {
+        buf.switch_mode(ModeKind::Normal);
+
+        // text is typed on every line of changed block
+        match operator {
+            Operator::Change => {
+                let rows = ranges.iter().filter(|range| !range.is_empty());
+                buf.set_cursors(doc, rows.map(|range| range.start));
+            }
+            _ => buf.set_cursors(doc, ranges.iter().map(|range| range.start).min()),
+        }
+    } else {
+        buf.transform_cursors(doc, |s| {
+            Selection::at_pos(s.start()).with_column(s.column())
+        });
+        buf.switch_mode(ModeKind::Normal);
+    }
+
+    match operator {
+        Operator::Delete | Operator::Change => {
+            yank_ranges(ctx.editor, register, &ranges, linewise, true);
+        }
+        Operator::Yank => yank_ranges(ctx.editor, register, &ranges, linewise, false),
+        _ => (),
+    }
+
+    let indent = ctx.editor.indent();
+    let (buf, doc) = current_mut!(ctx.editor);
+    let text = doc.text();
+
+    match operator {
+        Operator::Delete => {
+            if linewise {
+                // last line has no line break, take the preceding one
+                for range in ranges.iter_mut() {
+                    if range.end == text.len_chars()
+                        && range.start > 0
+                        && text.get_char(range.end.saturating_sub(1)) != Some('\n')
+                    {
+                        range.start -= 1;
+                    }
+                }
+            }
+
+            delete_ranges(
+                buf,
+                doc,
+                &before,
+                ranges,
+                UpdateBufPositionParams::default(),
+                true,
+            );
+        }
+        Operator::Change => {
+            if linewise {
+                // keep line break, so there is a line to insert text into, and indentation
+                let mut moved = vec![];
+
+                for range in ranges.iter_mut() {
+                    if range.end > range.start && text.char(range.end - 1) == '\n' {
+                        range.end -= 1;
+                    }
+
+                    let indentation = text
+                        .chars_at(range.start)
+                        .take(range.end - range.start)
+                        .take_while(|c| matches!(c, ' ' | '\t'))
+                        .count();
+
+                    moved.push((range.start, range.start + indentation));
+                    range.start += indentation;
+                }
+
+                buf.transform_cursors(doc, |s| {
+                    let pos = moved
+                        .iter()
+                        .find(|(start, _)| *start == s.head())
+                        .map_or(s.head(), |&(_, pos)| pos);
+
+                    Selection::at_pos(pos).with_column(s.column())
+                });
+            }
+
+            // deletion and inserted text form one undo step, committed on leaving insert mode
+            delete_ranges(
+                buf,
+                doc,
+                &before,
+                ranges,
+                UpdateBufPositionParams::inserting_text(),
+                false,
+            );
+
+            buf.switch_mode(ModeKind::Insert);
+            doc.open_transaction(buf.text_pos(), &before);
+        }
+        Operator::Yank => {}
+        Operator::Indent | Operator::Outdent => {
+            let shift = match operator {
+                Operator::Indent => Shift::Indent,
+                _ => Shift::Outdent,
+            };
+
+            indent_lines(buf, doc, &before, &ranges, &indent, shift);
+        }
+        Operator::ToggleCase | Operator::Lowercase | Operator::Uppercase => {
+            convert_case(buf, doc, &before, &ranges, operator);
+        }
+    }
+}
+
+/// Stores text covered by `ranges` in register, one value per cursor, `deleted` text goes to
+/// the numbered registers as well
+pub(super) fn yank_ranges(
+    editor: &mut Editor,
+    register: char,
+    ranges: &[Range<usize>],
+    linewise: bool,
+    deleted: bool,
+) {
+    if ranges.is_empty() {
+        return;
+    }
+
+    let (_, doc) = current!(editor);
+    let text = doc.text();
+
+    let value = RegisterValue::new(
+        ranges
+            .iter()
+            .map(|range| text.slice(range.clone()).to_string()),
+        linewise,
+    );
+
+    if deleted {
+        editor.registers.delete(register, value);
+    } else {
+        editor.registers.yank(register, value);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shift {
+    Indent,
+    Outdent,
+}
+
+fn indent_lines(
+    buf: &mut Buffer,
+    doc: &mut Document,
+    before: &SelectionSet,
+    ranges: &[Range<usize>],
+    indent: &Indent,
+    shift: Shift,
+) {
+    let text = doc.text();
+
+    let mut lines = ranges
+        .iter()
+        .flat_map(|range| {
+            let first_line = text.char_to_line(range.start);
+            let last_line = text.char_to_line(range.end.saturating_sub(1).max(range.start));
+
+            first_line..=last_line
+        })
+        .collect::<Vec<_>>();
+
+    lines.sort_unstable();
+    lines.dedup();
+
+    // (position, chars to delete, text to insert)
+    let edits = lines
+        .into_iter()
+        .filter_map(|line_idx| {
+            let line = text.line(line_idx);
+            let line_start = text.line_to_char(line_idx);
+
+            match shift {
+                Shift::Indent => {
+                    let empty = matches!(line.get_char(0), Some('\n') | None);
+                    (!empty).then_some((line_start, 0, indent.unit.as_str()))
+                }
+                Shift::Outdent => {
+                    let line = line.to_string();
+                    let indentation = leading_whitespace(&line);
+                    let kept = indent.outdent(indentation).len();
+                    let width = indentation.len() - kept;
+
+                    (width > 0).then_some((line_start + kept, width, ""))
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let start = match edits.last() {
+        Some(&(start, _, _)) => start,
+        None => return,
+    };
+
+    doc.with_new_transaction(start, before, |doc, tx| {
+        // go backwards, so positions of preceding lines stay valid
+        for &(line_start, delete, insert) in edits.iter().rev() {
+            tx.move_to(line_start);
+            tx.delete(delete);
+            tx.insert(insert);
+        }
+
+        tx.apply(doc.text_mut());
+
+        buf.map_cursors(doc, tx, Assoc::Before);
+        buf.for_each_cursor(doc, |buf| {
+            let text = doc.text();
+            let indent = text
+                .line(buf.line_idx())
+                .chars()
+                .take_while(|c| matches!(c, ' ' | '\t'))
+                .count();
+
+            buf.update_text_position(doc, buf.line_char() + indent, Default::default());
+        });
+
+        tx.move_to(buf.text_pos());
+
+        TransactionLeave::Commit(buf.selections().clone())
+    });
+}
+
+fn convert_case(
+    buf: &mut Buffer,
+    doc: &mut Document,
+    before: &SelectionSet,
+    ranges: &[Range<usize>],
+    operator: Operator,
+) {
+    let text = doc.text();
+
+    let edits = ranges
+        .iter()
+        .filter_map(|range| {
+            let slice = text.slice(range.clone());
+
+            let mut converted = String::with_capacity(slice.len_bytes());
+            for c in slice.chars() {
+                match operator {
+                    Operator::Lowercase => converted.extend(c.to_lowercase()),
+                    Operator::Uppercase => converted.extend(c.to_uppercase()),
+                    _ if c.is_lowercase() => converted.extend(c.to_uppercase()),
+                    _ => converted.extend(c.to_lowercase()),
+                }
+            }
+
+            (slice != converted.as_str()).then(|| (range.clone(), converted))
+        })
+        .collect::<Vec<_>>();
+
+    replace_ranges(buf, doc, before, &edits);
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::*;
+    use super::*;
+
+    #[test]
+    fn delete_lines() {
+        let text = "012\n456\n89A\nCDE\n";
+
+        let editor = test_keys(1, text, "dd");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "456\n89A\nCDE\n");
+        assert_eq!(buf.text_pos(), 0);
+        assert_eq!(buf.mode(), ModeKind::Normal);
+
+        let editor = test_keys(1, text, "d2j");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "CDE\n");
+
+        let editor = test_keys(5, text, "3dd");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "012\n");
+
+        let editor = test_keys(9, text, "dk");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "012\nCDE\n");
+
+        let editor = test_keys(4, "012\n456", "dd");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "012");
+    }
+
+    #[test]
+    fn delete_with_motion_count() {
+        let text = "0123456789\n";
+
+        let editor = test_keys(1, text, "d3l");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "0456789\n");
+        assert_eq!(buf.text_pos(), 1);
+
+        let editor = test_keys(1, text, "2d3l");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "0789\n");
+
+        let editor = test_keys(5, text, "d2h");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "01256789\n");
+
+        // exclusive motion reaches last character of the line
+        let editor = test_keys(8, text, "d5l");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "01234567\n");
+    }
+
+    #[test]
+    fn delete_words() {
+        let text = "foo bar\n  baz qux\n";
+
+        let editor = test_keys(0, text, "dw");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "bar\n  baz qux\n");
+
+        let editor = test_keys(4, text, "dw");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "foo \n  baz qux\n");
+
+        let editor = test_keys(0, text, "d3w");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "qux\n");
+
+        let editor = test_keys(0, text, "2de");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "\n  baz qux\n");
+
+        let editor = test_keys(14, text, "db");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "foo bar\n  qux\n");
+
+        let editor = test_keys(14, text, "dw");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "foo bar\n  baz \n");
+    }
+
+    #[test]
+    fn invalid_motion_aborts_operator() {
+        let editor = test_keys(1, "012\n", "dz");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "012\n");
+        assert_eq!(buf.mode(), ModeKind::Normal);
+
+        let editor = test_keys(1, "012\n", "d<ESC>");
+        let (buf, _) = current!(editor);
+        assert_eq!(buf.mode(), ModeKind::Normal);
+    }
+
+    #[test]
+    fn change_is_single_undo_step() {
+        let text = "012\n456\n";
+
+        let editor = test_keys(1, text, "c2lab");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "0ab\n456\n");
+        assert_eq!(buf.mode(), ModeKind::Insert);
+
+        let editor = test_keys(1, text, "ccab<ESC>");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "ab\n456\n");
+
+        let editor = test_keys(1, text, "cjab<ESC>u");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), text);
+
+        let editor = test_keys(0, "  ab\n", "ccx<ESC>");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "  x\n", "indentation is kept");
+    }
+
+    #[test]
+    fn yank() {
+        let text = "012\n456\n";
+
+        let mut editor = test_keys(5, text, "yk");
+        let value = editor.registers.get(UNNAMED).unwrap();
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), text);
+        assert_eq!(value.values, [text]);
+        assert!(value.linewise);
+        assert_eq!(buf.text_pos(), 1);
+
+        let mut editor = test_keys(1, text, "vly");
+        let value = editor.registers.get(UNNAMED).unwrap();
+        assert_eq!(value.values, ["12"]);
+        assert!(!value.linewise);
+    }
+
+    #[test]
+    fn indent() {
+        let text = "a\n\n    b\n\tc\n";
+
+        let editor = test_keys(0, text, "<GT>G");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "    a\n\n        b\n    \tc\n");
+        assert_eq!(buf.text_pos(), 4);
+
+        let editor = test_keys(0, text, "4<LT><LT>");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "a\n\nb\nc\n");
+
+        let editor = test_keys(7, text, "<LT><LT>u");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), text);
+    }
+
+    #[test]
+    fn indent_unit_of_language() {
+        let path = temp_path("indent.go");
+        std::fs::write(&path, "a\n\t\tb\n").unwrap();
+
+        let mut editor = test_editor(0, "");
+        editor.open(&path, true).unwrap();
+        feed_keys(&mut editor, "<GT><GT>jVk<LT>");
+        assert_eq!(current!(editor).1.text(), "a\n\tb\n");
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn convert_case() {
+        let text = "abC\ndEf\n";
+
+        let editor = test_keys(0, text, "g~j");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "ABc\nDeF\n");
+        assert_eq!(buf.text_pos(), 0);
+
+        let editor = test_keys(0, text, "gUU");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "ABC\ndEf\n");
+
+        let editor = test_keys(1, text, "gu2l");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "abc\ndEf\n");
+        assert_eq!(buf.text_pos(), 1);
+
+        let editor = test_keys(1, text, "vjU");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "aBC\nDEf\n");
+    }
+
+    #[test]
+    fn visual_line() {
+        let text = "012\n456\n89A\n";
+
+        let editor = test_keys(1, text, "Vjd");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "89A\n");
+        assert_eq!(buf.mode(), ModeKind::Normal);
+
+        let editor = test_keys(1, text, "vjVx");
+        assert_eq!(current!(editor).1.text(), "89A\n", "selection is kept");
+
+        let editor = test_keys(5, text, "VjcX<ESC>");
+        assert_eq!(current!(editor).1.text(), "012\nX\n");
+
+        let mut editor = test_keys(5, text, "Vy");
+        let value = editor.registers.get(UNNAMED).unwrap();
+        assert_eq!(value.values, ["456\n"]);
+        assert!(value.linewise);
+    }
+
+    #[test]
+    fn visual_block() {
+        let text = "0123\n4567\n89\n";
+
+        let editor = test_keys(1, text, "<C-v>jld");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "03\n47\n89\n");
+        assert_eq!(buf.text_pos(), 1);
+
+        let editor = test_keys(3, text, "<C-v>jjd");
+        assert_eq!(
+            current!(editor).1.text(),
+            "0\n4\n8\n",
+            "short lines are cut"
+        );
+
+        let editor = test_keys(7, text, "<C-v>khcX<ESC>");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "0X3\n4X7\n89\n");
+        assert_eq!(buf.selections().len(), 2, "text is typed on every line");
+
+        let mut editor = test_keys(1, text, "<C-v>jly");
+        let value = editor.registers.get(UNNAMED).unwrap();
+        assert_eq!(value.values, ["12", "56"]);
+        assert!(!value.linewise);
+
+        let editor = test_keys(1, text, "<C-v>jU");
+        assert_eq!(current!(editor).1.text(), text);
+    }
+
+    #[test]
+    fn visual_block_display_columns() {
+        let editor = test_keys(12, "\tab\n0123456789\n", "<C-v>kd");
+        assert_eq!(
+            current!(editor).1.text(),
+            "\t\n01234567\n",
+            "tab reaches tab stop"
+        );
+
+        let editor = test_keys(9, "\tab\n0123456789\n", "<C-v>kd");
+        assert_eq!(
+            current!(editor).1.text(),
+            "\n01234\n",
+            "tab partly in block is taken whole"
+        );
+
+        let editor = test_keys(1, "\u{4e16}\u{754c}\nabcd\n", "<C-v>jd");
+        assert_eq!(current!(editor).1.text(), "\na\n", "wide characters");
+
+        let editor = test_keys(0, "\u{200b}a\nbc\n", "<C-v>jd");
+        assert_eq!(
+            current!(editor).1.text(),
+            "\u{200b}\nc\n",
+            "zero width character at corner"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::{bail, Context, Result};
+use kaka_core::encoding_rs::Encoding;
+
+use crate::{
+    current_mut,
+    editor::{Clipboard, Editor},
+};
+
+use super::CommandData;
+
+/// `:set option=value`, `:set option` shows current value
+pub fn set_option(ctx: &mut CommandData) {
+    let setting = ctx.args.string(0).expect("Option is required");
+
+    let (name, value) = match setting.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (setting, None),
+    };
+
+    if let Err(err) = set(ctx.editor, name, value) {
+        log::error!("{err}");
+    }
+}
+
+fn set(editor: &mut Editor, name: &str, value: Option<&str>) -> Result<()> {
+    match (name, value) {
+        ("clipboard", None) => log::info!("clipboard={}", editor.registers.clipboard().name()),
+        ("clipboard", Some(value)) => editor.registers.set_clipboard(Clipboard::from_name(value)?),
+        _ => set_document_option(editor, name, value)?,
+    }
+
+    Ok(())
+}
+
+fn set_document_option(editor: &mut Editor, name: &str, value: Option<&str>) -> Result<()> {
+    let (_, doc) = current_mut!(editor);
+
+    match (name, value) {
+        ("fileformat" | "ff", None) => log::info!("fileformat={}", doc.line_ending()),
+        ("fileformat" | "ff", Some(value)) => doc.set_line_ending(value.parse()?),
+        ("fileencoding" | "fenc", None) => log::info!("fileencoding={}", doc.encoding().name()),
+        ("fileencoding" | "fenc", Some(value)) => {
+            let encoding = doc.encoding().with_encoding(parse_encoding(value)?);
+            doc.set_encoding(encoding);
+        }
+        _ => bail!("Unknown option: {name}"),
+    }
+
+    Ok(())
+}
+
+/// Accepts the same labels as browsers do, like `latin1` or `utf-16le`
+pub(super) fn parse_encoding(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.as_bytes()).with_context(|| format!("Unknown encoding: {label}"))
+}
+
+#[cfg(test)]
+mod test {
+    use kaka_core::document::LineEnding;
+
+    use super::super::test::*;
+    use crate::{client::composer::Context, current};
+
+    #[test]
+    fn set_fileformat() {
+        let mut editor = test_editor(0, "a\n");
+        let mut context = Context {
+            editor: &mut editor,
+        };
+
+        context.execute_command_line("set ff=dos");
+        assert_eq!(current!(editor).1.line_ending(), LineEnding::Crlf);
+        assert!(current!(editor).1.is_modified());
+
+        let mut context = Context {
+            editor: &mut editor,
+        };
+        context.execute_command_line("set fileformat=windows");
+        assert_eq!(current!(editor).1.line_ending(), LineEnding::Crlf);
+    }
+
+    #[test]
+    fn set_fileencoding() {
+        let mut editor = test_editor(0, "a\n");
+        let mut context = Context {
+            editor: &mut editor,
+        };
+
+        context.execute_command_line("set fenc=latin1");
+        assert_eq!(current!(editor).1.encoding().name(), "windows-1252");
+        assert!(current!(editor).1.is_modified());
+    }
+
+    #[test]
+    fn set_clipboard() {
+        let mut editor = test_editor(0, "a\n");
+        let mut context = Context {
+            editor: &mut editor,
+        };
+
+        context.execute_command_line("set clipboard=osc52");
+        context.execute_command_line("set clipboard=nonexistent");
+        assert_eq!(editor.registers.clipboard().name(), "osc52");
+    }
+}
@@ -0,0 +1,210 @@
+use std::ops::RangeInclusive;
+
+use anyhow::{bail, Context, Result};
+use kaka_core::document::Document;
+
+use crate::editor::Buffer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+    /// `.`
+    Current,
+    /// `$`
+    Last,
+    /// Line number, counted from 1
+    Line(usize),
+    /// `'<`
+    VisualStart,
+    /// `'>`
+    VisualEnd,
+}
+
+/// Line address of ex command, like `.+1` or `'<`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    base: Base,
+    offset: isize,
+}
+
+/// Lines ex command acts on, `%`, `.`, `$`, `'<,'>` or `n,m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineRange {
+    Whole,
+    Lines(Address, Address),
+}
+
+impl LineRange {
+    /// Parses range from the start of command line, returns it with the rest of the line
+    pub fn parse(line: &str) -> Result<(Option<Self>, &str)> {
+        if let Some(rest) = line.strip_prefix('%') {
+            return Ok((Some(Self::Whole), rest));
+        }
+
+        let (start, rest) = parse_address(line)?;
+
+        let (end, rest) = match rest.strip_prefix(',') {
+            Some(rest) => {
+                let (end, rest) = parse_address(rest)?;
+                (end.or(Some(CURRENT)), rest)
+            }
+            None => (start, rest),
+        };
+
+        let range = match (start, end) {
+            (None, None) => None,
+            (start, end) => Some(Self::Lines(
+                start.unwrap_or(CURRENT),
+                end.unwrap_or(CURRENT),
+            )),
+        };
+
+        Ok((range, rest))
+    }
+
+    /// Line under primary cursor
+    pub const fn current() -> Self {
+        Self::Lines(CURRENT, CURRENT)
+    }
+
+    /// Resolves range to indices of first and last line, backward range is swapped
+    pub fn resolve(&self, buf: &Buffer, doc: &Document) -> Result<RangeInclusive<usize>> {
+        let last = last_line(doc);
+
+        let (start, end) = match self {
+            Self::Whole => (0, last),
+            Self::Lines(start, end) => (
+                resolve_address(*start, buf, doc)?,
+                resolve_address(*end, buf, doc)?,
+            ),
+        };
+
+        Ok(start.min(end)..=start.max(end))
+    }
+}
+
+const CURRENT: Address = Address {
+    base: Base::Current,
+    offset: 0,
+};
+
+fn parse_address(s: &str) -> Result<(Option<Address>, &str)> {
+    let (base, mut rest) = if let Some(rest) = s.strip_prefix('.') {
+        (Some(Base::Current), rest)
+    } else if let Some(rest) = s.strip_prefix('$') {
+        (Some(Base::Last), rest)
+    } else if let Some(rest) = s.strip_prefix("'<") {
+        (Some(Base::VisualStart), rest)
+    } else if let Some(rest) = s.strip_prefix("'>") {
+        (Some(Base::VisualEnd), rest)
+    } else if s.starts_with('\'') {
+        bail!("Unknown mark in {s}");
+    } else {
+        let (number, rest) = split_number(s);
+        (number.map(Base::Line), rest)
+    };
+
+    let mut offset = 0isize;
+    let mut has_offset = false;
+
+    while let Some(sign) = rest.chars().next().filter(|c| matches!(c, '+' | '-')) {
+        let (number, after) = split_number(&rest[1..]);
+        let number = number.unwrap_or(1) as isize;
+
+        offset = if sign == '+' {
+            offset.checked_add(number)
+        } else {
+            offset.checked_sub(number)
+        }
+        .context("Line offset is too big")?;
+
+        has_offset = true;
+        rest = after;
+    }
+
+    let address = match base {
+        Some(base) => Some(Address { base, offset }),
+        None if has_offset => Some(Address {
+            base: Base::Current,
+            offset,
+        }),
+        None => None,
+    };
+
+    Ok((address, rest))
+}
+
+fn split_number(s: &str) -> (Option<usize>, &str) {
+    let len = s.chars().take_while(char::is_ascii_digit).count();
+
+    (s[..len].parse().ok(), &s[len..])
+}
+
+/// Last line with content, text usually ends with line break followed by empty line
+fn last_line(doc: &Document) -> usize {
+    let text = doc.text();
+    let lines = text.len_lines();
+
+    if lines > 1 && text.line(lines - 1).len_chars() == 0 {
+        lines - 2
+    } else {
+        lines - 1
+    }
+}
+
+fn resolve_address(address: Address, buf: &Buffer, doc: &Document) -> Result<usize> {
+    let text = doc.text();
+
+    let mark_line = |pos: usize| text.char_to_line(pos.min(text.len_chars()));
+
+    let line = match address.base {
+        Base::Current => buf.line_idx(),
+        Base::Last => last_line(doc),
+        Base::Line(number) => number.saturating_sub(1),
+        Base::VisualStart => mark_line(buf.visual_marks().context("Mark '< is not set")?.0),
+        Base::VisualEnd => mark_line(buf.visual_marks().context("Mark '> is not set")?.1),
+    };
+
+    line.checked_add_signed(address.offset)
+        .filter(|line| *line <= last_line(doc))
+        .context("Invalid range")
+}
+
+#[cfg(test)]
+mod test {
+    use kaka_core::ropey::Rope;
+
+    use super::*;
+
+    fn resolve(range: &str, pos: usize) -> Result<RangeInclusive<usize>> {
+        let mut doc = Document::new_scratch();
+        *doc.text_mut() = Rope::from("0\n1\n2\n3\n4\n");
+        let buf = Buffer::new_text(pos, &doc).unwrap();
+
+        let (range, rest) = LineRange::parse(range)?;
+        assert_eq!(rest, "s");
+
+        range.unwrap_or(LineRange::current()).resolve(&buf, &doc)
+    }
+
+    #[test]
+    fn parse_and_resolve() {
+        assert_eq!(resolve("s", 2).unwrap(), 1..=1);
+        assert_eq!(resolve("%s", 2).unwrap(), 0..=4);
+        assert_eq!(resolve(".s", 4).unwrap(), 2..=2);
+        assert_eq!(resolve("$s", 0).unwrap(), 4..=4);
+        assert_eq!(resolve("2,4s", 0).unwrap(), 1..=3);
+        assert_eq!(resolve("4,2s", 0).unwrap(), 1..=3);
+        assert_eq!(resolve(".,$s", 2).unwrap(), 1..=4);
+        assert_eq!(resolve(".+1,$-1s", 0).unwrap(), 1..=3);
+        assert_eq!(resolve("+,++s", 0).unwrap(), 1..=2);
+        assert_eq!(resolve("3,s", 0).unwrap(), 0..=2);
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert!(resolve("7s", 0).is_err());
+        assert!(resolve("-s", 0).is_err());
+        assert!(resolve("'<,'>s", 0).is_err(), "marks not set");
+        assert!(LineRange::parse("'a,'bs").is_err());
+    }
+}
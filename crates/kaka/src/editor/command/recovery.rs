@@ -0,0 +1,296 @@
+use crossterm::event::KeyCode;
+use kaka_core::{document::Document, ropey::Rope};
+
+use crate::{
+    client::composer::ConfirmWidget,
+    editor::{Buffer, BufferId, Editor, Recovery},
+};
+
+/// Asks what to do with every journal found when opening documents, `None` when there are
+/// none
+pub fn recovery_prompt(editor: &mut Editor) -> Option<ConfirmWidget> {
+    let mut recovery = editor.take_recovery()?;
+    let first = question(editor, &recovery);
+
+    // buffer to go back to from diff
+    let mut shown_before_diff: Option<BufferId> = None;
+
+    Some(ConfirmWidget::new(first, move |key, ctx| {
+        match key {
+            KeyCode::Char('r') => recover(ctx.editor, &recovery),
+            KeyCode::Char('d') => {
+                let current = ctx.editor.current;
+                if show_diff(ctx.editor, &recovery) {
+                    shown_before_diff.get_or_insert(current);
+                }
+                return Some(question(ctx.editor, &recovery));
+            }
+            KeyCode::Char('x') => {
+                log::info!("Discarded journal of {}", name(ctx.editor, &recovery));
+            }
+            _ => return Some(question(ctx.editor, &recovery)),
+        }
+
+        // journal is either replayed or discarded, it's replaced with the current one
+        ctx.editor
+            .start_journal(recovery.document, recovery.path.clone());
+
+        if let Some(buf_id) = shown_before_diff.take() {
+            ctx.editor.current = buf_id;
+        }
+
+        recovery = ctx.editor.take_recovery()?;
+        Some(question(ctx.editor, &recovery))
+    }))
+}
+
+fn question(editor: &Editor, recovery: &Recovery) -> String {
+    format!(
+        "{} has unsaved changes from a crashed session: (r)ecover, (d)iff, (x) discard?",
+        name(editor, recovery)
+    )
+}
+
+fn name(editor: &Editor, recovery: &Recovery) -> String {
+    editor.documents[&recovery.document]
+        .display_name()
+        .into_owned()
+}
+
+fn recover(editor: &mut Editor, recovery: &Recovery) {
+    let document = editor
+        .documents
+        .get_mut(&recovery.document)
+        .expect("Recovered document is open");
+
+    match document.recover(&recovery.journal) {
+        Ok(()) => log::info!("Recovered unsaved changes of {}", document.display_name()),
+        Err(err) => log::error!("Can't recover {}: {err}", document.display_name()),
+    }
+}
+
+/// Opens scratch buffer with changes journal would make, returns whether it was opened
+fn show_diff(editor: &mut Editor, recovery: &Recovery) -> bool {
+    let document = &editor.documents[&recovery.document];
+
+    let diff = match recovery.journal.diff(document.text()) {
+        Ok(diff) => diff,
+        Err(err) => {
+            log::error!("Can't compare {}: {err}", document.display_name());
+            return false;
+        }
+    };
+
+    let name = document.display_name();
+    let mut scratch = Document::new_scratch();
+    *scratch.text_mut() = Rope::from(format!("--- {name}\n+++ {name} (recovered)\n{diff}"));
+
+    let buffer = Buffer::new_text(0, &scratch).expect("Should not fail");
+    editor.add_buffer_and_document(buffer, scratch, true);
+
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use crossterm::event::Event;
+    use kaka_core::document::Journal;
+
+    use super::super::test::*;
+    use super::*;
+    use crate::{
+        client::composer::{Context, Widget},
+        current,
+        editor::utils::parse_mapping,
+    };
+
+    fn editor(recovery_dir: &Path, path: &Path) -> Editor {
+        let mut editor = test_editor(0, "");
+        editor.recovery_dir = Some(recovery_dir.to_path_buf());
+        editor.open(path, true).unwrap();
+        editor
+    }
+
+    /// Edits file and leaves journal behind, like a crashed session would
+    fn crash(recovery_dir: &Path, path: &Path, keys: &str) {
+        let mut editor = editor(recovery_dir, path);
+        feed_keys(&mut editor, keys);
+        editor.write_journals();
+    }
+
+    fn answer(editor: &mut Editor, keys: &str) {
+        let mut prompt = recovery_prompt(editor).expect("Journal should be found");
+        let mut context = Context { editor };
+
+        for key in parse_mapping(keys).unwrap() {
+            prompt.handle_event(&Event::Key(key), &mut context);
+        }
+    }
+
+    #[test]
+    fn recover_unsaved_changes() {
+        let recovery_dir = temp_path("recover_dir");
+        let path = temp_path("recover");
+        fs::write(&path, "abc\n").unwrap();
+
+        crash(&recovery_dir, &path, "xxiz<ESC>");
+
+        let mut editor = editor(&recovery_dir, &path);
+        answer(&mut editor, "dr");
+
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.path(), Some(path.as_path()), "back from diff");
+        assert_eq!(doc.text(), "zc\n");
+        assert!(doc.is_modified());
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(
+            current!(editor).1.text(),
+            "c\n",
+            "recovered changes can be undone"
+        );
+
+        editor.remove_journals();
+        let mut editor = self::editor(&recovery_dir, &path);
+        assert!(recovery_prompt(&mut editor).is_none());
+        assert_eq!(current!(editor).1.text(), "abc\n");
+
+        let _ = fs::remove_dir_all(&recovery_dir);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn recover_insertion_in_progress() {
+        let recovery_dir = temp_path("insertion_dir");
+        let path = temp_path("insertion");
+        fs::write(&path, "abc\n").unwrap();
+
+        crash(&recovery_dir, &path, "xAyz");
+
+        let mut editor = editor(&recovery_dir, &path);
+        answer(&mut editor, "r");
+        assert_eq!(current!(editor).1.text(), "bcyz\n");
+
+        let _ = fs::remove_dir_all(&recovery_dir);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn diff_and_discard() {
+        let recovery_dir = temp_path("discard_dir");
+        let path = temp_path("discard");
+        fs::write(&path, "abc\n").unwrap();
+
+        crash(&recovery_dir, &path, "x");
+
+        let mut editor = editor(&recovery_dir, &path);
+        let file_buffer = editor.current;
+
+        let mut prompt = recovery_prompt(&mut editor).unwrap();
+        let mut context = Context {
+            editor: &mut editor,
+        };
+        prompt.handle_event(&Event::Key(KeyCode::Char('d').into()), &mut context);
+        assert_eq!(
+            current!(context.editor).1.text().to_string(),
+            format!(
+                "--- {0}\n+++ {0} (recovered)\n@@ -1,1 +1,1 @@\n-abc\n+bc\n",
+                path.display()
+            )
+        );
+
+        prompt.handle_event(&Event::Key(KeyCode::Char('x').into()), &mut context);
+        assert_eq!(editor.current, file_buffer);
+        assert_eq!(current!(editor).1.text(), "abc\n");
+        assert!(!current!(editor).1.is_modified());
+
+        // journal was replaced with one without changes
+        let editor = self::editor(&recovery_dir, &path);
+        assert!(editor.recoveries.is_empty());
+
+        let _ = fs::remove_dir_all(&recovery_dir);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn journal_written_after_changes() {
+        let recovery_dir = temp_path("rewrite_dir");
+        let path = temp_path("rewrite");
+        fs::write(&path, "abc\n").unwrap();
+
+        let mut editor = editor(&recovery_dir, &path);
+        let journal_path = Journal::path_in(&recovery_dir, &path).unwrap();
+        fs::remove_file(&journal_path).unwrap();
+
+        editor.write_journals();
+        assert!(
+            !journal_path.exists(),
+            "nothing changed since the last write"
+        );
+
+        feed_keys(&mut editor, "ia");
+        editor.write_journals();
+        assert!(journal_path.exists(), "insertion in progress is a change");
+
+        let _ = fs::remove_dir_all(&recovery_dir);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_open_in_other_instance() {
+        let recovery_dir = temp_path("other_instance_dir");
+        let path = temp_path("other_instance");
+        fs::write(&path, "abc\n").unwrap();
+
+        crash(&recovery_dir, &path, "x");
+
+        // pretend the journal belongs to init, which is always running
+        let journal_path = fs::read_dir(&recovery_dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let journal = fs::read_to_string(&journal_path).unwrap();
+        let pid = format!("pid: {}\n", std::process::id());
+        fs::write(&journal_path, journal.replace(&pid, "pid: 1\n")).unwrap();
+
+        let mut editor = editor(&recovery_dir, &path);
+        assert!(recovery_prompt(&mut editor).is_none());
+
+        feed_keys(&mut editor, "x");
+        editor.write_journals();
+        assert!(
+            fs::read_to_string(&journal_path)
+                .unwrap()
+                .contains("pid: 1\n"),
+            "journal of the other instance is kept"
+        );
+
+        let _ = fs::remove_dir_all(&recovery_dir);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn journal_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let recovery_dir = temp_path("private_dir");
+        let path = temp_path("private");
+        fs::write(&path, "abc\n").unwrap();
+
+        crash(&recovery_dir, &path, "x");
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let journal_path = Journal::path_in(&recovery_dir, &path).unwrap();
+        assert_eq!(mode(&recovery_dir), 0o700);
+        assert_eq!(mode(&journal_path), 0o600);
+
+        let _ = fs::remove_dir_all(&recovery_dir);
+        let _ = fs::remove_file(&path);
+    }
+}
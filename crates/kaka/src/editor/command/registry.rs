@@ -15,6 +15,18 @@ macro_rules! command {
     ($fun: ident) => { command!($fun, true, true) };
 }
 
+macro_rules! motion {
+    ($fun: ident, $kind: ident) => {
+        command!($fun).with_motion_kind(MotionKind::$kind)
+    };
+}
+
+macro_rules! change {
+    ($fun: ident) => {
+        command!($fun).with_repeat()
+    };
+}
+
 #[derive(Debug, Default)]
 pub struct Registry {
     typable: HashMap<Cow<'static, str>, Arc<Command>>,
@@ -56,22 +68,88 @@ impl Registry {
         let commands = [
             command!(switch_to_normal_mode),
             command!(switch_to_visual_mode),
-            command!(switch_to_insert_mode_inplace),
-            command!(switch_to_insert_mode_line_start),
-            command!(switch_to_insert_mode_after),
-            command!(switch_to_insert_mode_line_end),
-            command!(move_left),
-            command!(move_down),
-            command!(move_up),
-            command!(move_right),
-            command!(goto_line_default_top),
-            command!(kill_line),
-            command!(goto_line_default_bottom),
+            command!(switch_to_visual_line_mode),
+            command!(switch_to_visual_block_mode),
+            change!(visual_insert),
+            change!(visual_append),
+            change!(switch_to_insert_mode_inplace),
+            change!(switch_to_insert_mode_line_start),
+            change!(switch_to_insert_mode_after),
+            change!(switch_to_insert_mode_line_end),
+            change!(switch_to_replace_mode),
+            command!(execute_normal_command, false, true),
+            command!(delete_word_before, false, true),
+            command!(delete_to_line_start, false, true),
+            command!(insert_register, false, true).with_key_argument(),
+            motion!(move_left, Exclusive),
+            motion!(move_down, Linewise),
+            motion!(move_up, Linewise),
+            motion!(move_right, Exclusive),
+            motion!(goto_line_default_top, Linewise),
+            motion!(goto_line_default_bottom, Linewise),
+            command!(goto_line, true, false, ["goto"])
+                .with_signature(Signature::new().required("line", ArgKind::Int)),
+            motion!(move_next_word_start, Exclusive),
+            motion!(move_prev_word_start, Exclusive),
+            motion!(move_next_word_end, Inclusive),
+            motion!(move_prev_word_end, Inclusive),
+            motion!(move_next_long_word_start, Exclusive),
+            motion!(move_prev_long_word_start, Exclusive),
+            motion!(move_next_long_word_end, Inclusive),
+            motion!(move_next_paragraph, Exclusive),
+            motion!(move_prev_paragraph, Exclusive),
+            motion!(move_next_sentence, Exclusive),
+            motion!(move_prev_sentence, Exclusive),
+            motion!(find_next_char, Find).with_key_argument(),
+            motion!(find_prev_char, Find).with_key_argument(),
+            motion!(till_next_char, Find).with_key_argument(),
+            motion!(till_prev_char, Find).with_key_argument(),
+            motion!(repeat_find, Find),
+            motion!(repeat_find_reverse, Find),
+            motion!(search_next, Exclusive),
+            motion!(search_prev, Exclusive),
+            command!(search_forward, false, true),
+            command!(search_backward, false, true),
+            motion!(current_line, Linewise),
+            motion!(select_inner_paragraph, Linewise),
+            motion!(select_a_paragraph, Linewise),
+            change!(operator_delete),
+            change!(operator_change),
+            command!(operator_yank),
+            change!(operator_indent),
+            change!(operator_outdent),
+            change!(operator_toggle_case),
+            change!(operator_lowercase),
+            change!(operator_uppercase),
+            command!(add_cursor_below),
+            command!(add_cursor_above),
+            command!(keep_primary_cursor),
             command!(undo),
             command!(redo),
-            command!(save, ["w"]),
+            command!(earlier).with_signature(Signature::new().optional("time", ArgKind::String)),
+            command!(later).with_signature(Signature::new().optional("time", ArgKind::String)),
+            command!(save, ["w"]).with_signature(Signature::new().optional("path", ArgKind::Path)),
+            command!(force_save, true, false, ["w!"])
+                .with_signature(Signature::new().optional("path", ArgKind::Path)),
+            command!(save_as, true, false, ["saveas"])
+                .with_signature(Signature::new().required("path", ArgKind::Path)),
+            command!(write_quit, true, false, ["wq"])
+                .with_signature(Signature::new().optional("path", ArgKind::Path)),
+            command!(edit, true, false, ["e"])
+                .with_signature(Signature::new().required("path", ArgKind::Path)),
             command!(close, ["q"]),
-            command!(kill),
+            command!(force_close, true, false, ["q!"]),
+            command!(reopen_with_encoding, true, false, ["reopen"])
+                .with_signature(Signature::new().required("encoding", ArgKind::String)),
+            command!(set_option, true, false, ["set"])
+                .with_signature(Signature::new().required("option", ArgKind::String)),
+            change!(kill),
+            change!(put_after),
+            change!(put_before),
+            change!(replace_char).with_key_argument(),
+            command!(repeat_last_change),
+            command!(record_macro, false, true).with_key_argument(),
+            command!(replay_macro, false, true).with_key_argument(),
             command!(command_mode, false, true),
             command!(buffer_next),
             command!(buffer_prev),
@@ -111,6 +189,9 @@ mod test {
 
         let command = command!(dummy, ["x", "d"]);
         assert_eq!(command.aliases(), &["x", "d"]);
+
+        let command = motion!(dummy, Linewise);
+        assert_eq!(command.motion_kind(), Some(MotionKind::Linewise));
     }
 
     #[test]
@@ -0,0 +1,138 @@
+use crossterm::event::KeyCode;
+use kaka_core::document::DocumentId;
+
+use crate::{client::composer::ConfirmWidget, editor::Editor};
+
+/// Asks whether to load modified documents changed on disk, `None` when there are none
+pub fn reload_prompt(editor: &mut Editor) -> Option<ConfirmWidget> {
+    let mut document = editor.take_reload_conflict()?;
+    let first = question(editor, document);
+
+    Some(ConfirmWidget::new(first, move |key, ctx| {
+        match key {
+            // reload is a commit, unsaved changes are still there in history
+            KeyCode::Char('l') => ctx.editor.reload_document(document),
+            KeyCode::Char('k') => {
+                let doc = ctx
+                    .editor
+                    .documents
+                    .get_mut(&document)
+                    .expect("Document exists");
+
+                if let Err(err) = doc.ignore_disk_changes() {
+                    log::error!("Can't check {}: {err}", doc.display_name());
+                }
+            }
+            _ => return Some(question(ctx.editor, document)),
+        }
+
+        document = ctx.editor.take_reload_conflict()?;
+        Some(question(ctx.editor, document))
+    }))
+}
+
+fn question(editor: &Editor, document: DocumentId) -> String {
+    format!(
+        "{} changed on disk and has unsaved changes: (l)oad, (k)eep?",
+        editor.documents[&document].display_name()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use crossterm::event::Event;
+
+    use super::super::test::*;
+    use super::*;
+    use crate::{
+        client::composer::{Context, Widget},
+        current,
+        editor::utils::parse_mapping,
+    };
+
+    fn editor(path: &Path) -> Editor {
+        let mut editor = test_editor(0, "");
+        editor.open(path, true).unwrap();
+        editor
+    }
+
+    fn answer(editor: &mut Editor, keys: &str) {
+        let mut prompt = reload_prompt(editor).expect("Conflict should be found");
+        let mut context = Context { editor };
+
+        for key in parse_mapping(keys).unwrap() {
+            prompt.handle_event(&Event::Key(key), &mut context);
+        }
+    }
+
+    #[test]
+    fn unmodified_document_follows_file() {
+        let path = temp_path("follow");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+        let mut editor = editor(&path);
+        feed_keys(&mut editor, "jjl");
+        assert!(!editor.check_files(), "nothing changed yet");
+
+        fs::write(&path, "zero\none\nthree\n").unwrap();
+        assert!(editor.check_files());
+
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "zero\none\nthree\n");
+        assert!(!doc.is_modified());
+        assert_eq!(buf.text_pos(), 10, "cursor stays on `h` of three");
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(current!(editor).1.text(), "one\ntwo\nthree\n");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn modified_document_asks() {
+        let path = temp_path("conflict");
+        fs::write(&path, "abc\n").unwrap();
+
+        let mut editor = editor(&path);
+        feed_keys(&mut editor, "x");
+
+        fs::write(&path, "abcd\n").unwrap();
+        assert!(!editor.check_files());
+        assert_eq!(current!(editor).1.text(), "bc\n");
+
+        answer(&mut editor, "l");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "abcd\n");
+        assert!(!doc.is_modified());
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(
+            current!(editor).1.text(),
+            "bc\n",
+            "unsaved changes are kept in history"
+        );
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn keep_modified_document() {
+        let path = temp_path("keep");
+        fs::write(&path, "abc\n").unwrap();
+
+        let mut editor = editor(&path);
+        feed_keys(&mut editor, "x");
+
+        fs::write(&path, "abcd\n").unwrap();
+        editor.check_files();
+        answer(&mut editor, "k");
+        assert_eq!(current!(editor).1.text(), "bc\n");
+
+        editor.check_files();
+        assert!(reload_prompt(&mut editor).is_none(), "asked only once");
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use crossterm::event::KeyEvent;
+use kaka_core::{
+    graphemes::{char_at_column, column},
+    ropey::Rope,
+};
+
+use crate::{
+    current, current_mut,
+    editor::{
+        buffer::{block_columns, UpdateBufPositionParams},
+        Buffer, ModeKind,
+    },
+};
+
+use super::{
+    apply_pending_operator, insert_mode_on_key, replace_mode_on_key, switch_to_normal_mode, Args,
+    Command, CommandData,
+};
+
+/// Command called with its count and key argument
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    pub command: Arc<Command>,
+    pub count: Option<usize>,
+    pub key_argument: Option<KeyEvent>,
+}
+
+/// Change repeated by `.`, recorded as it was typed
+#[derive(Debug, Clone)]
+pub struct LastChange {
+    pub change: Invocation,
+    pub register: Option<char>,
+    /// Motion selecting text for operator
+    pub motion: Option<Invocation>,
+    /// Input of insert mode started by the change
+    pub inserted: Vec<Inserted>,
+    /// Selection the change was made on in visual mode
+    pub visual: Option<VisualSize>,
+}
+
+/// Amount of text selected in visual mode, `.` selects as much from cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualSize {
+    pub mode: ModeKind,
+    /// Lines below the first one
+    pub lines: usize,
+    /// Characters after the first one within a line, column of the end when selection spans
+    /// more lines, display width of the block past its first column
+    pub columns: usize,
+}
+
+/// Typed key or command mapped in insert mode, like `<C-w>`
+#[derive(Debug, Clone)]
+pub enum Inserted {
+    Key(KeyEvent),
+    Command(Invocation),
+}
+
+impl LastChange {
+    pub const fn new(change: Invocation, register: Option<char>) -> Self {
+        Self {
+            change,
+            register,
+            motion: None,
+            inserted: Vec::new(),
+            visual: None,
+        }
+    }
+}
+
+impl VisualSize {
+    /// Size of primary selection of `buf` in visual mode
+    pub fn new(buf: &Buffer, text: &Rope) -> Self {
+        let selection = buf.selections().primary();
+        let (start, end) = selection.range();
+        let (top, bottom) = (text.char_to_line(start), text.char_to_line(end));
+
+        let columns = match buf.mode() {
+            ModeKind::VisualBlock => {
+                let (left, right) = block_columns(text, selection);
+                right - left
+            }
+            _ if top == bottom => end - start,
+            _ => end - text.line_to_char(bottom),
+        };
+
+        Self {
+            mode: buf.mode(),
+            lines: bottom - top,
+            columns,
+        }
+    }
+
+    /// Enters visual mode selecting text of the same size from cursor, cut to the text
+    fn select(self, buf: &mut Buffer, text: &Rope) {
+        let pos = buf.text_pos();
+        let line_idx = text.char_to_line(pos);
+        let last_line = text.char_to_line(text.len_chars().saturating_sub(1));
+        let bottom = (line_idx + self.lines).min(last_line);
+
+        // last character of the line
+        let line_end = |line_idx| {
+            let line = text.line(line_idx);
+            let mut len = line.len_chars();
+            if len > 0 && line.char(len - 1) == '\n' {
+                len -= 1;
+            }
+
+            text.line_to_char(line_idx) + len.saturating_sub(1)
+        };
+
+        let head = match self.mode {
+            ModeKind::VisualLine => text.line_to_char(bottom),
+            ModeKind::Visual if self.lines == 0 => (pos + self.columns).min(line_end(line_idx)),
+            ModeKind::VisualBlock => {
+                let left = column(text.line(line_idx), pos - text.line_to_char(line_idx));
+                let right = char_at_column(text.line(bottom), left + self.columns);
+                (text.line_to_char(bottom) + right).min(line_end(bottom))
+            }
+            _ => (text.line_to_char(bottom) + self.columns).min(line_end(bottom)),
+        };
+
+        buf.switch_mode(self.mode);
+        buf.update_text_position(text, head, UpdateBufPositionParams::default());
+    }
+}
+
+/// `.`, repeats the last change at cursor, count replaces the one change was made with
+pub fn repeat_last_change(ctx: &mut CommandData) {
+    let Some(last) = ctx.editor.last_change.clone() else {
+        return;
+    };
+
+    let (count, motion_count) = match ctx.count {
+        Some(count) => (Some(count), None),
+        None => (
+            last.change.count,
+            last.motion.as_ref().and_then(|m| m.count),
+        ),
+    };
+
+    if let Some(visual) = last.visual {
+        let (buf, doc) = current_mut!(ctx.editor);
+        visual.select(buf, doc.text());
+    }
+
+    let mut data = CommandData {
+        editor: ctx.editor,
+        count,
+        register: last.register,
+        key_argument: last.change.key_argument,
+        args: Args::default(),
+        callback: None,
+    };
+
+    last.change.command.call(&mut data);
+
+    let pending = current!(data.editor).0.pending_operator();
+    if let (Some(pending), Some(motion)) = (pending, &last.motion) {
+        data.count = pending.count_with(motion_count);
+        data.key_argument = motion.key_argument;
+        motion.command.call(&mut data);
+
+        if let Some(kind) = motion.command.motion_kind() {
+            apply_pending_operator(&mut data, kind);
+        }
+    }
+
+    let on_key = match current!(data.editor).0.mode() {
+        ModeKind::Insert => insert_mode_on_key,
+        ModeKind::Replace => replace_mode_on_key,
+        _ => return,
+    };
+
+    for inserted in last.inserted {
+        match inserted {
+            Inserted::Key(key) => on_key(&mut data, key),
+            Inserted::Command(invocation) => {
+                data.count = invocation.count;
+                data.key_argument = invocation.key_argument;
+                invocation.command.call(&mut data);
+            }
+        }
+    }
+
+    switch_to_normal_mode(&mut data);
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::*;
+    use crate::current;
+
+    #[test]
+    fn repeat_operator_with_motion() {
+        let editor = test_keys(0, "a b c d e f\n", "dw.");
+        assert_eq!(current!(editor).1.text(), "c d e f\n");
+
+        let editor = test_keys(0, "a b c d e f\n", "d2w.");
+        assert_eq!(current!(editor).1.text(), "e f\n");
+
+        let editor = test_keys(0, "a b c d e f\n", "dw3.");
+        assert_eq!(
+            current!(editor).1.text(),
+            "e f\n",
+            "count replaces the original"
+        );
+
+        let editor = test_keys(0, "1\n2\n3\n4\n", "dd.");
+        assert_eq!(current!(editor).1.text(), "3\n4\n");
+
+        let editor = test_keys(0, "a.b.c\n", "dt..");
+        assert_eq!(current!(editor).1.text(), ".c\n", "key argument of motion");
+    }
+
+    #[test]
+    fn repeat_insertion() {
+        let editor = test_keys(0, "ab\n", "ix<ESC>l.");
+        assert_eq!(current!(editor).1.text(), "xxab\n");
+
+        let editor = test_keys(0, "a\nb\n", "Ayz<ESC>j.");
+        assert_eq!(current!(editor).1.text(), "ayz\nbyz\n");
+
+        let editor = test_keys(0, "ab\n", "ix<ESC>3.");
+        assert_eq!(current!(editor).1.text(), "xxxxab\n");
+
+        let editor = test_keys(0, "one two\nthree four\n", "cezero<ESC>jb.");
+        assert_eq!(current!(editor).1.text(), "zero two\nzero four\n");
+
+        let editor = test_keys(0, "ab\n", "iyz<BS>x<ESC>.");
+        assert_eq!(current!(editor).1.text(), "yyxxab\n", "keys are replayed");
+
+        let editor = test_keys(0, "ab cd\nef gh\n", "A<C-w>x<ESC>j.");
+        assert_eq!(
+            current!(editor).1.text(),
+            "ab x\nef x\n",
+            "mapped commands are replayed"
+        );
+    }
+
+    #[test]
+    fn repeat_visual_change() {
+        let editor = test_keys(0, "0123456\n", "vld.");
+        assert_eq!(current!(editor).1.text(), "456\n");
+
+        let editor = test_keys(0, "012\n45\n", "vlldj.");
+        assert_eq!(current!(editor).1.text(), "\n\n", "cut to the line");
+
+        let editor = test_keys(0, "a\nb\nc\nd\n", "Vj<GT>jj.");
+        assert_eq!(current!(editor).1.text(), "    a\n    b\n    c\n    d\n");
+
+        let editor = test_keys(0, "012\n345\n678\n", "vjd.");
+        assert_eq!(current!(editor).1.text(), "78\n", "lines of selection");
+
+        let editor = test_keys(0, "abc\ndef\nghi\njkl\n", "<C-v>jcx<ESC>jj.");
+        assert_eq!(current!(editor).1.text(), "xbc\nxef\nxhi\nxkl\n");
+
+        let editor = test_keys(0, "abc\ndef\nghi\njkl\n", "<C-v>jlUjj.");
+        assert_eq!(current!(editor).1.text(), "ABc\nDEf\nGHi\nJKl\n");
+    }
+
+    #[test]
+    fn not_repeated() {
+        let editor = test_keys(0, "ab\n", "xyl.");
+        assert_eq!(current!(editor).1.text(), "\n", "yank is not a change");
+
+        let editor = test_keys(0, "abc\n", "xdz.");
+        assert_eq!(current!(editor).1.text(), "c\n", "aborted operator");
+
+        let editor = test_keys(0, "ab\n", ".");
+        assert_eq!(current!(editor).1.text(), "ab\n");
+    }
+}
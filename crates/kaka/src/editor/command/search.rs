@@ -0,0 +1,210 @@
+use kaka_core::{
+    movement::Direction,
+    search::{Regex, Search},
+};
+
+use crate::{client::composer::PromptWidget, current, current_mut, editor::Editor};
+
+use super::{cancel_pending_operator, CommandData};
+
+pub fn search_forward(ctx: &mut CommandData) {
+    let prompt = search_prompt(ctx.editor, Direction::Forward, ctx.count.unwrap_or(1));
+    ctx.push_widget(prompt);
+}
+
+pub fn search_backward(ctx: &mut CommandData) {
+    let prompt = search_prompt(ctx.editor, Direction::Backward, ctx.count.unwrap_or(1));
+    ctx.push_widget(prompt);
+}
+
+pub fn search_next(ctx: &mut CommandData) {
+    search_next_impl(ctx, false);
+}
+
+pub fn search_prev(ctx: &mut CommandData) {
+    search_next_impl(ctx, true);
+}
+
+fn search_next_impl(ctx: &mut CommandData, reverse: bool) {
+    let count = ctx.count.unwrap_or(1);
+    let search = ctx.editor.last_search.clone();
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    let Some(search) = search else {
+        cancel_pending_operator(buf);
+        return;
+    };
+
+    let search = if reverse { search.reversed() } else { search };
+
+    let mut found = false;
+
+    buf.for_each_cursor(doc, |buf| {
+        let text = doc.text().slice(..);
+
+        if let Some(pos) = search.find(text, buf.text_pos(), count) {
+            found = true;
+            buf.update_text_position(doc, pos, Default::default());
+        }
+    });
+
+    if !found {
+        log::warn!("Pattern not found: {}", search.regex.as_str());
+        cancel_pending_operator(buf);
+    }
+}
+
+/// Prompt moving primary cursor to the match as the pattern is typed
+fn search_prompt(editor: &Editor, direction: Direction, count: usize) -> PromptWidget {
+    let (buf, _) = current!(editor);
+    let origin = buf.text_pos();
+    let previous = editor.last_search.clone();
+
+    let greeter = match direction {
+        Direction::Forward => "/",
+        Direction::Backward => "?",
+    };
+
+    let restore = previous.clone();
+
+    PromptWidget::new(greeter, move |this, ctx| {
+        // empty pattern repeats the last one
+        let search = if this.text().is_empty() {
+            previous.as_ref().map(|search| Search {
+                direction,
+                ..search.clone()
+            })
+        } else {
+            Regex::new(this.text())
+                .map_err(|err| log::error!("{err}"))
+                .ok()
+                .map(|regex| Search::new(regex, direction))
+        };
+
+        match search {
+            Some(search) => {
+                if !goto_match(ctx.editor, &search, origin, count) {
+                    log::warn!("Pattern not found: {}", search.regex.as_str());
+                }
+                ctx.editor.last_search = Some(search);
+            }
+            None => ctx.editor.last_search = previous.clone(),
+        }
+
+        None
+    })
+    .on_change(move |this, ctx| {
+        let search = Regex::new(this.text())
+            .ok()
+            .filter(|_| !this.text().is_empty())
+            .map(|regex| Search::new(regex, direction));
+
+        if let Some(search) = &search {
+            goto_match(ctx.editor, search, origin, count);
+        } else {
+            goto_pos(ctx.editor, origin);
+        }
+
+        ctx.editor.last_search = search;
+    })
+    .on_abort(move |_, ctx| {
+        goto_pos(ctx.editor, origin);
+        ctx.editor.last_search = restore.clone();
+    })
+}
+
+/// Moves primary cursor to the match found from `origin`, or back to `origin` if not found
+fn goto_match(editor: &mut Editor, search: &Search, origin: usize, count: usize) -> bool {
+    let (_, doc) = current!(editor);
+    let found = search.find(doc.text().slice(..), origin, count);
+
+    goto_pos(editor, found.unwrap_or(origin));
+    found.is_some()
+}
+
+fn goto_pos(editor: &mut Editor, pos: usize) {
+    let (buf, doc) = current_mut!(editor);
+
+    buf.update_text_position(doc, pos, Default::default());
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::Event;
+
+    use super::super::test::*;
+    use super::*;
+    use crate::{
+        client::composer::{Context, Widget},
+        editor::{utils::parse_mapping, ModeKind},
+    };
+
+    fn type_pattern(editor: &mut Editor, direction: Direction, keys: &str) {
+        let mut prompt = search_prompt(editor, direction, 1);
+        let mut context = Context { editor };
+
+        for key in parse_mapping(keys).unwrap() {
+            prompt.handle_event(&Event::Key(key), &mut context);
+        }
+    }
+
+    fn set_search(editor: &mut Editor, pattern: &str) {
+        let regex = Regex::new(pattern).unwrap();
+        editor.last_search = Some(Search::new(regex, Direction::Forward));
+    }
+
+    #[test]
+    fn incremental_search() {
+        let mut editor = test_editor(0, "foo bar\nbaz bar\n");
+
+        type_pattern(&mut editor, Direction::Forward, "ba");
+        assert_eq!(current!(editor).0.text_pos(), 4);
+
+        type_pattern(&mut editor, Direction::Forward, "baz");
+        assert_eq!(current!(editor).0.text_pos(), 8);
+
+        // no match, cursor stays at origin
+        type_pattern(&mut editor, Direction::Forward, "bax");
+        assert_eq!(current!(editor).0.text_pos(), 8);
+
+        type_pattern(&mut editor, Direction::Backward, "foo<CR>");
+        assert_eq!(current!(editor).0.text_pos(), 0);
+        assert_eq!(editor.last_search.unwrap().regex.as_str(), "foo");
+    }
+
+    #[test]
+    fn abort_restores_position_and_pattern() {
+        let mut editor = test_editor(0, "foo bar\nbaz bar\n");
+
+        type_pattern(&mut editor, Direction::Forward, "bar<CR>");
+        type_pattern(&mut editor, Direction::Forward, "baz<ESC>");
+
+        assert_eq!(current!(editor).0.text_pos(), 4);
+        assert_eq!(editor.last_search.unwrap().regex.as_str(), "bar");
+    }
+
+    #[test]
+    fn search_next_prev() {
+        let mut editor = test_editor(0, "ab ab ab\n");
+        set_search(&mut editor, "ab");
+
+        for (keys, pos) in [("n", 3), ("n", 6), ("n", 0), ("N", 6), ("2N", 0)] {
+            feed_keys(&mut editor, keys);
+            assert_eq!(current!(editor).0.text_pos(), pos, "after {keys}");
+        }
+    }
+
+    #[test]
+    fn search_operator() {
+        let mut editor = test_editor(0, "foo bar baz\n");
+        set_search(&mut editor, "ba");
+
+        feed_keys(&mut editor, "d2n");
+        assert_eq!(current!(editor).1.text().to_string(), "baz\n");
+
+        // no pattern, operator is aborted
+        let editor = test_keys(0, "foo bar baz\n", "dn");
+        assert_eq!(current!(editor).1.text().to_string(), "foo bar baz\n");
+        assert_eq!(current!(editor).0.mode(), ModeKind::Normal);
+    }
+}
@@ -0,0 +1,375 @@
+use std::ops::Range;
+
+use anyhow::{bail, Context, Result};
+use crossterm::event::KeyCode;
+use kaka_core::{
+    document::Document,
+    movement::Direction,
+    search::{Regex, Search},
+    substitute::Substitution,
+};
+
+use crate::{
+    client::composer::ConfirmWidget,
+    current_mut,
+    editor::{buffer::UpdateBufPositionParams, Buffer},
+};
+
+use super::{range::LineRange, text_manipulation::replace_ranges, CommandData};
+
+/// `:[range]s/pattern/replacement/flags`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitute {
+    range: Option<LineRange>,
+    pattern: String,
+    replacement: String,
+    global: bool,
+    confirm: bool,
+}
+
+impl Substitute {
+    /// Parses command line, `None` means the line is not a substitution at all
+    pub fn parse(line: &str) -> Option<Result<Self>> {
+        let (range, rest) = match LineRange::parse(line.trim_start()) {
+            Ok(parsed) => parsed,
+            Err(err) => return Some(Err(err)),
+        };
+
+        let rest = rest.trim_start();
+        let rest = rest
+            .strip_prefix("substitute")
+            .or_else(|| rest.strip_prefix('s'))?;
+
+        let delimiter = rest
+            .chars()
+            .next()
+            .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && !matches!(c, '\\' | '"'))?;
+
+        let rest = &rest[delimiter.len_utf8()..];
+        let (pattern, rest) = split_delimited(rest, delimiter);
+        let (replacement, flags) = split_delimited(rest.unwrap_or_default(), delimiter);
+
+        let mut substitute = Self {
+            range,
+            pattern,
+            replacement,
+            global: false,
+            confirm: false,
+        };
+
+        for flag in flags.unwrap_or_default().trim_end().chars() {
+            match flag {
+                'g' => substitute.global = true,
+                'c' => substitute.confirm = true,
+                _ => return Some(Err(anyhow::anyhow!("Unknown flag: {flag}"))),
+            }
+        }
+
+        Some(Ok(substitute))
+    }
+}
+
+/// Splits at the first unescaped `delimiter`, escaped delimiter loses its backslash
+fn split_delimited(s: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut part = String::new();
+    let mut chars = s.char_indices();
+
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, c)) if c == delimiter => part.push(c),
+                Some((_, c)) => {
+                    part.push('\\');
+                    part.push(c);
+                }
+                None => part.push('\\'),
+            },
+            c if c == delimiter => return (part, Some(&s[idx + c.len_utf8()..])),
+            c => part.push(c),
+        }
+    }
+
+    (part, None)
+}
+
+/// Replaces matches in lines covered by the range as a single undo step, empty pattern
+/// reuses the last search
+pub fn substitute(ctx: &mut CommandData, substitute: &Substitute) -> Result<()> {
+    let pattern = if substitute.pattern.is_empty() {
+        let search = ctx.editor.last_search.as_ref();
+        search
+            .context("No previous pattern")?
+            .regex
+            .as_str()
+            .to_string()
+    } else {
+        substitute.pattern.clone()
+    };
+
+    let substitution = Substitution::new(&pattern, &substitute.replacement)?;
+
+    // matches stay highlighted and `n` jumps between them
+    ctx.editor.last_search = Some(Search::new(Regex::new(&pattern)?, Direction::Forward));
+
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    let lines = substitute
+        .range
+        .unwrap_or(LineRange::current())
+        .resolve(buf, doc)?;
+
+    let text = doc.text();
+    let mut edits = vec![];
+
+    for line_idx in lines {
+        let line_start = text.line_to_char(line_idx);
+        let line = text.line(line_idx).to_string();
+        let content = line.strip_suffix('\n').unwrap_or(&line);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+
+        edits.extend(
+            substitution
+                .replacements(content, substitute.global)
+                .into_iter()
+                .map(|(range, replaced)| {
+                    (range.start + line_start..range.end + line_start, replaced)
+                }),
+        );
+    }
+
+    if edits.is_empty() {
+        bail!("Pattern not found: {pattern}");
+    }
+
+    if substitute.confirm {
+        goto(buf, doc, edits[0].0.start);
+        ctx.push_widget(confirm_widget(edits));
+    } else {
+        apply_edits(buf, doc, &edits);
+    }
+
+    Ok(())
+}
+
+/// Asks about every match, accepted ones are replaced together when asking is done
+fn confirm_widget(edits: Vec<(Range<usize>, String)>) -> ConfirmWidget {
+    let question = |replaced: &str| format!("replace with {replaced} (y/n/a/q/l)?");
+
+    let first = question(&edits[0].1);
+    let mut accepted = vec![];
+    let mut idx = 0;
+
+    ConfirmWidget::new(first, move |key, ctx| {
+        let finish = match key {
+            KeyCode::Char('y') => {
+                accepted.push(edits[idx].clone());
+                false
+            }
+            KeyCode::Char('l') => {
+                accepted.push(edits[idx].clone());
+                true
+            }
+            KeyCode::Char('a') => {
+                accepted.extend_from_slice(&edits[idx..]);
+                true
+            }
+            KeyCode::Char('n') => false,
+            KeyCode::Char('q') | KeyCode::Esc => true,
+            _ => return Some(question(&edits[idx].1)),
+        };
+
+        idx += 1;
+
+        let (buf, doc) = current_mut!(ctx.editor);
+
+        if finish || idx == edits.len() {
+            apply_edits(buf, doc, &accepted);
+            None
+        } else {
+            goto(buf, doc, edits[idx].0.start);
+            Some(question(&edits[idx].1))
+        }
+    })
+}
+
+/// Replaces edits and puts cursor on the first non-blank char of the last changed line
+fn apply_edits(buf: &mut Buffer, doc: &mut Document, edits: &[(Range<usize>, String)]) {
+    let Some((last, _)) = edits.last() else {
+        return;
+    };
+
+    // position of the last edit shifted by preceding ones
+    let shift = edits[..edits.len() - 1]
+        .iter()
+        .fold(0isize, |shift, (range, replaced)| {
+            shift + replaced.chars().count() as isize - range.len() as isize
+        });
+    let last_start = last.start.saturating_add_signed(shift);
+
+    let text = doc.text();
+    let mut lines = edits
+        .iter()
+        .map(|(range, _)| text.char_to_line(range.start))
+        .collect::<Vec<_>>();
+    lines.dedup();
+
+    let before = buf.selections().clone();
+    replace_ranges(buf, doc, &before, edits);
+
+    let text = doc.text();
+    let line_idx = text.char_to_line(last_start.min(text.len_chars()));
+    let indent = text
+        .line(line_idx)
+        .chars()
+        .take_while(|c| matches!(c, ' ' | '\t'))
+        .count();
+
+    goto(buf, doc, text.line_to_char(line_idx) + indent);
+
+    log::info!("{} substitutions on {} lines", edits.len(), lines.len());
+}
+
+fn goto(buf: &mut Buffer, doc: &Document, pos: usize) {
+    buf.update_text_position(doc, pos, UpdateBufPositionParams::default());
+}
+
+/// Substitutes when command line is `:s`, returns `false` for other commands
+pub fn try_substitute(ctx: &mut CommandData, line: &str) -> bool {
+    let Some(parsed) = Substitute::parse(line) else {
+        return false;
+    };
+
+    if let Err(err) = parsed.and_then(|parsed| substitute(ctx, &parsed)) {
+        log::error!("{err}");
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::Event;
+
+    use super::super::test::*;
+    use super::*;
+    use crate::{
+        client::composer::{Context, Widget},
+        current,
+        editor::{utils::parse_mapping, Editor},
+    };
+
+    fn execute(editor: &mut Editor, line: &str) {
+        let mut context = Context { editor };
+        context.execute_command_line(line);
+    }
+
+    fn text(editor: &Editor) -> String {
+        current!(editor).1.text().to_string()
+    }
+
+    #[test]
+    fn parse() {
+        let parsed = Substitute::parse("%s/a\\/b/c/gc").unwrap().unwrap();
+        assert_eq!(parsed.range, Some(LineRange::Whole));
+        assert_eq!(parsed.pattern, "a/b");
+        assert_eq!(parsed.replacement, "c");
+        assert!(parsed.global && parsed.confirm);
+
+        let parsed = Substitute::parse("s#x\\d#y").unwrap().unwrap();
+        assert_eq!(parsed.range, None);
+        assert_eq!(parsed.pattern, "x\\d");
+        assert_eq!(parsed.replacement, "y");
+        assert!(!parsed.global && !parsed.confirm);
+
+        assert!(Substitute::parse("save").is_none());
+        assert!(Substitute::parse("s").is_none());
+        assert!(Substitute::parse("s/a/b/x").unwrap().is_err());
+    }
+
+    #[test]
+    fn substitute_ranges() {
+        let start = "a a\na a\na a\n";
+
+        let mut editor = test_editor(4, start);
+        execute(&mut editor, "s/a/b/");
+        assert_eq!(text(&editor), "a a\nb a\na a\n");
+
+        let mut editor = test_editor(0, start);
+        execute(&mut editor, "%s/a/b/g");
+        assert_eq!(text(&editor), "b b\nb b\nb b\n");
+        assert_eq!(
+            current!(editor).0.text_pos(),
+            8,
+            "cursor on the last changed line"
+        );
+
+        let mut editor = test_editor(0, start);
+        execute(&mut editor, "2,$s/a/b/");
+        assert_eq!(text(&editor), "a a\nb a\nb a\n");
+
+        let mut editor = test_editor(0, start);
+        feed_keys(&mut editor, "jvj<ESC>gg");
+        execute(&mut editor, "'<,'>s/ a/-/");
+        assert_eq!(text(&editor), "a a\na-\na-\n");
+    }
+
+    #[test]
+    fn capture_groups_and_newlines() {
+        let mut editor = test_editor(0, "key=value\n");
+        execute(&mut editor, r"s/(\w+)=(\w+)/\2: \1\n/");
+        assert_eq!(text(&editor), "value: key\n\n");
+    }
+
+    #[test]
+    fn word_boundary_in_non_ascii_text() {
+        let mut editor = test_editor(0, "zażółć foo\nfoo\n");
+        execute(&mut editor, r"%s/\bfoo\b/bar/");
+        assert_eq!(text(&editor), "zażółć bar\nbar\n");
+    }
+
+    #[test]
+    fn single_undo_step() {
+        let start = "a\na\na\n";
+        let mut editor = test_editor(0, start);
+        execute(&mut editor, "%s/a/bb/");
+        assert_eq!(text(&editor), "bb\nbb\nbb\n");
+
+        feed_keys(&mut editor, "u");
+        assert_eq!(text(&editor), start);
+    }
+
+    #[test]
+    fn empty_pattern_uses_last_search() {
+        let mut editor = test_editor(0, "foo bar\n");
+        editor.last_search = Some(Search::new(Regex::new("o+").unwrap(), Direction::Forward));
+
+        execute(&mut editor, "s//0/");
+        assert_eq!(text(&editor), "f0 bar\n");
+    }
+
+    #[test]
+    fn confirm() {
+        let mut editor = test_editor(0, "a a a a\n");
+        let edits = (0..4)
+            .map(|i| (i * 2..i * 2 + 1, "b".to_string()))
+            .collect::<Vec<_>>();
+
+        let mut widget = confirm_widget(edits);
+        let mut context = Context {
+            editor: &mut editor,
+        };
+
+        for key in parse_mapping("ynxy").unwrap() {
+            widget.handle_event(&Event::Key(key), &mut context);
+        }
+        assert_eq!(current!(context.editor).0.text_pos(), 6);
+        assert_eq!(text(context.editor), "a a a a\n", "nothing replaced yet");
+
+        for key in parse_mapping("n").unwrap() {
+            widget.handle_event(&Event::Key(key), &mut context);
+        }
+        assert_eq!(text(&editor), "b a b a\n");
+        feed_keys(&mut editor, "u");
+        assert_eq!(text(&editor), "a a a a\n");
+    }
+}
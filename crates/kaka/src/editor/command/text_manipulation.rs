@@ -1,103 +1,569 @@
+use std::ops::Range;
+
 use buffer::Buffer;
+use crossterm::event::KeyCode;
 use kaka_core::{
     document::{Document, TransactionLeave},
-    graphemes::next_grapheme_boundary,
+    graphemes::{next_grapheme_boundary, nth_next_grapheme_boundary},
+    selection::{Selection, SelectionSet},
+    transaction::Assoc,
 };
 
 use crate::{
     current_mut,
     editor::{
         buffer::{self, UpdateBufPositionParams},
+        register::{RegisterValue, UNNAMED},
         ModeKind,
     },
 };
 
-use super::CommandData;
+use super::{operator_delete, yank_ranges, CommandData};
+
+/// `x`, deletes character under cursor, in visual modes the selection like `d`
+pub fn kill(ctx: &mut CommandData) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    if buf.mode().is_visual() {
+        operator_delete(ctx);
+        return;
+    }
+
+    let before = buf.selections().clone();
+    let ranges = char_ranges(buf, doc);
+
+    let register = ctx.register.unwrap_or(UNNAMED);
+    yank_ranges(ctx.editor, register, &ranges, false, true);
 
-pub fn kill_line(ctx: &mut CommandData) {
     let (buf, doc) = current_mut!(ctx.editor);
+    delete_ranges(
+        buf,
+        doc,
+        &before,
+        ranges,
+        UpdateBufPositionParams {
+            line_keep: true,
+            allow_on_newline: false,
+            ..Default::default()
+        },
+        true,
+    );
+}
+
+/// `p`, puts text after cursor or lines below the current one, in visual mode it replaces
+/// selection and the replaced text is yanked
+pub fn put_after(ctx: &mut CommandData) {
+    put(ctx, true);
+}
+
+/// `P`, puts text before cursor or lines above the current one, in visual mode it replaces
+/// selection and registers are left as they are
+pub fn put_before(ctx: &mut CommandData) {
+    put(ctx, false);
+}
+
+fn put(ctx: &mut CommandData, after: bool) {
+    let register = ctx.register.unwrap_or(UNNAMED);
+    let count = ctx.count.unwrap_or(1);
+
+    let Some(mut value) = ctx.editor.registers.get(register) else {
+        log::error!("Nothing in register {register}");
+        return;
+    };
+
+    if value.linewise {
+        for line in value.values.iter_mut().filter(|line| !line.ends_with('\n')) {
+            line.push('\n');
+        }
+    }
+
+    let (buf, doc) = current_mut!(ctx.editor);
+    let before = buf.selections().clone();
+
+    if buf.mode().is_visual() {
+        let ranges = buf.visual_ranges(doc);
+        let texts = put_texts(&value, ranges.len(), count);
+        let mode = buf.mode();
+        let linewise = mode == ModeKind::VisualLine;
+
+        buf.switch_mode(ModeKind::Normal);
+        if mode == ModeKind::VisualBlock {
+            buf.set_cursors(doc, ranges.iter().map(|range| range.start).min());
+        } else {
+            buf.transform_cursors(doc, |s| {
+                Selection::at_pos(s.start()).with_column(s.column())
+            });
+        }
+
+        if after {
+            yank_ranges(ctx.editor, UNNAMED, &ranges, linewise, true);
+        }
+
+        // lines are put on their own
+        let edits = ranges
+            .into_iter()
+            .zip(texts)
+            .map(|(range, text)| match (linewise, value.linewise) {
+                (false, true) => (range, format!("\n{text}")),
+                (true, false) => (range, format!("{text}\n")),
+                _ => (range, text),
+            })
+            .collect::<Vec<_>>();
+
+        let (buf, doc) = current_mut!(ctx.editor);
+        replace_ranges(buf, doc, &before, &edits);
+        return;
+    }
 
     let text = doc.text();
-    let line_start = buf.line_char();
-    let line_end = text.line_to_char(buf.line_idx() + 1);
+    let texts = put_texts(&value, before.len(), count);
+
+    // (position, text, cursor offset inside of the text) for every cursor
+    let mut puts = before
+        .iter()
+        .zip(texts)
+        .map(|(selection, mut put)| {
+            let pos = selection.head();
+
+            if value.linewise {
+                let line = text.char_to_line(pos);
+                let pos = text.line_to_char(if after { line + 1 } else { line });
+                let indent = put.chars().take_while(|c| matches!(c, ' ' | '\t')).count();
 
-    doc.with_new_transaction(buf.text_pos(), |doc, tx| {
-        tx.move_to(line_start);
-        tx.delete(line_end - line_start);
+                // last line without line break gets one
+                if pos == text.len_chars() && pos > 0 && text.char(pos - 1) != '\n' {
+                    put.pop();
+                    return (pos, format!("\n{put}"), indent + 1);
+                }
+
+                (pos, put, indent)
+            } else {
+                let on_newline = matches!(text.get_char(pos), Some('\n') | None);
+                let pos = if after && !on_newline {
+                    next_grapheme_boundary(text.slice(..), pos)
+                } else {
+                    pos
+                };
+
+                // cursor ends on the last put character, unless it spans lines
+                let offset = match put.contains('\n') {
+                    true => 0,
+                    false => put.chars().count().saturating_sub(1),
+                };
+
+                (pos, put, offset)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // cursor of every put, in order of selections
+    let mut targets = vec![0; puts.len()];
+    let mut order = (0..puts.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| puts[idx].0);
+
+    let mut shift = 0;
+    for &idx in &order {
+        let (pos, ref put, offset) = puts[idx];
+        targets[idx] = pos + shift + offset;
+        shift += put.chars().count();
+    }
+
+    puts.sort_by_key(|&(pos, _, _)| pos);
+
+    let start = match puts.last() {
+        Some(&(pos, _, _)) => pos,
+        None => return,
+    };
+
+    doc.with_new_transaction(start, &before, |doc, tx| {
+        // go backwards, so positions of preceding puts stay valid
+        for (pos, put, _) in puts.iter().rev() {
+            tx.move_to(*pos);
+            tx.insert(put.as_str());
+        }
 
         tx.apply(doc.text_mut());
 
-        TransactionLeave::Commit
+        let mut targets = targets.iter();
+        buf.for_each_cursor(doc, |buf| {
+            let target = *targets.next().expect("Target of every cursor");
+            buf.update_text_position(doc, target, Default::default());
+        });
+
+        tx.move_to(buf.text_pos());
+
+        TransactionLeave::Commit(buf.selections().clone())
     });
 }
 
-pub fn kill(ctx: &mut CommandData) {
+/// `r{char}`, replaces count of graphemes under cursors with the character, nothing is replaced
+/// when a line is too short
+pub fn replace_char(ctx: &mut CommandData) {
+    let replacement = match ctx.key_argument.map(|key| key.code) {
+        Some(KeyCode::Char(c)) => c,
+        Some(KeyCode::Enter) => '\n',
+        _ => return,
+    };
+
+    let count = ctx.count.unwrap_or(1).max(1);
+
     let (buf, doc) = current_mut!(ctx.editor);
+    let text = doc.text();
+    let before = buf.selections().clone();
 
-    if let Some(selection) = buf.selection().map(|s| s.range()) {
-        kill_selection(buf, doc, selection);
-        buf.switch_mode(ModeKind::Normal);
+    let mut edits = Vec::with_capacity(before.len());
+    for selection in before.iter() {
+        let pos = selection.head();
+        let line_idx = text.char_to_line(pos);
+        let line = text.line(line_idx);
+        let len = line.len_chars();
+        let line_end =
+            text.line_to_char(line_idx) + len - usize::from(len > 0 && line.char(len - 1) == '\n');
+
+        let end = nth_next_grapheme_boundary(text.slice(..), pos, count);
+        if end > line_end || end == pos {
+            return;
+        }
+
+        // like in vim, all of the characters are replaced by single line break
+        let replaced = match replacement {
+            '\n' => String::from('\n'),
+            c => c.to_string().repeat(count),
+        };
+
+        edits.push((pos..end, replaced));
+    }
+
+    // cursor stays on the last replaced character or goes to the new line
+    let mut shift = 0isize;
+    let mut targets = edits
+        .iter()
+        .map(|(range, replaced)| {
+            let start = range.start.saturating_add_signed(shift);
+            let len = replaced.chars().count();
+            shift += len as isize - range.len() as isize;
+
+            match replacement {
+                '\n' => start + 1,
+                _ => start + len - 1,
+            }
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    replace_ranges(buf, doc, &before, &edits);
+
+    buf.for_each_cursor(doc, |buf| {
+        let pos = targets.next().unwrap_or(buf.text_pos());
+        buf.update_text_position(doc, pos, Default::default());
+    });
+}
+
+/// Text put by every cursor, each one puts its own value when register holds as many of them
+fn put_texts(value: &RegisterValue, cursors: usize, count: usize) -> Vec<String> {
+    if value.values.len() == cursors {
+        value.values.iter().map(|text| text.repeat(count)).collect()
     } else {
-        kill_char(buf, doc);
+        vec![value.joined().repeat(count); cursors]
     }
 }
 
-fn kill_selection(buf: &mut Buffer, doc: &mut Document, (start, mut end): (usize, usize)) {
-    doc.with_new_transaction(start, |doc, tx| {
-        let mut tmp = doc.text().clone();
-        end = next_grapheme_boundary(tmp.slice(..), end);
-
-        tx.delete(end - start);
-        tx.apply(&mut tmp);
-
-        if let Some(new_pos) = buf.update_text_position(
-            &tmp,
-            start,
-            UpdateBufPositionParams {
-                line_keep: false,
-                allow_on_newline: false,
-                ..Default::default()
-            },
-        ) {
-            tx.move_to(new_pos);
+fn char_ranges(buf: &Buffer, doc: &Document) -> Vec<Range<usize>> {
+    let text = doc.text();
+
+    buf.selections()
+        .iter()
+        .map(|selection| selection.head())
+        .filter(|pos| {
+            let line = text.line(text.char_to_line(*pos));
+            !matches!(line.get_char(0), Some('\n') | None)
+        })
+        .map(|pos| pos..next_grapheme_boundary(text.slice(..), pos))
+        .collect()
+}
+
+/// Deletes `ranges` in one transaction and moves cursors accordingly, transaction is kept
+/// open unless `commit`
+///
+/// `before` are selections restored when deletion is undone.
+pub(super) fn delete_ranges(
+    buf: &mut Buffer,
+    doc: &mut Document,
+    before: &SelectionSet,
+    mut ranges: Vec<Range<usize>>,
+    params: UpdateBufPositionParams,
+    commit: bool,
+) {
+    ranges.sort_by_key(|range| range.start);
+    ranges.dedup_by(|next, prev| {
+        let overlaps = next.start <= prev.end;
+        if overlaps {
+            prev.end = prev.end.max(next.end);
+        }
+        overlaps
+    });
+
+    let start = match ranges.last() {
+        Some(range) => range.start,
+        None => return,
+    };
+
+    doc.with_new_transaction(start, before, |doc, tx| {
+        // go backwards, so positions of preceding ranges stay valid
+        for range in ranges.iter().rev() {
+            tx.move_to(range.start);
+            tx.delete(range.len());
         }
 
         tx.apply(doc.text_mut());
 
-        TransactionLeave::Commit
+        buf.map_cursors(doc, tx, Assoc::After);
+        buf.for_each_cursor(doc, |buf| {
+            buf.update_text_position(doc, buf.text_pos(), params);
+        });
+
+        tx.move_to(buf.text_pos());
+
+        if commit {
+            TransactionLeave::Commit(buf.selections().clone())
+        } else {
+            TransactionLeave::Keep
+        }
     });
 }
 
-fn kill_char(buf: &mut Buffer, doc: &mut Document) {
-    let pos = buf.text_pos();
+/// Replaces every range with its text in single transaction, ranges must not overlap
+pub(super) fn replace_ranges(
+    buf: &mut Buffer,
+    doc: &mut Document,
+    before: &SelectionSet,
+    edits: &[(Range<usize>, String)],
+) {
+    let mut edits = edits.iter().collect::<Vec<_>>();
+    edits.sort_by_key(|(range, _)| range.start);
 
-    doc.with_new_transaction(pos, |doc, tx| {
-        if matches!(
-            doc.text().line(buf.line_idx()).get_char(0),
-            Some('\n') | None
-        ) {
-            return TransactionLeave::Rollback;
-        }
+    let start = match edits.last() {
+        Some((range, _)) => range.start,
+        None => return,
+    };
 
-        tx.delete(1);
-        let mut tmp = doc.text().clone();
-        tx.apply(&mut tmp);
-
-        if let Some(new_pos) = buf.update_text_position(
-            &tmp,
-            pos,
-            UpdateBufPositionParams {
-                line_keep: true,
-                allow_on_newline: false,
-                ..Default::default()
-            },
-        ) {
-            tx.move_to(new_pos);
-            log::info!("Pos: {}", buf.text_pos());
+    doc.with_new_transaction(start, before, |doc, tx| {
+        // go backwards, so positions of preceding ranges stay valid
+        for (range, replaced) in edits.iter().rev() {
+            tx.move_to(range.start);
+            tx.delete(range.len());
+            tx.insert(replaced.as_str());
         }
 
         tx.apply(doc.text_mut());
 
-        TransactionLeave::Commit
+        buf.map_cursors(doc, tx, Assoc::Before);
+        buf.for_each_cursor(doc, |buf| {
+            buf.update_text_position(doc, buf.text_pos(), Default::default());
+        });
+
+        tx.move_to(buf.text_pos());
+
+        TransactionLeave::Commit(buf.selections().clone())
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::test::*;
+    use super::*;
+    use crate::{
+        current,
+        editor::command::{add_cursor_below, move_right, switch_to_visual_mode},
+    };
+
+    #[test]
+    fn kill_char() {
+        test_cmd(1, "012\n", kill, |buf: B, doc: D| {
+            assert_eq!(doc.text(), "02\n");
+            assert_eq!(buf.text_pos(), 1);
+        });
+
+        test_cmd(2, "012\n", kill, |buf: B, doc: D| {
+            assert_eq!(doc.text(), "01\n");
+            assert_eq!(buf.text_pos(), 1);
+        });
+
+        test_cmd(0, "\n", kill, |buf: B, doc: D| {
+            assert_eq!(doc.text(), "\n");
+            assert_eq!(buf.text_pos(), 0);
+        });
+    }
+
+    #[test]
+    fn kill_all_cursors() {
+        let text = "012\n456\n89A";
+
+        test_cmds(1, text, &[add_cursor_below, kill], |buf: B, doc: D| {
+            assert_eq!(doc.text(), "02\n46\n89A");
+            assert_eq!(
+                buf.selections().ranges().collect::<Vec<_>>(),
+                [(1, 1), (4, 4)]
+            );
+        });
+
+        test_cmds(
+            0,
+            text,
+            &[add_cursor_below, switch_to_visual_mode, move_right, kill],
+            |buf: B, doc: D| {
+                assert_eq!(doc.text(), "2\n6\n89A");
+                assert_eq!(buf.mode(), ModeKind::Normal);
+                assert_eq!(
+                    buf.selections().ranges().collect::<Vec<_>>(),
+                    [(0, 0), (2, 2)]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn kill_selection_keeps_line() {
+        let editor = test_keys(1, "012\n456\n", "vlx");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "0\n456\n");
+        assert_eq!(
+            buf.text_pos(),
+            0,
+            "cursor stays on the line, not on its line break"
+        );
+
+        let editor = test_keys(6, "012\n456\n", "vkx");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "01\n");
+        assert_eq!(buf.text_pos(), 1);
+    }
+
+    #[test]
+    fn kill_yanks_text() {
+        let mut editor = test_keys(1, "012\n", "x");
+
+        assert_eq!(editor.registers.get(UNNAMED).unwrap().values, ["1"]);
+    }
+
+    #[test]
+    fn put_lines() {
+        let text = "012\n456\n";
+
+        let editor = test_keys(1, text, "yyp");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "012\n012\n456\n");
+        assert_eq!(buf.text_pos(), 4);
+
+        let editor = test_keys(5, text, "yy2P");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "012\n456\n456\n456\n");
+        assert_eq!(buf.text_pos(), 4);
+
+        let editor = test_keys(2, "a\n  b", "jyykp");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "a\n  b\n  b");
+        assert_eq!(buf.text_pos(), 4, "first non-blank of put line");
+
+        let editor = test_keys(2, "a\n  b", "yyp");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "a\n  b\n  b", "last line gets line break");
+        assert_eq!(buf.text_pos(), 8);
+
+        let editor = test_keys(1, text, "yyjVp");
+        assert_eq!(current!(editor).1.text(), "012\n012\n");
+
+        let editor = test_keys(1, text, "ylVjp");
+        assert_eq!(
+            current!(editor).1.text(),
+            "1\n",
+            "text replacing lines is a line"
+        );
+    }
+
+    #[test]
+    fn put_chars() {
+        let editor = test_keys(0, "abc\n", "xp");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "bac\n");
+        assert_eq!(buf.text_pos(), 1);
+
+        let editor = test_keys(0, "abc\n", "yl3P");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "aaaabc\n");
+        assert_eq!(buf.text_pos(), 2);
+
+        let mut editor = test_keys(0, "abc def\n", "yewvep");
+        assert_eq!(editor.registers.get(UNNAMED).unwrap().values, ["def"]);
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "abc abc\n");
+        assert_eq!(buf.mode(), ModeKind::Normal);
+
+        let mut editor = test_keys(0, "ab\ncd\n", "<C-j>ylp");
+        assert_eq!(
+            current!(editor).1.text(),
+            "aab\nccd\n",
+            "value of every cursor"
+        );
+        feed_keys(&mut editor, "<ESC>p");
+        assert_eq!(current!(editor).1.text(), "aab\ncca\ncd\n", "values joined");
+    }
+
+    #[test]
+    fn selected_registers() {
+        let text = "012\n456\n";
+
+        let mut editor = test_keys(0, text, "\"ayyj\"byy\"ap\"_ddk\"bP");
+        assert_eq!(current!(editor).1.text(), "012\n456\n456\n");
+        assert_eq!(editor.registers.get(UNNAMED).unwrap().values, ["456\n"]);
+
+        let mut editor = test_keys(0, text, "\"+yyj\"Ayl\"+p");
+        assert_eq!(current!(editor).1.text(), "012\n456\n012\n");
+        assert_eq!(editor.registers.get('a').unwrap().values, ["4"]);
+
+        let editor = test_keys(0, text, "\"!p");
+        assert_eq!(current!(editor).1.text(), text, "invalid register");
+    }
+
+    #[test]
+    fn replace_char() {
+        let editor = test_keys(0, "abc\n", "rx");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "xbc\n");
+        assert_eq!(buf.text_pos(), 0);
+
+        let editor = test_keys(0, "abc\n", "3rx");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "xxx\n");
+        assert_eq!(buf.text_pos(), 2);
+
+        let editor = test_keys(0, "abc\n", "4rx");
+        assert_eq!(current!(editor).1.text(), "abc\n", "line is too short");
+
+        let editor = test_keys(1, "abc\n", "r<CR>");
+        let (buf, doc) = current!(editor);
+        assert_eq!(doc.text(), "a\nc\n");
+        assert_eq!(buf.text_pos(), 2);
+
+        let editor = test_keys(0, "e\u{301}b\n", "2rx");
+        assert_eq!(current!(editor).1.text(), "xx\n");
+
+        let editor = test_keys(0, "abc\n", "2rxu");
+        assert_eq!(current!(editor).1.text(), "abc\n");
+
+        let editor = test_keys(0, "abc\n", "rxl.");
+        assert_eq!(current!(editor).1.text(), "xxc\n");
+    }
+
+    #[test]
+    fn kill_is_single_undo_step() {
+        test_cmds(
+            1,
+            "012\n456\n",
+            &[add_cursor_below, kill, crate::editor::command::undo],
+            |_: B, doc: D| {
+                assert_eq!(doc.text(), "012\n456\n");
+            },
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use kaka_core::ropey::Rope;
+
+use crate::current_mut;
+
+use super::CommandData;
+
+pub fn select_inner_paragraph(ctx: &mut CommandData) {
+    select_paragraph(ctx, false);
+}
+
+pub fn select_a_paragraph(ctx: &mut CommandData) {
+    select_paragraph(ctx, true);
+}
+
+fn select_paragraph(ctx: &mut CommandData, around: bool) {
+    let (buf, doc) = current_mut!(ctx.editor);
+
+    if !buf.mode().is_selecting() {
+        return;
+    }
+
+    buf.for_each_cursor(doc, |buf| {
+        let text = doc.text();
+        let (first, last) = paragraph(text, buf.line_idx(), around);
+
+        let last_line_end = text.line_to_char(last) + text.line(last).len_chars().saturating_sub(1);
+
+        buf.update_anchor(text.line_to_char(first));
+        buf.update_text_position(doc, last_line_end, Default::default());
+    });
+}
+
+/// Returns first and last line of paragraph containing `line_idx`.
+///
+/// Blank lines separate paragraphs, `around` includes blank lines following paragraph
+/// or preceding ones if there are none.
+fn paragraph(text: &Rope, line_idx: usize, around: bool) -> (usize, usize) {
+    let is_blank = |line_idx| text.line(line_idx).chars().all(char::is_whitespace);
+
+    // empty line after the final line break is not a line of its own
+    let mut last_idx = text.len_lines().saturating_sub(1);
+    if last_idx > 0 && text.line(last_idx).len_chars() == 0 {
+        last_idx -= 1;
+    }
+
+    let line_idx = line_idx.min(last_idx);
+    let blank = is_blank(line_idx);
+
+    let mut first = line_idx;
+    while first > 0 && is_blank(first - 1) == blank {
+        first -= 1;
+    }
+
+    let mut last = line_idx;
+    while last < last_idx && is_blank(last + 1) == blank {
+        last += 1;
+    }
+
+    if around {
+        if last < last_idx {
+            while last < last_idx && is_blank(last + 1) != blank {
+                last += 1;
+            }
+        } else {
+            while first > 0 && is_blank(first - 1) != blank {
+                first -= 1;
+            }
+        }
+    }
+
+    (first, last)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::*;
+    use crate::{current, editor::ModeKind};
+
+    #[test]
+    fn paragraph_operator() {
+        let text = "012\n456\n\n\n9AB\n";
+
+        let editor = test_keys(5, text, "dip");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "\n\n9AB\n");
+
+        let editor = test_keys(5, text, "dap");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "9AB\n");
+
+        let editor = test_keys(12, text, "dap");
+        let (_, doc) = current!(editor);
+        assert_eq!(
+            doc.text(),
+            "012\n456\n",
+            "blank lines before last paragraph"
+        );
+
+        let editor = test_keys(12, text, "<GT>ap");
+        let (_, doc) = current!(editor);
+        assert_eq!(doc.text(), "012\n456\n\n\n    9AB\n");
+    }
+
+    #[test]
+    fn paragraph_visual() {
+        let editor = test_keys(1, "012\n456\n\n9AB", "vip");
+        let (buf, _) = current!(editor);
+
+        assert_eq!(buf.mode(), ModeKind::Visual);
+        assert_eq!(buf.selections().ranges().collect::<Vec<_>>(), [(0, 6)]);
+    }
+}
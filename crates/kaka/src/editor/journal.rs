@@ -0,0 +1,201 @@
+//! Periodic journals of unsaved changes, replayed after the editor died without saving them
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use kaka_core::document::{self, DocumentId, Journal, JournalState};
+
+use super::{Buffer, Editor};
+
+/// Journal left by a session that ended without saving, waits for user to decide about it
+#[derive(Debug)]
+pub struct Recovery {
+    pub document: DocumentId,
+    pub path: PathBuf,
+    pub journal: Journal,
+}
+
+/// Journal kept up to date with the document
+#[derive(Debug)]
+pub(super) struct Journaled {
+    path: PathBuf,
+    /// Document state of the last written journal, it's not written again until this changes
+    written: Option<JournalState>,
+}
+
+impl Editor {
+    /// Takes over journal of just opened document, unless there is something to recover from it
+    /// or other instance has the document open
+    pub(super) fn open_journal(&mut self, id: DocumentId) {
+        let document = &self.documents[&id];
+        let (Some(dir), Some(path)) = (self.recovery_dir.as_deref(), document.path()) else {
+            return;
+        };
+
+        let name = document.display_name().into_owned();
+
+        let journal_path =
+            match document::create_private_dir(dir).and_then(|()| Journal::path_in(dir, path)) {
+                Ok(journal_path) => journal_path,
+                Err(err) => {
+                    log::warn!("Journal of {name}: {err}");
+                    return;
+                }
+            };
+
+        match Journal::read(&journal_path) {
+            Ok(journal) if journal.pid() != std::process::id() && process_alive(journal.pid()) => {
+                log::warn!(
+                    "{name} is open in another kaka instance (pid {}), changes are not journaled",
+                    journal.pid()
+                );
+                return;
+            }
+            Ok(journal) if !journal.is_empty() => {
+                self.recoveries.push_back(Recovery {
+                    document: id,
+                    path: journal_path,
+                    journal,
+                });
+                return;
+            }
+            Ok(_) => (),
+            Err(document::Error::Io(err)) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => log::warn!("Journal of {name}: {err}"),
+        }
+
+        self.start_journal(id, journal_path);
+    }
+
+    /// Journals document to `path` from now on, whatever was there is replaced
+    pub fn start_journal(&mut self, document: DocumentId, path: PathBuf) {
+        self.journals.insert(
+            document,
+            Journaled {
+                path,
+                written: None,
+            },
+        );
+
+        self.write_journals();
+    }
+
+    /// Next journal found on opening a document
+    pub fn take_recovery(&mut self) -> Option<Recovery> {
+        self.recoveries.pop_front()
+    }
+
+    /// Writes journals of documents changed since the last write, journals of documents no
+    /// longer shown in any buffer are removed
+    pub fn write_journals(&mut self) {
+        let shown = self
+            .buffers
+            .values()
+            .map(Buffer::document_id)
+            .collect::<HashSet<_>>();
+
+        self.journals.retain(|id, journaled| {
+            let Some(document) = self.documents.get(id).filter(|_| shown.contains(id)) else {
+                remove_journal(&journaled.path);
+                return false;
+            };
+
+            if journaled
+                .written
+                .as_ref()
+                .is_some_and(|state| document.is_journaled(state))
+            {
+                return true;
+            }
+
+            // failed write is not retried until the next change, so error is logged once
+            if let Err(err) = document.journal().write(&journaled.path) {
+                log::error!("Journal of {}: {err}", document.display_name());
+            }
+            journaled.written = Some(document.journal_state());
+
+            true
+        });
+    }
+
+    /// Removes all journals, nothing is left to recover after regular exit
+    pub fn remove_journals(&mut self) {
+        for (_, journaled) in self.journals.drain() {
+            remove_journal(&journaled.path);
+        }
+    }
+}
+
+fn remove_journal(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(()) => (),
+        Err(err) if err.kind() == ErrorKind::NotFound => (),
+        Err(err) => log::error!("Can't remove journal {}: {err}", path.display()),
+    }
+}
+
+/// Signal 0 is not sent, only checked whether it could be, process of other user can't get it
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    // SAFETY: kill with signal 0 has no effects besides the error code
+    let signaled = unsafe { libc::kill(pid, 0) } == 0;
+
+    signaled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Process of other user can't be opened, but it's alive
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    use winapi::{
+        shared::winerror::ERROR_ACCESS_DENIED,
+        um::{
+            handleapi::CloseHandle,
+            minwinbase::STILL_ACTIVE,
+            processthreadsapi::{GetExitCodeProcess, OpenProcess},
+            winnt::PROCESS_QUERY_LIMITED_INFORMATION,
+        },
+    };
+
+    // SAFETY: handle is checked before use and closed after it
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            let denied = ERROR_ACCESS_DENIED as i32;
+            return std::io::Error::last_os_error().raw_os_error() == Some(denied);
+        }
+
+        let mut code = 0;
+        let running = GetExitCodeProcess(handle, &mut code) != 0 && code == STILL_ACTIVE;
+        CloseHandle(handle);
+
+        running
+    }
+}
+
+/// Without a way to tell, journal is assumed to be left by a crashed session
+#[cfg(not(any(unix, windows)))]
+const fn process_alive(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::process_alive;
+
+    #[cfg(unix)]
+    #[test]
+    fn living_process() {
+        assert!(process_alive(std::process::id()));
+
+        let mut exited = std::process::Command::new("true").spawn().unwrap();
+        exited.wait().unwrap();
+        assert!(!process_alive(exited.id()));
+    }
+}
@@ -47,7 +47,13 @@ impl Keymap {
                 .expect("Failed to find command")
         };
 
-        Self::with_mappings([("<ESC>", c("switch_to_normal_mode"))])
+        Self::with_mappings([
+            ("<ESC>", c("switch_to_normal_mode")),
+            ("<C-o>", c("execute_normal_command")),
+            ("<C-w>", c("delete_word_before")),
+            ("<C-u>", c("delete_to_line_start")),
+            ("<C-r>", c("insert_register")),
+        ])
     }
 
     pub fn visual_mode(registry: &CommandRegistry) -> Self {
@@ -61,6 +67,11 @@ impl Keymap {
             // mode_switch
             ("<ESC>", c("switch_to_normal_mode")),
             (":", c("command_mode")),
+            ("v", c("switch_to_visual_mode")),
+            ("V", c("switch_to_visual_line_mode")),
+            ("<C-v>", c("switch_to_visual_block_mode")),
+            ("I", c("visual_insert")),
+            ("A", c("visual_append")),
             // movement
             ("h", c("move_left")),
             ("j", c("move_down")),
@@ -68,8 +79,99 @@ impl Keymap {
             ("l", c("move_right")),
             ("gg", c("goto_line_default_top")),
             ("G", c("goto_line_default_bottom")),
+            ("w", c("move_next_word_start")),
+            ("b", c("move_prev_word_start")),
+            ("e", c("move_next_word_end")),
+            ("ge", c("move_prev_word_end")),
+            ("W", c("move_next_long_word_start")),
+            ("B", c("move_prev_long_word_start")),
+            ("E", c("move_next_long_word_end")),
+            ("}", c("move_next_paragraph")),
+            ("{", c("move_prev_paragraph")),
+            (")", c("move_next_sentence")),
+            ("(", c("move_prev_sentence")),
+            ("f", c("find_next_char")),
+            ("F", c("find_prev_char")),
+            ("t", c("till_next_char")),
+            ("T", c("till_prev_char")),
+            (";", c("repeat_find")),
+            (",", c("repeat_find_reverse")),
+            ("/", c("search_forward")),
+            ("?", c("search_backward")),
+            ("n", c("search_next")),
+            ("N", c("search_prev")),
+            // multi_cursor
+            ("<C-j>", c("add_cursor_below")),
+            ("<C-k>", c("add_cursor_above")),
+            // operator
+            ("d", c("operator_delete")),
+            ("c", c("operator_change")),
+            ("y", c("operator_yank")),
+            ("<GT>", c("operator_indent")),
+            ("<LT>", c("operator_outdent")),
+            ("~", c("operator_toggle_case")),
+            ("u", c("operator_lowercase")),
+            ("U", c("operator_uppercase")),
+            // text_object
+            ("ip", c("select_inner_paragraph")),
+            ("ap", c("select_a_paragraph")),
             // text_manipulation
             ("x", c("kill")),
+            ("p", c("put_after")),
+            ("P", c("put_before")),
+        ];
+
+        Self::with_mappings(mappings)
+    }
+
+    pub fn operator_pending_mode(registry: &CommandRegistry) -> Self {
+        let c = |name: &str| {
+            registry
+                .mappable_command_by_name(name)
+                .expect("Failed to find command")
+        };
+
+        let mappings = [
+            // mode_switch
+            ("<ESC>", c("switch_to_normal_mode")),
+            // movement
+            ("h", c("move_left")),
+            ("j", c("move_down")),
+            ("k", c("move_up")),
+            ("l", c("move_right")),
+            ("gg", c("goto_line_default_top")),
+            ("G", c("goto_line_default_bottom")),
+            ("w", c("move_next_word_start")),
+            ("b", c("move_prev_word_start")),
+            ("e", c("move_next_word_end")),
+            ("ge", c("move_prev_word_end")),
+            ("W", c("move_next_long_word_start")),
+            ("B", c("move_prev_long_word_start")),
+            ("E", c("move_next_long_word_end")),
+            ("}", c("move_next_paragraph")),
+            ("{", c("move_prev_paragraph")),
+            (")", c("move_next_sentence")),
+            ("(", c("move_prev_sentence")),
+            ("f", c("find_next_char")),
+            ("F", c("find_prev_char")),
+            ("t", c("till_next_char")),
+            ("T", c("till_prev_char")),
+            (";", c("repeat_find")),
+            (",", c("repeat_find_reverse")),
+            ("n", c("search_next")),
+            ("N", c("search_prev")),
+            // doubled operator acts on lines
+            ("d", c("current_line")),
+            ("c", c("current_line")),
+            ("y", c("current_line")),
+            ("<GT>", c("current_line")),
+            ("<LT>", c("current_line")),
+            ("~", c("current_line")),
+            ("u", c("current_line")),
+            ("U", c("current_line")),
+            // text_object
+            ("ip", c("select_inner_paragraph")),
+            ("ap", c("select_a_paragraph")),
         ];
 
         Self::with_mappings(mappings)
@@ -95,7 +197,10 @@ impl Keymap {
             ("I", c("switch_to_insert_mode_line_start")),
             ("a", c("switch_to_insert_mode_after")),
             ("A", c("switch_to_insert_mode_line_end")),
+            ("R", c("switch_to_replace_mode")),
             ("v", c("switch_to_visual_mode")),
+            ("V", c("switch_to_visual_line_mode")),
+            ("<C-v>", c("switch_to_visual_block_mode")),
             // movement
             ("h", c("move_left")),
             ("j", c("move_down")),
@@ -103,13 +208,54 @@ impl Keymap {
             ("l", c("move_right")),
             ("gg", c("goto_line_default_top")),
             ("G", c("goto_line_default_bottom")),
+            ("w", c("move_next_word_start")),
+            ("b", c("move_prev_word_start")),
+            ("e", c("move_next_word_end")),
+            ("ge", c("move_prev_word_end")),
+            ("W", c("move_next_long_word_start")),
+            ("B", c("move_prev_long_word_start")),
+            ("E", c("move_next_long_word_end")),
+            ("}", c("move_next_paragraph")),
+            ("{", c("move_prev_paragraph")),
+            (")", c("move_next_sentence")),
+            ("(", c("move_prev_sentence")),
+            ("f", c("find_next_char")),
+            ("F", c("find_prev_char")),
+            ("t", c("till_next_char")),
+            ("T", c("till_prev_char")),
+            (";", c("repeat_find")),
+            (",", c("repeat_find_reverse")),
+            ("/", c("search_forward")),
+            ("?", c("search_backward")),
+            ("n", c("search_next")),
+            ("N", c("search_prev")),
+            // multi_cursor
+            ("<C-j>", c("add_cursor_below")),
+            ("<C-k>", c("add_cursor_above")),
+            ("<ESC>", c("keep_primary_cursor")),
+            // operator
+            ("d", c("operator_delete")),
+            ("c", c("operator_change")),
+            ("y", c("operator_yank")),
+            ("<GT>", c("operator_indent")),
+            ("<LT>", c("operator_outdent")),
+            ("g~", c("operator_toggle_case")),
+            ("gu", c("operator_lowercase")),
+            ("gU", c("operator_uppercase")),
             // text_manipulation
-            ("dd", c("kill_line")),
             ("x", c("kill")),
+            ("p", c("put_after")),
+            ("P", c("put_before")),
+            ("r", c("replace_char")),
+            (".", c("repeat_last_change")),
+            ("q", c("record_macro")),
+            ("@", c("replay_macro")),
             (":", c("command_mode")),
             // history
             ("u", c("undo")),
             ("<C-r>", c("redo")),
+            ("g-", c("earlier")),
+            ("g+", c("later")),
         ];
 
         Self::with_mappings(mappings)
@@ -138,7 +284,7 @@ impl Keymap {
                 if len > 1 {
                     e.insert(KeymapTreeElement::Node(Self::default()));
                 } else {
-                    e.insert(KeymapTreeElement::Leaf(command));
+                    e.insert(KeymapTreeElement::leaf(command));
                     continue;
                 }
             }
@@ -148,7 +294,7 @@ impl Keymap {
                 .get_mut(&first)
                 .map(|elem| match elem {
                     KeymapTreeElement::Node(ref mut n) => n,
-                    KeymapTreeElement::Leaf(_) => unreachable!(),
+                    _ => unreachable!(),
                 })
                 .unwrap();
 
@@ -157,7 +303,7 @@ impl Keymap {
                     if idx < len - 1 {
                         e.insert(KeymapTreeElement::Node(Self::default()));
                     } else {
-                        e.insert(KeymapTreeElement::Leaf(command));
+                        e.insert(KeymapTreeElement::leaf(command));
                         break;
                     }
                 }
@@ -167,7 +313,7 @@ impl Keymap {
                     .get_mut(&keycode)
                     .map(|elem| match elem {
                         KeymapTreeElement::Node(ref mut n) => n,
-                        KeymapTreeElement::Leaf(_) => unreachable!(),
+                        _ => unreachable!(),
                     })
                     .unwrap();
             }
@@ -180,9 +326,21 @@ impl Keymap {
 #[derive(Debug)]
 pub enum KeymapTreeElement {
     Leaf(Arc<Command>),
+    /// Command taking next pressed key as an argument
+    Argument(Arc<Command>),
     Node(Keymap),
 }
 
+impl KeymapTreeElement {
+    fn leaf(command: Arc<Command>) -> Self {
+        if command.key_argument() {
+            Self::Argument(command)
+        } else {
+            Self::Leaf(command)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -193,4 +351,16 @@ mod test {
         let keymap = Keymap::normal_mode(&registry);
         println!("Keymap {keymap:#?}");
     }
+
+    #[test]
+    fn argument_element() {
+        let registry = CommandRegistry::populate();
+        let keymap = Keymap::normal_mode(&registry);
+
+        let f = super::super::utils::parse_mapping("f").unwrap()[0];
+        assert!(matches!(
+            keymap.feed(f),
+            Some(KeymapTreeElement::Argument(_))
+        ));
+    }
 }
@@ -0,0 +1,134 @@
+//! Keys recorded with `q` and replayed with `@`, they pass through the composer the same way
+//! typed ones do
+use crossterm::event::KeyEvent;
+
+/// Keys replayed for single typed key, stops macros which never end
+const MAX_REPLAYED_KEYS: usize = 100_000;
+
+#[derive(Debug, Default)]
+pub struct Macros {
+    /// Register and keys typed since `q`
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// Macros being replayed, the innermost one last
+    replaying: Vec<Replay>,
+    replayed_keys: usize,
+    /// Replayed again by `@@`
+    last_replayed: Option<char>,
+}
+
+#[derive(Debug)]
+struct Replay {
+    register: char,
+    keys: Vec<KeyEvent>,
+    pos: usize,
+    /// Times keys are replayed after the current one
+    repeat: usize,
+}
+
+impl Macros {
+    pub fn start_recording(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Returns register and recorded keys
+    pub const fn stop_recording(&mut self) -> Option<(char, Vec<KeyEvent>)> {
+        self.recording.take()
+    }
+
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some((_, keys)) = self.recording.as_mut() {
+            keys.push(key);
+        }
+    }
+
+    pub const fn last_replayed(&self) -> Option<char> {
+        self.last_replayed
+    }
+
+    /// Queues keys of macro in `register` before the rest of the one being replayed, fails
+    /// when the macro would call itself
+    ///
+    /// Every call of a macro already being replayed is refused, even one which would stop on
+    /// its own once a motion fails, so recursive macros known from vim don't work. Limit of
+    /// replayed keys is left to stop macros replayed with a huge count.
+    pub fn replay(&mut self, register: char, keys: Vec<KeyEvent>, count: usize) -> bool {
+        if self
+            .replaying
+            .iter()
+            .any(|replay| replay.register == register)
+        {
+            self.replaying.clear();
+            return false;
+        }
+
+        self.last_replayed = Some(register);
+
+        if !keys.is_empty() && count > 0 {
+            self.replaying.push(Replay {
+                register,
+                keys,
+                pos: 0,
+                repeat: count - 1,
+            });
+        }
+
+        true
+    }
+
+    /// Next key to be handled as if it was typed
+    pub fn next_key(&mut self) -> Option<KeyEvent> {
+        // finished replay is kept until its last key is handled, so that it can't call itself
+        while let Some(replay) = self.replaying.last_mut() {
+            if replay.pos == replay.keys.len() && replay.repeat > 0 {
+                replay.pos = 0;
+                replay.repeat -= 1;
+            }
+
+            if replay.pos < replay.keys.len() {
+                break;
+            }
+
+            self.replaying.pop();
+        }
+
+        let Some(replay) = self.replaying.last_mut() else {
+            self.replayed_keys = 0;
+            return None;
+        };
+
+        self.replayed_keys += 1;
+        if self.replayed_keys > MAX_REPLAYED_KEYS {
+            log::error!("Macro replay stopped after {MAX_REPLAYED_KEYS} keys");
+            self.replaying.clear();
+            self.replayed_keys = 0;
+            return None;
+        }
+
+        replay.pos += 1;
+        Some(replay.keys[replay.pos - 1])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use super::*;
+
+    #[test]
+    fn runaway_replay() {
+        let key = KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE);
+        let mut macros = Macros::default();
+
+        assert!(macros.replay('a', vec![key, key], usize::MAX));
+        assert_eq!(
+            std::iter::from_fn(|| macros.next_key()).count(),
+            MAX_REPLAYED_KEYS
+        );
+        assert_eq!(macros.next_key(), None);
+    }
+}
@@ -1,24 +1,44 @@
 mod buffer;
+mod clipboard;
 mod command;
+mod journal;
 mod keymap;
+mod macros;
 mod mode;
+mod register;
 pub mod utils;
+mod watch;
 
-use std::collections::{BTreeMap, HashMap};
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub use buffer::{Buffer, BufferId};
-use kaka_core::document::{Document, DocumentId};
+pub use clipboard::Clipboard;
+pub use journal::Recovery;
+use kaka_core::document::{self, Document, DocumentId};
+use kaka_core::indent::Indent;
+use kaka_core::languages::Languages;
+use kaka_core::movement::FindChar;
 use kaka_core::ropey::Rope;
+use kaka_core::search::Search;
 use kaka_core::shapes::{Point, Rect};
 pub use keymap::{Keymap, KeymapTreeElement};
+pub use macros::Macros;
 pub use mode::ModeKind;
+pub use register::Registers;
+pub use watch::Watcher;
 
 use crate::client::composer::Cursor;
 use crate::client::Redraw;
 use crate::current;
 
-pub use self::command::{insert_mode_on_key, Command, CommandData, CommandRegistry};
+pub use self::command::{
+    apply_pending_operator, insert_mode_on_key, recovery_prompt, reload_prompt,
+    replace_mode_on_key, resume_insert_mode, switch_to_normal_mode, try_substitute, Args, Command,
+    CommandData, CommandRegistry, Inserted, Invocation, LastChange, VisualSize,
+};
 pub use self::keymap::Keymaps;
 
 /// Holds editor state
@@ -29,6 +49,25 @@ pub struct Editor {
     pub exit_code: Option<i32>,
     pub keymaps: Keymaps,
     pub command_registry: CommandRegistry,
+    pub registers: Registers,
+    /// Last in-line character search, repeated by `;` and `,`
+    pub last_find: Option<FindChar>,
+    /// Last search pattern, repeated by `n` and `N`, its matches are highlighted
+    pub last_search: Option<Search>,
+    /// Repeated by `.`
+    pub last_change: Option<LastChange>,
+    pub macros: Macros,
+    /// Indentation of documents follows their language
+    pub languages: Languages,
+    /// Where undo history is kept between sessions, `None` keeps it only in memory
+    pub undo_dir: Option<PathBuf>,
+    /// Where journals of unsaved changes are written, `None` disables them
+    pub recovery_dir: Option<PathBuf>,
+    /// Notices files changed outside of the editor, `None` leaves it to periodic checks
+    pub watcher: Option<Watcher>,
+    journals: HashMap<DocumentId, journal::Journaled>,
+    recoveries: VecDeque<Recovery>,
+    reload_conflicts: VecDeque<DocumentId>,
     logger: BufferId,
 }
 
@@ -38,8 +77,19 @@ impl Editor {
         let registry = CommandRegistry::populate();
 
         keymaps.register_keymap_for_mode(&ModeKind::Insert, Keymap::insert_mode(&registry));
+        keymaps.register_keymap_for_mode(&ModeKind::Replace, Keymap::insert_mode(&registry));
         keymaps.register_keymap_for_mode(&ModeKind::Normal, Keymap::normal_mode(&registry));
-        keymaps.register_keymap_for_mode(&ModeKind::Visual, Keymap::visual_mode(&registry));
+        for mode in [
+            ModeKind::Visual,
+            ModeKind::VisualLine,
+            ModeKind::VisualBlock,
+        ] {
+            keymaps.register_keymap_for_mode(&mode, Keymap::visual_mode(&registry));
+        }
+        keymaps.register_keymap_for_mode(
+            &ModeKind::OperatorPending,
+            Keymap::operator_pending_mode(&registry),
+        );
 
         Self {
             buffers: BTreeMap::new(),
@@ -48,19 +98,80 @@ impl Editor {
             logger: BufferId::MAX,
             exit_code: None,
             command_registry: registry,
+            registers: Registers::default(),
+            last_find: None,
+            last_search: None,
+            last_change: None,
+            macros: Macros::default(),
+            languages: Languages::builtin(),
+            undo_dir: None,
+            recovery_dir: None,
+            watcher: None,
+            journals: HashMap::new(),
+            recoveries: VecDeque::new(),
+            reload_conflicts: VecDeque::new(),
             keymaps,
         }
     }
 
+    /// Opens file in a new buffer, unless it's already open in one
     pub fn open(&mut self, path: impl AsRef<Path>, set_current: bool) -> anyhow::Result<()> {
-        let document = Document::from_path(path)?;
+        if let Some(buffer_id) = self.buffer_of_path(path.as_ref()) {
+            if set_current {
+                self.current = buffer_id;
+            }
+
+            return Ok(());
+        }
+
+        let mut document = Document::from_path(&path)?;
+        document.set_indent(self.indent_for(path.as_ref()));
+        self.read_undo_file(&mut document);
+
         let buffer = Buffer::new_text(0, &document)?;
+        let document_id = document.id();
 
         self.add_buffer_and_document(buffer, document, set_current);
+        self.open_journal(document_id);
 
         Ok(())
     }
 
+    /// Buffer showing file at `path`, paths are compared after resolving them
+    pub fn buffer_of_path(&self, path: &Path) -> Option<BufferId> {
+        self.buffers
+            .values()
+            .find(|buf| self.documents[&buf.document_id()].has_path(path))
+            .map(Buffer::id)
+    }
+
+    fn read_undo_file(&self, document: &mut Document) {
+        let Some(dir) = self.undo_dir.as_deref() else {
+            return;
+        };
+
+        match document.read_undo_file(dir) {
+            Ok(()) => log::debug!("Restored undo history of {}", document.display_name()),
+            Err(document::Error::Io(err)) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => log::warn!("Undo history of {}: {err}", document.display_name()),
+        }
+    }
+
+    /// Keeps history of just saved document for the next session
+    pub fn write_undo_file(&self, document: &Document) {
+        let Some(dir) = self.undo_dir.as_deref() else {
+            return;
+        };
+
+        let written = document::create_private_dir(dir)
+            .map_err(document::Error::from)
+            .and_then(|()| document.write_undo_file(dir));
+
+        if let Err(err) = written {
+            log::error!("Undo history of {}: {err}", document.display_name());
+        }
+    }
+
     pub fn open_scratch(&mut self, set_current: bool) {
         let document = Document::new_scratch();
         let buffer = Buffer::new_text(0, &document).expect("Should not fail");
@@ -83,6 +194,19 @@ impl Editor {
         }
     }
 
+    /// Indentation rules of the current document, picked when it was opened
+    pub fn indent(&self) -> Arc<Indent> {
+        current!(self).1.indent()
+    }
+
+    /// Indentation rules of the language of file at `path`, the default ones when it's not known
+    pub fn indent_for(&self, path: &Path) -> Indent {
+        self.languages
+            .for_path(path)
+            .map(|language| language.indent.clone())
+            .unwrap_or_default()
+    }
+
     pub const fn should_exit(&self) -> bool {
         self.exit_code.is_some()
     }
@@ -106,7 +230,7 @@ impl Editor {
         Cursor(point, kind)
     }
 
-    pub fn set_logger(&mut self, id: BufferId) {
+    pub const fn set_logger(&mut self, id: BufferId) {
         self.logger = id;
     }
 
@@ -122,3 +246,32 @@ impl Editor {
         Redraw(self.current == self.logger)
     }
 }
+
+/// `$XDG_CONFIG_HOME/kaka/languages.yaml`, falling back to `~/.config/kaka/languages.yaml`
+pub fn user_languages_file() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(config.join("kaka/languages.yaml"))
+}
+
+/// `$XDG_STATE_HOME/kaka/undo`, falling back to `~/.local/state/kaka/undo`
+pub fn default_undo_dir() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("undo"))
+}
+
+/// `$XDG_STATE_HOME/kaka/recovery`, falling back to `~/.local/state/kaka/recovery`
+pub fn default_recovery_dir() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("recovery"))
+}
+
+fn state_dir() -> Option<PathBuf> {
+    let state = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+
+    Some(state.join("kaka"))
+}
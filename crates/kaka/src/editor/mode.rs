@@ -1,14 +1,21 @@
 use std::fmt::{Debug, Display};
 
-use kaka_core::selection::Selection;
-
 use crate::client::style::CursorKind;
 
+use super::command::PendingOperator;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModeKind {
     Normal,
     Insert,
+    /// Typed text overwrites the existing one
+    Replace,
     Visual,
+    /// Visual mode selecting whole lines
+    VisualLine,
+    /// Visual mode selecting rectangle between cursor and anchor
+    VisualBlock,
+    OperatorPending,
 }
 
 impl ModeKind {
@@ -19,17 +26,31 @@ impl ModeKind {
     pub const fn name(&self) -> &str {
         match self {
             Self::Insert => "insert",
+            Self::Replace => "replace",
             Self::Normal => "normal",
             Self::Visual => "visual",
+            Self::VisualLine => "visual_line",
+            Self::VisualBlock => "visual_block",
+            Self::OperatorPending => "operator_pending",
         }
     }
 
     pub const fn cursor_kind(&self) -> CursorKind {
         match self {
             Self::Insert => CursorKind::Line,
+            Self::Replace | Self::OperatorPending => CursorKind::Underscore,
             _ => CursorKind::Block,
         }
     }
+
+    pub const fn is_visual(&self) -> bool {
+        matches!(self, Self::Visual | Self::VisualLine | Self::VisualBlock)
+    }
+
+    /// Cursor movement extends selections instead of moving them
+    pub const fn is_selecting(&self) -> bool {
+        self.is_visual() || matches!(self, Self::OperatorPending)
+    }
 }
 
 impl Display for ModeKind {
@@ -38,19 +59,31 @@ impl Display for ModeKind {
     }
 }
 
+/// Mode specific state, selections are kept by `Buffer` itself
 #[derive(Debug, PartialEq, Eq)]
 pub enum ModeData {
     Normal,
     Insert,
-    Visual(Selection),
+    /// Text overwritten by every cursor, restored by backspace, empty where text was added at
+    /// the end of line
+    Replace(Vec<Vec<String>>),
+    Visual,
+    VisualLine,
+    VisualBlock,
+    OperatorPending(PendingOperator),
 }
 
 impl ModeData {
-    pub const fn new(kind: ModeKind, pos: usize) -> Self {
+    /// Returns `None` for modes which can't be entered without additional data
+    pub const fn new(kind: ModeKind) -> Option<Self> {
         match kind {
-            ModeKind::Normal => Self::Normal,
-            ModeKind::Insert => Self::Insert,
-            ModeKind::Visual => Self::Visual(Selection::at_pos(pos)),
+            ModeKind::Normal => Some(Self::Normal),
+            ModeKind::Insert => Some(Self::Insert),
+            ModeKind::Replace => Some(Self::Replace(Vec::new())),
+            ModeKind::Visual => Some(Self::Visual),
+            ModeKind::VisualLine => Some(Self::VisualLine),
+            ModeKind::VisualBlock => Some(Self::VisualBlock),
+            ModeKind::OperatorPending => None,
         }
     }
 
@@ -58,13 +91,11 @@ impl ModeData {
         match self {
             Self::Normal => ModeKind::Normal,
             Self::Insert => ModeKind::Insert,
-            Self::Visual(_) => ModeKind::Visual,
-        }
-    }
-
-    pub fn update(&mut self, pos: usize) {
-        if let Self::Visual(selection) = self {
-            selection.update_head(pos);
+            Self::Replace(_) => ModeKind::Replace,
+            Self::Visual => ModeKind::Visual,
+            Self::VisualLine => ModeKind::VisualLine,
+            Self::VisualBlock => ModeKind::VisualBlock,
+            Self::OperatorPending(_) => ModeKind::OperatorPending,
         }
     }
 }
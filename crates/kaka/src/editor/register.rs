@@ -0,0 +1,218 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::clipboard::{Clipboard, ClipboardKind};
+
+/// Register used when none is selected with `"`
+pub const UNNAMED: char = '"';
+
+/// Number of deletions kept in `"1` to `"9`
+const DELETED: usize = 9;
+
+/// Text stored in register, one value per cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterValue {
+    pub values: Vec<String>,
+    pub linewise: bool,
+}
+
+impl RegisterValue {
+    pub fn new(values: impl IntoIterator<Item = String>, linewise: bool) -> Self {
+        Self {
+            values: values.into_iter().collect(),
+            linewise,
+        }
+    }
+
+    /// Text coming from outside of the editor, full lines are put as lines
+    pub fn from_text(text: String) -> Self {
+        let linewise = text.ends_with('\n');
+        Self::new([text], linewise)
+    }
+
+    /// Values of all cursors as single text, lines are already separated
+    pub fn joined(&self) -> String {
+        if self.linewise {
+            self.values.concat()
+        } else {
+            self.values.join("\n")
+        }
+    }
+
+    /// `"A` to `"Z` append to the register, each cursor to its own value when the numbers
+    /// of cursors match
+    fn append(&mut self, other: Self) {
+        if self.values.len() != other.values.len() {
+            *self = Self::new([self.joined()], self.linewise);
+            return self.append(Self::new([other.joined()], other.linewise));
+        }
+
+        for (value, appended) in self.values.iter_mut().zip(other.values) {
+            if other.linewise && !self.linewise && !value.is_empty() {
+                value.push('\n');
+            }
+            value.push_str(&appended);
+        }
+
+        self.linewise |= other.linewise;
+    }
+}
+
+/// Named registers like in vim, `"_` discards text and `"+` with `"*` are the system clipboard
+#[derive(Debug, Default)]
+pub struct Registers {
+    unnamed: Option<RegisterValue>,
+    /// `"0`, the last yank
+    yanked: Option<RegisterValue>,
+    /// `"1` to `"9`, deletions of lines with the most recent first
+    deleted: VecDeque<RegisterValue>,
+    /// `"-`, the last deletion within a line
+    small_delete: Option<RegisterValue>,
+    /// `"a` to `"z`
+    named: HashMap<char, RegisterValue>,
+    clipboard: Clipboard,
+}
+
+impl Registers {
+    /// Register can be selected with `"`
+    pub const fn is_valid(name: char) -> bool {
+        matches!(
+            name,
+            UNNAMED | '0'..='9' | 'a'..='z' | 'A'..='Z' | '-' | '_' | '+' | '*'
+        )
+    }
+
+    /// Stores yanked text in register `name`, unnamed one gets it too
+    pub fn yank(&mut self, name: char, value: RegisterValue) {
+        if name == UNNAMED {
+            self.yanked = Some(value.clone());
+        }
+
+        self.store(name, value);
+    }
+
+    /// Stores deleted text in register `name`, deletions into the unnamed one go through
+    /// numbered registers
+    pub fn delete(&mut self, name: char, value: RegisterValue) {
+        if name == UNNAMED {
+            if value.linewise || value.values.iter().any(|value| value.contains('\n')) {
+                self.deleted.push_front(value.clone());
+                self.deleted.truncate(DELETED);
+            } else {
+                self.small_delete = Some(value.clone());
+            }
+        }
+
+        self.store(name, value);
+    }
+
+    /// Stores keys of recorded macro as text, unlike yank the unnamed register is kept
+    pub fn record(&mut self, name: char, keys: String) {
+        let unnamed = self.unnamed.take();
+        self.store(name, RegisterValue::from_text(keys));
+
+        if name != UNNAMED {
+            self.unnamed = unnamed;
+        }
+    }
+
+    fn store(&mut self, name: char, value: RegisterValue) {
+        match name {
+            '_' => return,
+            'a'..='z' => {
+                self.named.insert(name, value.clone());
+            }
+            'A'..='Z' => {
+                let name = name.to_ascii_lowercase();
+                let value = match self.named.remove(&name) {
+                    Some(mut stored) => {
+                        stored.append(value);
+                        stored
+                    }
+                    None => value,
+                };
+
+                self.named.insert(name, value.clone());
+                self.unnamed = Some(value);
+                return;
+            }
+            '+' => self.clipboard.set(ClipboardKind::Clipboard, value.clone()),
+            '*' => self.clipboard.set(ClipboardKind::Selection, value.clone()),
+            // numbered and `"-` are filled only by the editor
+            _ => (),
+        }
+
+        self.unnamed = Some(value);
+    }
+
+    pub fn get(&mut self, name: char) -> Option<RegisterValue> {
+        match name {
+            UNNAMED => self.unnamed.clone(),
+            '0' => self.yanked.clone(),
+            '1'..='9' => {
+                let idx = name as usize - '1' as usize;
+                self.deleted.get(idx).cloned()
+            }
+            '-' => self.small_delete.clone(),
+            'a'..='z' | 'A'..='Z' => self.named.get(&name.to_ascii_lowercase()).cloned(),
+            '+' => self.clipboard.get(ClipboardKind::Clipboard),
+            '*' => self.clipboard.get(ClipboardKind::Selection),
+            _ => None,
+        }
+    }
+
+    pub const fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
+    pub fn set_clipboard(&mut self, clipboard: Clipboard) {
+        self.clipboard = clipboard;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn value(text: &str) -> RegisterValue {
+        RegisterValue::from_text(text.to_string())
+    }
+
+    #[test]
+    fn deletions_shift_numbered_registers() {
+        let mut registers = Registers::default();
+
+        registers.delete(UNNAMED, value("1\n"));
+        registers.delete(UNNAMED, value("2\n"));
+        registers.delete(UNNAMED, value("x"));
+        registers.yank(UNNAMED, value("y"));
+
+        assert_eq!(registers.get('1'), Some(value("2\n")));
+        assert_eq!(registers.get('2'), Some(value("1\n")));
+        assert_eq!(registers.get('-'), Some(value("x")));
+        assert_eq!(registers.get('0'), Some(value("y")));
+        assert_eq!(registers.get(UNNAMED), Some(value("y")));
+
+        for i in 0..DELETED {
+            registers.delete(UNNAMED, value(&format!("{i}\n")));
+        }
+        assert_eq!(registers.get('9'), Some(value("0\n")));
+    }
+
+    #[test]
+    fn named_registers() {
+        let mut registers = Registers::default();
+
+        registers.yank('a', value("a"));
+        registers.yank('A', value("b"));
+        assert_eq!(registers.get('a'), Some(value("ab")));
+        assert_eq!(registers.get(UNNAMED), Some(value("ab")));
+        assert_eq!(registers.get('0'), None, "named yank is not the last yank");
+
+        registers.yank('A', value("line\n"));
+        assert_eq!(registers.get('A'), Some(value("ab\nline\n")));
+
+        registers.delete('_', value("gone\n"));
+        assert_eq!(registers.get(UNNAMED), Some(value("ab\nline\n")));
+        assert_eq!(registers.get('1'), None);
+    }
+}
@@ -15,7 +15,7 @@ fn to_tokens(chars: &str) -> Result<Vec<Token<'_>>> {
     let mut tokens = vec![];
     let mut diamond_start = None;
 
-    for (i, ch) in chars.chars().enumerate() {
+    for (i, ch) in chars.char_indices() {
         let err = || anyhow::bail!("Unexpected char {ch} on pos {i}");
 
         if ch.is_ascii_whitespace() {
@@ -60,45 +60,73 @@ impl<'a> Token<'a> {
                 Ok(KeyEvent::new(KeyCode::Char(c), modifier))
             }
             Token::Diamond(string) => {
-                let mut chars = string.chars();
-                let first_char = chars.next().context("no start")?;
-
-                if first_char.to_ascii_uppercase() == 'F' {
-                    let num = string[1..].parse()?;
-                    return Ok(KeyEvent::new(KeyCode::F(num), KeyModifiers::empty()));
-                }
-
                 if let Some(event) = to_known_special_keyevent(string) {
                     return Ok(event);
                 }
 
-                let modifier = match first_char.to_ascii_uppercase() {
-                    'C' => KeyModifiers::CONTROL,
-                    'M' => KeyModifiers::ALT,
-                    _ => bail!("Unknown modifier: {first_char}"),
-                };
+                let mut modifiers = KeyModifiers::empty();
+                let mut rest = string;
 
-                let next = chars.next();
-                ensure!(next == Some('-'), "Expected -, got {next:?}");
+                while let Some((modifier, tail)) = split_modifier(rest) {
+                    modifiers |= modifier;
+                    rest = tail;
+                }
 
-                let code = chars
-                    .next()
-                    .context("Unexpected end of input between diamond brackets")?;
+                let mut chars = rest.chars();
+                let code = match (chars.next(), chars.next()) {
+                    (None, _) => bail!("Unexpected end of input between diamond brackets"),
+                    (Some(c), None) => {
+                        ensure!(!modifiers.is_empty(), "Unknown key: {string}");
 
-                ensure!(is_char_valid(code));
-                ensure!(chars.next().is_none(), "trailing input");
+                        if c.is_uppercase() {
+                            modifiers |= KeyModifiers::SHIFT;
+                        }
 
-                Ok(KeyEvent::new(KeyCode::Char(code), modifier))
+                        KeyCode::Char(c)
+                    }
+                    (Some('F' | 'f'), Some(_)) if rest[1..].parse::<u8>().is_ok() => {
+                        KeyCode::F(rest[1..].parse()?)
+                    }
+                    _ => {
+                        to_known_special_keyevent(rest)
+                            .with_context(|| format!("Unknown key: {string}"))?
+                            .code
+                    }
+                };
+
+                // shift turns tab around
+                let code = match code {
+                    KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                    code => code,
+                };
+
+                Ok(KeyEvent::new(code, modifiers))
             }
         }
     }
 }
 
+/// Splits modifier like `C-` off the start of key written between diamond brackets
+fn split_modifier(string: &str) -> Option<(KeyModifiers, &str)> {
+    let prefix = string.get(..2)?;
+    let rest = &string[2..];
+
+    let modifier = match prefix.to_ascii_uppercase().as_str() {
+        "C-" => KeyModifiers::CONTROL,
+        "M-" => KeyModifiers::ALT,
+        "S-" => KeyModifiers::SHIFT,
+        _ => return None,
+    };
+
+    (!rest.is_empty()).then_some((modifier, rest))
+}
+
 fn to_known_special_keyevent(string: &str) -> Option<KeyEvent> {
     let mut modifiers = KeyModifiers::empty();
     let code = match &*string.to_uppercase() {
         "ESC" => KeyCode::Esc,
-        "BS" | "DEL" => KeyCode::Backspace,
+        "BS" => KeyCode::Backspace,
+        "DEL" => KeyCode::Delete,
         "CR" => KeyCode::Enter,
         "TAB" => KeyCode::Tab,
         "S-TAB" => {
@@ -109,6 +137,13 @@ fn to_known_special_keyevent(string: &str) -> Option<KeyEvent> {
         "DOWN" => KeyCode::Down,
         "UP" => KeyCode::Up,
         "RIGHT" => KeyCode::Right,
+        "HOME" => KeyCode::Home,
+        "END" => KeyCode::End,
+        "PAGEUP" => KeyCode::PageUp,
+        "PAGEDOWN" => KeyCode::PageDown,
+        "INSERT" => KeyCode::Insert,
+        "LT" => KeyCode::Char('<'),
+        "GT" => KeyCode::Char('>'),
         _ => return None,
     };
 
@@ -120,6 +155,89 @@ fn to_known_special_keyevent(string: &str) -> Option<KeyEvent> {
     })
 }
 
+/// Key written the way `parse_keys` reads it, `None` for keys without notation like
+/// `CapsLock` or with modifiers other than control, alt and shift
+pub fn format_key(key: KeyEvent) -> Option<String> {
+    let known = KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT;
+    if !known.contains(key.modifiers) {
+        return None;
+    }
+
+    let mut modifiers = key.modifiers;
+
+    // shift is part of typed character and of `<S-TAB>`
+    if matches!(key.code, KeyCode::Char(_) | KeyCode::BackTab) {
+        modifiers.remove(KeyModifiers::SHIFT);
+    }
+
+    let prefix = [
+        (KeyModifiers::CONTROL, "C-"),
+        (KeyModifiers::ALT, "M-"),
+        (KeyModifiers::SHIFT, "S-"),
+    ]
+    .into_iter()
+    .filter(|(modifier, _)| modifiers.contains(*modifier))
+    .map(|(_, prefix)| prefix)
+    .collect::<String>();
+
+    let name = match key.code {
+        KeyCode::Char('<') => "LT".into(),
+        KeyCode::Char('>') if !prefix.is_empty() => "GT".into(),
+        KeyCode::Char(c) if prefix.is_empty() => return Some(c.to_string()),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(num) => format!("F{num}"),
+        code => match code {
+            KeyCode::Esc => "ESC",
+            KeyCode::Backspace => "BS",
+            KeyCode::Enter => "CR",
+            KeyCode::Tab => "TAB",
+            KeyCode::BackTab => "S-TAB",
+            KeyCode::Left => "LEFT",
+            KeyCode::Down => "DOWN",
+            KeyCode::Up => "UP",
+            KeyCode::Right => "RIGHT",
+            KeyCode::Delete => "DEL",
+            KeyCode::Home => "HOME",
+            KeyCode::End => "END",
+            KeyCode::PageUp => "PAGEUP",
+            KeyCode::PageDown => "PAGEDOWN",
+            KeyCode::Insert => "INSERT",
+            _ => return None,
+        }
+        .to_string(),
+    };
+
+    Some(format!("<{prefix}{name}>"))
+}
+
+/// Reads keys written by `format_key`, unlike mappings the text may contain any character,
+/// line breaks are left out
+pub fn parse_keys(text: &str) -> Result<Vec<KeyEvent>> {
+    let mut keys = vec![];
+    let mut rest = text;
+
+    while let Some(ch) = rest.chars().next() {
+        let token = if ch == '<' {
+            let end = rest.find('>').context("Unclosed <")?;
+            let token = Token::Diamond(&rest[1..end]);
+            rest = &rest[end + 1..];
+            token
+        } else {
+            rest = &rest[ch.len_utf8()..];
+
+            if matches!(ch, '\n' | '\r') {
+                continue;
+            }
+
+            Token::Char(ch)
+        };
+
+        keys.push(token.try_into_key_event()?);
+    }
+
+    Ok(keys)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -148,4 +266,34 @@ mod test {
             &[Token::Diamond("S-a"), Token::Char('x'), Token::Char('d')],
         );
     }
+
+    #[test]
+    fn keys_round_trip() {
+        let text = "ia b<LT>é<ESC><C-r>:s/x/y<CR><S-TAB>Z<HOME><DEL>";
+        let keys = parse_keys(text).unwrap();
+        assert_eq!(keys.len(), 19);
+
+        let formatted = keys.into_iter().filter_map(format_key).collect::<String>();
+        assert_eq!(formatted, text);
+
+        let text = "<C-LT><M-GT><C-S-TAB><S-PAGEUP><PAGEDOWN><C-INSERT><M-ESC><C-F5><C-é>";
+        let keys = parse_keys(text).unwrap();
+        let formatted = keys.into_iter().filter_map(format_key).collect::<String>();
+        assert_eq!(formatted, text, "modifiers");
+
+        let key = KeyEvent::new(
+            KeyCode::Char('A'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        assert_eq!(format_key(key).as_deref(), Some("<C-A>"));
+        assert_eq!(parse_keys("<C-A>").unwrap(), vec![key]);
+
+        let key = KeyEvent::new(KeyCode::CapsLock, KeyModifiers::NONE);
+        assert_eq!(format_key(key), None);
+
+        assert_eq!(parse_keys("dd\n").unwrap(), parse_mapping("dd").unwrap());
+        assert!(parse_keys("i<ESC").is_err());
+        assert!(parse_keys("<a>").is_err());
+        assert!(parse_keys("<C->").is_err());
+    }
 }
@@ -0,0 +1,130 @@
+//! Documents changed outside of the editor, the unmodified ones are reloaded and the modified
+//! ones wait for user to decide about them
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use kaka_core::{
+    document::DocumentId,
+    selection::{Selection, SelectionSet},
+    transaction::Assoc,
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use super::Editor;
+
+/// Watches directories of open documents, watch of the file itself would be lost once it's
+/// replaced by rename
+pub struct Watcher {
+    inner: RecommendedWatcher,
+    dirs: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Events are passed to `handler` from another thread
+    pub fn new(handler: impl notify::EventHandler) -> notify::Result<Self> {
+        Ok(Self {
+            inner: notify::recommended_watcher(handler)?,
+            dirs: HashSet::new(),
+        })
+    }
+
+    fn watch_parent(&mut self, path: &Path) {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let Ok(dir) = dir.canonicalize() else {
+            return;
+        };
+
+        if self.dirs.contains(&dir) {
+            return;
+        }
+
+        if let Err(err) = self.inner.watch(&dir, RecursiveMode::NonRecursive) {
+            log::warn!("Can't watch {}: {err}", dir.display());
+        }
+
+        // failed ones are not retried, files are still checked periodically
+        self.dirs.insert(dir);
+    }
+}
+
+impl Editor {
+    /// Reloads documents changed on disk, modified ones are queued to be asked about, returns
+    /// whether any document was reloaded
+    pub fn check_files(&mut self) -> bool {
+        if let Some(watcher) = self.watcher.as_mut() {
+            for path in self.documents.values().filter_map(|doc| doc.path()) {
+                watcher.watch_parent(path);
+            }
+        }
+
+        let mut reloaded = false;
+        let ids = self.documents.keys().copied().collect::<Vec<_>>();
+
+        for id in ids {
+            let document = self.documents.get_mut(&id).expect("Document exists");
+
+            // text of insertion in progress is based on the current one, checked once it ends
+            if document.transaction_active() || self.reload_conflicts.contains(&id) {
+                continue;
+            }
+
+            match document.changed_on_disk() {
+                Ok(true) if document.is_modified() => self.reload_conflicts.push_back(id),
+                Ok(true) => {
+                    self.reload_document(id);
+                    reloaded = true;
+                }
+                Ok(false) => (),
+                Err(err) => log::warn!("Can't check {}: {err}", document.display_name()),
+            }
+        }
+
+        reloaded
+    }
+
+    /// Modified document changed on disk as well
+    pub fn take_reload_conflict(&mut self) -> Option<DocumentId> {
+        self.reload_conflicts.pop_front()
+    }
+
+    /// Replaces text of document with its file, cursors of buffers showing it follow the
+    /// changes
+    pub fn reload_document(&mut self, id: DocumentId) {
+        let document = self.documents.get_mut(&id).expect("Document exists");
+
+        if document.transaction_active() {
+            log::error!("Can't reload {} while inserting", document.display_name());
+            return;
+        }
+
+        // selections restored by undo, current buffer's are the ones user looks at
+        let selections = self
+            .buffers
+            .get(&self.current)
+            .filter(|buf| buf.document_id() == id)
+            .or_else(|| self.buffers.values().find(|buf| buf.document_id() == id))
+            .map_or_else(
+                || SelectionSet::single(Selection::at_pos(0)),
+                |buf| buf.selections().clone(),
+            );
+
+        match document.reload(&selections) {
+            Ok(tx) => {
+                for buf in self.buffers.values_mut() {
+                    if buf.document_id() == id {
+                        buf.map_cursors(document, &tx, Assoc::Before);
+                    }
+                }
+
+                log::info!("Reloaded {} changed on disk", document.display_name());
+            }
+            Err(err) => log::error!("Can't reload {}: {err}", document.display_name()),
+        }
+    }
+}
@@ -1,10 +1,10 @@
-use std::mem::MaybeUninit;
+use std::sync::OnceLock;
 
 use kaka_core::ropey::Rope;
 use log::{set_logger, Log};
 use tokio::sync::mpsc::UnboundedSender;
 
-static mut LOGGER: MaybeUninit<BufferLogger> = MaybeUninit::uninit();
+static LOGGER: OnceLock<BufferLogger> = OnceLock::new();
 
 pub struct BufferLogger {
     tx: UnboundedSender<Rope>,
@@ -24,7 +24,7 @@ impl Log for BufferLogger {
 }
 
 pub fn enable(tx: UnboundedSender<Rope>) {
-    let logger = unsafe { LOGGER.write(BufferLogger { tx }) };
+    let logger = LOGGER.get_or_init(|| BufferLogger { tx });
 
     set_logger(logger)
         .map(|_| log::set_max_level(log::LevelFilter::Trace))